@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS public.schedule_scheduled;
+DROP INDEX IF EXISTS public.schedule_puzzle_id;
+
+ALTER TABLE public.schedule DROP COLUMN puzzle_id;
@@ -0,0 +1,6 @@
+-- Link each schedule to the draft puzzle it publishes
+ALTER TABLE public.schedule
+    ADD COLUMN puzzle_id INTEGER NULL REFERENCES public.puzzle(id) ON DELETE CASCADE;
+
+CREATE INDEX schedule_puzzle_id ON public.schedule USING btree (puzzle_id);
+CREATE INDEX schedule_scheduled ON public.schedule USING btree (scheduled);
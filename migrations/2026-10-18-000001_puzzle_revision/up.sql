@@ -0,0 +1,12 @@
+-- Snapshots of puzzle content, solution and memo after each edit
+CREATE TABLE puzzle_revision (
+    id          SERIAL PRIMARY KEY,
+    puzzle_id   INTEGER NOT NULL REFERENCES puzzle(id) ON DELETE CASCADE,
+    user_id     INTEGER NULL REFERENCES "user"(id) ON DELETE SET NULL,
+    content     TEXT NOT NULL,
+    solution    TEXT NOT NULL,
+    memo        TEXT NOT NULL,
+    created     TIMESTAMP WITH TIME ZONE DEFAULT now() NOT NULL
+);
+
+CREATE INDEX puzzle_revision_puzzle_id ON puzzle_revision USING btree (puzzle_id);
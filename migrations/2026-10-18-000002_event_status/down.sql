@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+-- Statuses of the legacy server are not restored.
+ALTER TABLE public.event DROP CONSTRAINT event_status_check;
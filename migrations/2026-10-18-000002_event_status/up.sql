@@ -0,0 +1,8 @@
+-- Map the statuses left by the legacy server onto `EventStatus`. Events already
+-- ended had their awards handled there, so they are closed without granting
+-- awards again, and only the others are closed by the server when they end.
+UPDATE public.event
+    SET status = CASE WHEN end_time > now() THEN 0 ELSE 1 END;
+
+ALTER TABLE public.event
+    ADD CONSTRAINT event_status_check CHECK (status IN (0, 1));
@@ -0,0 +1,12 @@
+-- Suspensions of users by staff, lifted once `expires` has passed
+CREATE TABLE user_suspension (
+    id          SERIAL PRIMARY KEY,
+    user_id     INTEGER NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+    actor_id    INTEGER NULL REFERENCES "user"(id) ON DELETE SET NULL,
+    reason      TEXT NOT NULL,
+    scope       INTEGER NOT NULL DEFAULT 2,
+    created     TIMESTAMP WITH TIME ZONE DEFAULT now() NOT NULL,
+    expires     TIMESTAMP WITH TIME ZONE NULL
+);
+
+CREATE INDEX user_suspension_user_id ON user_suspension USING btree (user_id);
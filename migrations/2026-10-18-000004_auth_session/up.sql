@@ -0,0 +1,15 @@
+CREATE EXTENSION IF NOT EXISTS "uuid-ossp";
+
+-- Login sessions holding the current refresh token of a user
+CREATE TABLE auth_session (
+    id              UUID DEFAULT uuid_generate_v4(),
+    user_id         INTEGER NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+    refresh_token   TEXT NOT NULL,
+    created         TIMESTAMP WITH TIME ZONE DEFAULT now() NOT NULL,
+    refreshed       TIMESTAMP WITH TIME ZONE DEFAULT now() NOT NULL,
+    expires         TIMESTAMP WITH TIME ZONE NOT NULL,
+    revoked         TIMESTAMP WITH TIME ZONE NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX auth_session_user_id ON auth_session USING btree (user_id);
@@ -0,0 +1,11 @@
+-- Single-use tokens mailed to users to reset their passwords
+CREATE TABLE password_reset_token (
+    id          SERIAL PRIMARY KEY,
+    user_id     INTEGER NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+    token       TEXT NOT NULL UNIQUE,
+    created     TIMESTAMP WITH TIME ZONE DEFAULT now() NOT NULL,
+    expires     TIMESTAMP WITH TIME ZONE NOT NULL,
+    used        TIMESTAMP WITH TIME ZONE NULL
+);
+
+CREATE INDEX password_reset_token_user_id ON password_reset_token USING btree (user_id);
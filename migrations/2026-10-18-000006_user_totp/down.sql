@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE user_totp_backup_code;
+DROP TABLE user_totp;
@@ -0,0 +1,18 @@
+-- TOTP secrets of users enrolled in two-factor authentication
+CREATE TABLE user_totp (
+    user_id     INTEGER PRIMARY KEY REFERENCES "user"(id) ON DELETE CASCADE,
+    secret      TEXT NOT NULL,
+    created     TIMESTAMP WITH TIME ZONE DEFAULT now() NOT NULL,
+    confirmed   TIMESTAMP WITH TIME ZONE NULL,
+    last_step   BIGINT NOT NULL DEFAULT 0
+);
+
+-- Single-use codes to pass two-factor authentication without the device
+CREATE TABLE user_totp_backup_code (
+    id          SERIAL PRIMARY KEY,
+    user_id     INTEGER NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+    code        TEXT NOT NULL,
+    used        TIMESTAMP WITH TIME ZONE NULL
+);
+
+CREATE INDEX user_totp_backup_code_user_id ON user_totp_backup_code USING btree (user_id);
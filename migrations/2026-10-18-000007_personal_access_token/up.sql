@@ -0,0 +1,14 @@
+-- Named, scoped API tokens for bots and scripts
+CREATE TABLE personal_access_token (
+    id          SERIAL PRIMARY KEY,
+    user_id     INTEGER NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+    name        TEXT NOT NULL,
+    token       TEXT NOT NULL UNIQUE,
+    scopes      INTEGER[] NOT NULL DEFAULT '{}',
+    created     TIMESTAMP WITH TIME ZONE DEFAULT now() NOT NULL,
+    last_used   TIMESTAMP WITH TIME ZONE NULL,
+    expires     TIMESTAMP WITH TIME ZONE NULL,
+    revoked     TIMESTAMP WITH TIME ZONE NULL
+);
+
+CREATE INDEX personal_access_token_user_id ON personal_access_token USING btree (user_id);
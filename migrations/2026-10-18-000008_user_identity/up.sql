@@ -0,0 +1,13 @@
+-- External identities (e.g. OpenID Connect providers) linked to users
+CREATE TABLE user_identity (
+    id          SERIAL PRIMARY KEY,
+    user_id     INTEGER NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+    provider    TEXT NOT NULL,
+    subject     TEXT NOT NULL,
+    email       TEXT NOT NULL DEFAULT '',
+    created     TIMESTAMP WITH TIME ZONE DEFAULT now() NOT NULL,
+    last_login  TIMESTAMP WITH TIME ZONE NULL,
+    UNIQUE (provider, subject)
+);
+
+CREATE INDEX user_identity_user_id ON user_identity USING btree (user_id);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE email_verification_token;
+ALTER TABLE "user" DROP COLUMN email_verified;
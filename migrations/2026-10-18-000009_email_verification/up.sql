@@ -0,0 +1,15 @@
+-- When the current email of the user is verified, cleared on change
+ALTER TABLE "user" ADD COLUMN email_verified TIMESTAMP WITH TIME ZONE NULL;
+
+-- Single-use tokens mailed to users to verify their emails
+CREATE TABLE email_verification_token (
+    id          SERIAL PRIMARY KEY,
+    user_id     INTEGER NOT NULL REFERENCES "user"(id) ON DELETE CASCADE,
+    email       VARCHAR(254) NOT NULL,
+    token       TEXT NOT NULL UNIQUE,
+    created     TIMESTAMP WITH TIME ZONE DEFAULT now() NOT NULL,
+    expires     TIMESTAMP WITH TIME ZONE NOT NULL,
+    used        TIMESTAMP WITH TIME ZONE NULL
+);
+
+CREATE INDEX email_verification_token_user_id ON email_verification_token USING btree (user_id);
@@ -0,0 +1,11 @@
+-- Codes generated by staff to sign up with, when signup is invite-only
+CREATE TABLE invite_code (
+    id          SERIAL PRIMARY KEY,
+    code        TEXT NOT NULL UNIQUE,
+    created_by  INTEGER NULL REFERENCES "user"(id) ON DELETE SET NULL,
+    max_uses    INTEGER NOT NULL DEFAULT 1 CHECK (max_uses > 0),
+    uses        INTEGER NOT NULL DEFAULT 0,
+    created     TIMESTAMP WITH TIME ZONE DEFAULT now() NOT NULL,
+    expires     TIMESTAMP WITH TIME ZONE NULL,
+    revoked     TIMESTAMP WITH TIME ZONE NULL
+);
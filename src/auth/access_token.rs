@@ -0,0 +1,95 @@
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+
+use crate::models::{PersonalAccessToken, Timestamptz, TokenScope, User, ID};
+use crate::schema::{personal_access_token, user};
+
+use super::session::{digest_secret, gen_secret};
+
+/// Prefix telling personal access tokens apart from JWTs in the Authorization header
+const TOKEN_PREFIX: &str = "cindy_pat_";
+
+/// Whether the bearer token is a personal access token rather than a JWT.
+pub fn is_access_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+/// Create a token for the user, returning it along with the token to hand out once.
+pub fn create_access_token(
+    conn: &mut PgConnection,
+    user_id: ID,
+    name: &str,
+    scopes: &[TokenScope],
+    expires: Option<Timestamptz>,
+) -> Result<(PersonalAccessToken, String)> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(anyhow!("Name of the token cannot be blank"));
+    }
+    if let Some(expires) = expires {
+        if expires <= Utc::now() {
+            return Err(anyhow!("Expiry of the token should be in future"));
+        }
+    }
+    let mut scopes = scopes.to_vec();
+    scopes.sort_by_key(|scope| *scope as i32);
+    scopes.dedup();
+
+    let token = format!("{}{}", TOKEN_PREFIX, gen_secret());
+    let inst: PersonalAccessToken = diesel::insert_into(personal_access_token::table)
+        .values((
+            personal_access_token::user_id.eq(user_id),
+            personal_access_token::name.eq(name),
+            personal_access_token::token.eq(digest_secret(&token)),
+            personal_access_token::scopes.eq(&scopes),
+            personal_access_token::expires.eq(expires),
+        ))
+        .get_result(conn)?;
+
+    Ok((inst, token))
+}
+
+/// Look up the active user and scopes of a token, marking it as used.
+pub fn authenticate_access_token(
+    conn: &mut PgConnection,
+    token: &str,
+) -> Result<(User, Vec<TokenScope>)> {
+    let now = Utc::now();
+    let (inst, usr): (PersonalAccessToken, User) = personal_access_token::table
+        .inner_join(user::table)
+        .filter(personal_access_token::token.eq(digest_secret(token)))
+        .filter(personal_access_token::revoked.is_null())
+        .filter(
+            personal_access_token::expires
+                .is_null()
+                .or(personal_access_token::expires.gt(now).assume_not_null()),
+        )
+        .filter(user::is_active.eq(true))
+        .first(conn)
+        .optional()?
+        .ok_or(anyhow!("Invalid or expired access token"))?;
+
+    // Only touch the row once a minute for busy bots
+    diesel::update(&inst)
+        .filter(
+            personal_access_token::last_used
+                .is_null()
+                .or(personal_access_token::last_used
+                    .lt(now - Duration::minutes(1))
+                    .assume_not_null()),
+        )
+        .set(personal_access_token::last_used.eq(Some(now)))
+        .execute(conn)?;
+
+    Ok((usr, inst.scopes))
+}
+
+/// Revoke all tokens of the user, returning the number revoked.
+pub fn revoke_user_access_tokens(conn: &mut PgConnection, user_id: ID) -> QueryResult<usize> {
+    diesel::update(personal_access_token::table)
+        .filter(personal_access_token::user_id.eq(user_id))
+        .filter(personal_access_token::revoked.is_null())
+        .set(personal_access_token::revoked.eq(Some(Utc::now())))
+        .execute(conn)
+}
@@ -0,0 +1,222 @@
+use anyhow::{anyhow, Result};
+use chrono::{Duration, TimeZone, Utc};
+use ring::rand::SystemRandom;
+use ring::{digest, hmac};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::http_client;
+use crate::models::Timestamptz;
+
+use super::session::gen_secret;
+
+/// Anti-spam challenge to solve before signing up.
+///
+/// Set with `SIGNUP_CHALLENGE` env var, or plugged in with
+/// `GlobalCtx::with_signup_challenge`. Clients get the parameters from
+/// `/signup/challenge`, and send the solution as `challenge_response` to `/signup`.
+pub trait SignupChallenge: Send + Sync {
+    /// Name for clients to pick the widget or solver
+    fn kind(&self) -> &'static str;
+
+    /// Parameters of a new challenge for the client.
+    fn issue(&self) -> Result<Value>;
+
+    /// Check the response of the client, which may block the thread.
+    fn verify(&self, response: &str, ip_addr: Option<&str>) -> Result<()>;
+}
+
+/// Accept every signup.
+#[derive(Default)]
+pub struct NoChallenge;
+
+impl SignupChallenge for NoChallenge {
+    fn kind(&self) -> &'static str {
+        "none"
+    }
+
+    fn issue(&self) -> Result<Value> {
+        Ok(json!({}))
+    }
+
+    fn verify(&self, _response: &str, _ip_addr: Option<&str>) -> Result<()> {
+        Ok(())
+    }
+}
+
+lazy_static! {
+    /// Key signing proof-of-work challenges, so that nothing is kept until solved
+    static ref POW_KEY: hmac::Key =
+        hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new()).expect("Error generating key");
+    /// Proof-of-work challenges solved already, by their expiry
+    static ref POW_SOLVED: Mutex<HashMap<String, Timestamptz>> = Default::default();
+}
+
+fn pow_challenge_max_age() -> Duration {
+    Duration::minutes(10)
+}
+
+/// Drop solved proof-of-work challenges past their expiry, which are refused anyway.
+pub fn cleanup() {
+    let mut map = POW_SOLVED.lock().unwrap();
+    let now = Utc::now();
+    map.retain(|_, expires| *expires > now);
+}
+
+/// Ask for a nonce such that SHA-256 of `<challenge>:<nonce>` starts with
+/// `difficulty` zero bits, costing bots about `2^difficulty` hashes per signup.
+pub struct ProofOfWork {
+    difficulty: u32,
+}
+
+impl ProofOfWork {
+    pub fn new(difficulty: u32) -> Self {
+        Self { difficulty }
+    }
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+impl SignupChallenge for ProofOfWork {
+    fn kind(&self) -> &'static str {
+        "proof_of_work"
+    }
+
+    /// Issue `<secret>.<expiry>.<signature>`, checked by its signature on `verify`.
+    fn issue(&self) -> Result<Value> {
+        let payload = format!(
+            "{}.{}",
+            gen_secret(),
+            (Utc::now() + pow_challenge_max_age()).timestamp()
+        );
+        let signature = hmac::sign(&POW_KEY, payload.as_bytes());
+        let challenge = format!(
+            "{}.{}",
+            payload,
+            base64::encode_config(signature.as_ref(), base64::URL_SAFE_NO_PAD)
+        );
+        Ok(json!({
+            "challenge": challenge,
+            "difficulty": self.difficulty,
+        }))
+    }
+
+    /// Check the response `<challenge>:<nonce>`, each challenge accepted only once.
+    fn verify(&self, response: &str, _ip_addr: Option<&str>) -> Result<()> {
+        let (challenge, _) = response
+            .split_once(':')
+            .ok_or(anyhow!("Challenge response is required"))?;
+        let expires = challenge
+            .rsplit_once('.')
+            .and_then(|(payload, signature)| {
+                let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD).ok()?;
+                hmac::verify(&POW_KEY, payload.as_bytes(), &signature).ok()?;
+                let (_, expires) = payload.split_once('.')?;
+                Utc.timestamp_opt(expires.parse().ok()?, 0).single()
+            })
+            .ok_or(anyhow!("Invalid challenge"))?;
+        if expires <= Utc::now() {
+            return Err(anyhow!("Challenge expired. Please try again."));
+        }
+
+        let hash = digest::digest(&digest::SHA256, response.as_bytes());
+        if leading_zero_bits(hash.as_ref()) < self.difficulty {
+            return Err(anyhow!("Invalid challenge response"));
+        }
+
+        // Only solved challenges are remembered, which costs the work to fill up
+        let mut solved = POW_SOLVED.lock().unwrap();
+        if solved.contains_key(challenge) {
+            return Err(anyhow!("Challenge expired. Please try again."));
+        }
+        solved.insert(challenge.to_string(), expires);
+        Ok(())
+    }
+}
+
+/// Verify CAPTCHA tokens with the siteverify API shared by reCAPTCHA, hCaptcha and
+/// Turnstile.
+pub struct Captcha {
+    verify_url: String,
+    site_key: String,
+    secret: String,
+}
+
+impl Captcha {
+    pub fn new(
+        verify_url: impl Into<String>,
+        site_key: impl Into<String>,
+        secret: impl Into<String>,
+    ) -> Self {
+        Self {
+            verify_url: verify_url.into(),
+            site_key: site_key.into(),
+            secret: secret.into(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CaptchaVerification {
+    success: bool,
+    #[serde(rename = "error-codes", default)]
+    error_codes: Vec<String>,
+}
+
+impl SignupChallenge for Captcha {
+    fn kind(&self) -> &'static str {
+        "captcha"
+    }
+
+    fn issue(&self) -> Result<Value> {
+        Ok(json!({ "site_key": &self.site_key }))
+    }
+
+    fn verify(&self, response: &str, ip_addr: Option<&str>) -> Result<()> {
+        if response.is_empty() {
+            return Err(anyhow!("Challenge response is required"));
+        }
+
+        let mut form = vec![("secret", self.secret.as_str()), ("response", response)];
+        if let Some(ip_addr) = ip_addr {
+            form.push(("remoteip", ip_addr));
+        }
+        let verification: CaptchaVerification =
+            http_client::post_form(&self.verify_url, &form)?.json()?;
+        if !verification.success {
+            info!("CAPTCHA rejected: {:?}", &verification.error_codes);
+            return Err(anyhow!("Invalid challenge response"));
+        }
+        Ok(())
+    }
+}
+
+/// Challenge configured by env vars, falling back to `NoChallenge`.
+pub fn challenge_from_env() -> Box<dyn SignupChallenge> {
+    match dotenv::var("SIGNUP_CHALLENGE").unwrap_or_default().as_str() {
+        "proof_of_work" => Box::new(ProofOfWork::new(
+            dotenv::var("SIGNUP_POW_DIFFICULTY")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(20),
+        )),
+        "captcha" => Box::new(Captcha::new(
+            dotenv::var("CAPTCHA_VERIFY_URL")
+                .unwrap_or("https://hcaptcha.com/siteverify".to_string()),
+            dotenv::var("CAPTCHA_SITE_KEY").unwrap_or_default(),
+            dotenv::var("CAPTCHA_SECRET").unwrap_or_default(),
+        )),
+        _ => Box::new(NoChallenge),
+    }
+}
@@ -0,0 +1,134 @@
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::{HttpRequest, HttpResponseBuilder};
+use rand::{distributions::Alphanumeric, Rng};
+
+use super::access_token_max_age;
+use super::session::session_max_age;
+
+pub const JWT_COOKIE: &str = "cindy-jwt-token";
+pub const REFRESH_COOKIE: &str = "cindy-refresh-token";
+/// Readable by scripts, to be sent back in `CSRF_HEADER` (double-submit)
+pub const CSRF_COOKIE: &str = "cindy-csrf-token";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+const CSRF_TOKEN_LEN: usize = 32;
+
+/// Whether cookies are only sent over HTTPS, set with `COOKIE_SECURE`.
+fn cookie_secure() -> bool {
+    dotenv::var("COOKIE_SECURE")
+        .map(|s| s != "false" && s != "0")
+        .unwrap_or(true)
+}
+
+fn build_cookie(name: &'static str, value: String, http_only: bool) -> Cookie<'static> {
+    Cookie::build(name, value)
+        .path("/")
+        .http_only(http_only)
+        .secure(cookie_secure())
+        .same_site(SameSite::Lax)
+        .finish()
+}
+
+/// Cookies holding the access and refresh tokens of a session in cookie mode.
+pub fn session_cookies(jwt: String, refresh_token: String) -> Vec<Cookie<'static>> {
+    let mut jwt_cookie = build_cookie(JWT_COOKIE, jwt, true);
+    jwt_cookie.set_max_age(access_token_max_age());
+    let mut refresh_cookie = build_cookie(REFRESH_COOKIE, refresh_token, true);
+    refresh_cookie.set_max_age(session_max_age());
+    vec![jwt_cookie, refresh_cookie]
+}
+
+/// Cookie with the access token, e.g. after switching roles.
+pub fn jwt_cookie(jwt: String) -> Cookie<'static> {
+    let mut cookie = build_cookie(JWT_COOKIE, jwt, true);
+    cookie.set_max_age(access_token_max_age());
+    cookie
+}
+
+/// Cookie with a new CSRF token, issued on login.
+pub fn csrf_cookie() -> Cookie<'static> {
+    let token = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(CSRF_TOKEN_LEN)
+        .collect();
+    let mut cookie = build_cookie(CSRF_COOKIE, token, false);
+    cookie.set_max_age(session_max_age());
+    cookie
+}
+
+/// Cookies clearing the session on logout.
+pub fn removal_cookies() -> Vec<Cookie<'static>> {
+    [JWT_COOKIE, REFRESH_COOKIE, CSRF_COOKIE]
+        .iter()
+        .map(|name| {
+            let mut cookie = build_cookie(name, String::new(), true);
+            cookie.make_removal();
+            cookie
+        })
+        .collect()
+}
+
+/// Set all the cookies on the response.
+pub fn set_cookies<'a>(
+    builder: &'a mut HttpResponseBuilder,
+    cookies: Vec<Cookie<'static>>,
+) -> &'a mut HttpResponseBuilder {
+    for cookie in cookies {
+        builder.cookie(cookie);
+    }
+    builder
+}
+
+/// Whether the CSRF token sent back matches the cookie.
+pub fn csrf_matches(cookie: Option<&str>, sent: Option<&str>) -> bool {
+    match (cookie, sent) {
+        (Some(cookie), Some(sent)) => !cookie.is_empty() && cookie == sent,
+        _ => false,
+    }
+}
+
+/// Whether the request carries the CSRF token of its cookie in `CSRF_HEADER`.
+pub fn check_csrf(req: &HttpRequest) -> bool {
+    let cookie = req.cookie(CSRF_COOKIE);
+    csrf_matches(
+        cookie.as_ref().map(|cookie| cookie.value()),
+        req.headers()
+            .get(CSRF_HEADER)
+            .and_then(|value| value.to_str().ok()),
+    )
+}
+
+/// Extract the token from an `Authorization: Bearer <token>` value.
+pub fn bearer_token(value: &str) -> Option<String> {
+    // Drop `Bearer `
+    value.splitn(2, ' ').nth(1).map(|v| v.to_string())
+}
+
+/// Access token of the request, from the Authorization header or else the cookie.
+///
+/// State-changing endpoints use this, so a cookie is only accepted along with the CSRF
+/// token. Returns whether the token is taken from the cookie as well.
+pub fn request_token(req: &HttpRequest) -> Result<Option<(String, bool)>, &'static str> {
+    let header_token = req
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(bearer_token);
+    if let Some(token) = header_token {
+        return Ok(Some((token, false)));
+    }
+    match req.cookie(JWT_COOKIE) {
+        Some(cookie) if check_csrf(req) => Ok(Some((cookie.value().to_string(), true))),
+        Some(_) => Err("Invalid CSRF token"),
+        None => Ok(None),
+    }
+}
+
+/// Refresh token from the cookie, only accepted along with the CSRF token.
+pub fn refresh_token(req: &HttpRequest) -> Result<String, &'static str> {
+    match req.cookie(REFRESH_COOKIE) {
+        Some(cookie) if check_csrf(req) => Ok(cookie.value().to_string()),
+        Some(_) => Err("Invalid CSRF token"),
+        None => Err("Refresh token is required"),
+    }
+}
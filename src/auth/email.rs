@@ -0,0 +1,179 @@
+use actix_web::{web, HttpResponse, Result};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::context::GlobalCtx;
+use crate::mailer::{Mail, Mailer};
+use crate::models::{Timestamptz, User, ID};
+use crate::schema::{email_verification_token, user};
+
+use super::session::{digest_secret, gen_secret};
+use super::{error_response, AuthResponse};
+
+const EMAIL_MAX_LEN: usize = 254;
+
+#[derive(Serialize, Default)]
+pub struct EmailResponse {
+    error: Option<String>,
+    data: Option<EmailResponseData>,
+}
+
+impl AuthResponse for EmailResponse {
+    type Data = EmailResponseData;
+    fn data(&mut self, data: Self::Data) -> &mut Self {
+        self.data = Some(data);
+        self
+    }
+    fn error(&mut self, error: String) -> &mut Self {
+        self.error = Some(error);
+        self
+    }
+}
+
+#[derive(Serialize, Default)]
+pub struct EmailResponseData {
+    ok: bool,
+}
+
+fn email_verification_max_age() -> Duration {
+    Duration::hours(
+        dotenv::var("EMAIL_VERIFICATION_MAX_AGE")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(48),
+    )
+}
+
+/// Minimum interval between verification mails to a user.
+fn resend_interval() -> Duration {
+    Duration::minutes(1)
+}
+
+/// Check that the email looks like an address, the rest being up to the verification mail.
+pub fn validate_email(email: &str) -> Result<(), &'static str> {
+    if email.len() > EMAIL_MAX_LEN {
+        return Err("Email should be at most 254 characters");
+    }
+    match email.split_once('@') {
+        Some((local, domain))
+            if !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !email.contains(char::is_whitespace) =>
+        {
+            Ok(())
+        }
+        _ => Err("Invalid email address"),
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = email_verification_token)]
+struct CreateEmailVerificationTokenData<'a> {
+    user_id: ID,
+    email: &'a str,
+    token: String,
+    expires: Timestamptz,
+}
+
+/// Mail a single-use token to verify the current email of the user.
+pub fn send_verification(
+    conn: &mut PgConnection,
+    mailer: &dyn Mailer,
+    usr: &User,
+) -> anyhow::Result<()> {
+    if usr.email.is_empty() {
+        return Err(anyhow::anyhow!("No email to verify"));
+    }
+
+    let last_sent: Option<Timestamptz> = email_verification_token::table
+        .filter(email_verification_token::user_id.eq(usr.id))
+        .select(email_verification_token::created)
+        .order(email_verification_token::created.desc())
+        .first(conn)
+        .optional()?;
+    if last_sent.is_some_and(|created| created + resend_interval() > Utc::now()) {
+        return Err(anyhow::anyhow!(
+            "Verification mail was just sent. Please try again later."
+        ));
+    }
+
+    let secret = gen_secret();
+    diesel::insert_into(email_verification_token::table)
+        .values(&CreateEmailVerificationTokenData {
+            user_id: usr.id,
+            email: &usr.email,
+            token: digest_secret(&secret),
+            expires: Utc::now() + email_verification_max_age(),
+        })
+        .execute(conn)?;
+
+    let link = dotenv::var("EMAIL_VERIFICATION_URL")
+        .map(|url| format!("{}{}", url, secret))
+        .unwrap_or(secret);
+    mailer.send(&Mail {
+        to: usr.email.clone(),
+        subject: "Verify your email".to_string(),
+        body: format!(
+            "Hi {},\n\nUse the following to verify your email within {} hours:\n\n{}\n\nIf you did not sign up with this email, just ignore this mail.",
+            &usr.nickname,
+            email_verification_max_age().num_hours(),
+            link
+        ),
+    })?;
+
+    info!("Verification mail: User<{}:{}>", &usr.id, &usr.nickname);
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailBody {
+    token: String,
+}
+
+/// Mark the email as verified with the mailed token.
+pub async fn verify_email(
+    item: web::Json<VerifyEmailBody>,
+    ctx: web::Data<GlobalCtx>,
+) -> Result<HttpResponse> {
+    let result = ctx.get_conn().and_then(|mut conn| {
+        conn.transaction::<_, anyhow::Error, _>(|conn| {
+            // Consume the token, so that it can be used only once
+            let (user_id, email): (ID, String) = diesel::update(email_verification_token::table)
+                .filter(email_verification_token::token.eq(digest_secret(item.token.trim())))
+                .filter(email_verification_token::used.is_null())
+                .filter(email_verification_token::expires.gt(Utc::now()))
+                .set(email_verification_token::used.eq(Some(Utc::now())))
+                .returning((
+                    email_verification_token::user_id,
+                    email_verification_token::email,
+                ))
+                .get_result(conn)
+                .optional()?
+                .ok_or(anyhow::anyhow!("Invalid or expired token"))?;
+
+            // The email may have been changed after the mail is sent
+            let updated = diesel::update(user::table)
+                .filter(user::id.eq(user_id))
+                .filter(user::email.eq(&email))
+                .set(user::email_verified.eq(Some(Utc::now())))
+                .execute(conn)?;
+            if updated == 0 {
+                return Err(anyhow::anyhow!("Email has been changed since the mail"));
+            }
+
+            Ok(user_id)
+        })
+    });
+    let user_id = match result {
+        Ok(user_id) => user_id,
+        Err(error) => return error_response::<EmailResponse, _>(format!("{}", error)),
+    };
+
+    info!("/verify_email: User<{}>", user_id);
+
+    Ok(HttpResponse::Ok().json(EmailResponse::default().data(EmailResponseData { ok: true })))
+}
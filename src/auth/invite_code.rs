@@ -0,0 +1,68 @@
+use anyhow::anyhow;
+use chrono::Utc;
+use diesel::prelude::*;
+use rand::{seq::SliceRandom, thread_rng};
+
+use crate::models::{InviteCode, Timestamptz, ID};
+use crate::schema::invite_code;
+
+const INVITE_CODE_LEN: usize = 12;
+/// Letters and digits hard to mix up when typed by hand
+const INVITE_CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// Whether signing up requires an invite code, set with `SIGNUP_INVITE_ONLY`.
+pub fn invite_only() -> bool {
+    dotenv::var("SIGNUP_INVITE_ONLY")
+        .map(|s| s == "true" || s == "1")
+        .unwrap_or(false)
+}
+
+fn gen_invite_code() -> String {
+    let mut rng = thread_rng();
+    (0..INVITE_CODE_LEN)
+        .map(|_| *INVITE_CODE_CHARS.choose(&mut rng).unwrap() as char)
+        .collect()
+}
+
+/// Create a code usable for `max_uses` signups until it expires.
+pub fn create_invite_code(
+    conn: &mut PgConnection,
+    created_by: ID,
+    max_uses: i32,
+    expires: Option<Timestamptz>,
+) -> anyhow::Result<InviteCode> {
+    if max_uses < 1 {
+        return Err(anyhow!("Invite code should be usable at least once"));
+    }
+
+    let code = diesel::insert_into(invite_code::table)
+        .values((
+            invite_code::code.eq(gen_invite_code()),
+            invite_code::created_by.eq(Some(created_by)),
+            invite_code::max_uses.eq(max_uses),
+            invite_code::expires.eq(expires),
+        ))
+        .get_result(conn)?;
+
+    Ok(code)
+}
+
+/// Count a signup with the code, failing if it is used up, expired or revoked.
+///
+/// Call within the transaction creating the user, so that failed signups do not use it up.
+pub fn consume_invite_code(conn: &mut PgConnection, code: &str) -> anyhow::Result<ID> {
+    diesel::update(invite_code::table)
+        .filter(invite_code::code.eq(code.trim().to_uppercase()))
+        .filter(invite_code::revoked.is_null())
+        .filter(
+            invite_code::expires
+                .is_null()
+                .or(invite_code::expires.gt(Utc::now())),
+        )
+        .filter(invite_code::uses.lt(invite_code::max_uses))
+        .set(invite_code::uses.eq(invite_code::uses + 1))
+        .returning(invite_code::id)
+        .get_result(conn)
+        .optional()?
+        .ok_or(anyhow!("Invalid or expired invite code"))
+}
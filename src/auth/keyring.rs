@@ -0,0 +1,237 @@
+use actix_web::HttpResponse;
+use anyhow::{anyhow, Context, Result};
+use frank_jwt::{decode, encode, Algorithm, ValidationOptions};
+use openssl::rsa::Rsa;
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+
+use super::DEFAULT_SECRET;
+
+const PRIVATE_KEY_SUFFIX: &str = ".key.pem";
+const PUBLIC_KEY_SUFFIX: &str = ".pub.pem";
+/// Secret once suggested by `.env.example`, as public as the default
+const EXAMPLE_SECRET: &str = "CINDYTHINK_SECRET";
+
+/// A key trusted to verify tokens, and to sign them if it is the active one.
+struct JwtKey {
+    kid: String,
+    algorithm: Algorithm,
+    signing_key: Option<Vec<u8>>,
+    verifying_key: Vec<u8>,
+    /// Public key in JWK format, for RSA keys only
+    jwk: Option<Value>,
+}
+
+impl JwtKey {
+    fn rsa(kid: String, signing_key: Option<Vec<u8>>, verifying_key: Vec<u8>) -> Result<Self> {
+        let rsa = Rsa::public_key_from_pem(&verifying_key)
+            .with_context(|| format!("Invalid RSA public key `{}`", &kid))?;
+        let jwk = json!({
+            "kty": "RSA",
+            "use": "sig",
+            "alg": "RS256",
+            "kid": &kid,
+            "n": base64::encode_config(rsa.n().to_vec(), base64::URL_SAFE_NO_PAD),
+            "e": base64::encode_config(rsa.e().to_vec(), base64::URL_SAFE_NO_PAD),
+        });
+        Ok(Self {
+            kid,
+            algorithm: Algorithm::RS256,
+            signing_key,
+            verifying_key,
+            jwk: Some(jwk),
+        })
+    }
+
+    fn hmac(kid: String, secret: String) -> Self {
+        let secret = secret.into_bytes();
+        Self {
+            kid,
+            algorithm: Algorithm::HS256,
+            signing_key: Some(secret.clone()),
+            verifying_key: secret,
+            jwk: None,
+        }
+    }
+}
+
+/// Keys for signing and verifying JWTs.
+///
+/// Tokens are signed by the active key with its `kid` in the header, and accepted when
+/// signed by any key in the ring, so that keys can be rotated without logging users out:
+/// add a new key, make it active, and drop the old one after access tokens expire.
+pub struct Keyring {
+    keys: Vec<JwtKey>,
+    active: usize,
+}
+
+impl Keyring {
+    /// Load the keyring configured by env vars.
+    ///
+    /// - `JWT_KEYRING`: folder of `<kid>.pub.pem` RSA public keys, with `<kid>.key.pem`
+    ///   private keys for those that may sign, and `JWT_ACTIVE_KEY` the kid signing
+    /// - `PRIVATE_KEY_PATH` and `PUBLIC_KEY_PATH`: a single RSA key
+    /// - `SECRET`: a single HMAC secret, refused if left as the default or the one in
+    ///   `.env.example` unless `DEV_MODE`
+    pub fn from_env() -> Result<Self> {
+        if let Ok(folder) = dotenv::var("JWT_KEYRING") {
+            return Self::from_folder(PathBuf::from(folder));
+        }
+
+        if let Ok(keypath) = dotenv::var("PRIVATE_KEY_PATH") {
+            let signing_key = fs::read(&keypath)
+                .with_context(|| format!("Error reading private key {}", &keypath))?;
+            let public_keypath =
+                dotenv::var("PUBLIC_KEY_PATH").context("PUBLIC_KEY_PATH is not set")?;
+            let verifying_key = fs::read(&public_keypath)
+                .with_context(|| format!("Error reading public key {}", &public_keypath))?;
+            return Ok(Self {
+                keys: vec![JwtKey::rsa(
+                    "default".to_string(),
+                    Some(signing_key),
+                    verifying_key,
+                )?],
+                active: 0,
+            });
+        }
+
+        let secret = dotenv::var("SECRET").unwrap_or(DEFAULT_SECRET.to_string());
+        if (secret == DEFAULT_SECRET || secret == EXAMPLE_SECRET) && !dev_mode() {
+            return Err(anyhow!(
+                "Refusing to sign tokens with a default secret. Set SECRET, or DEV_MODE=true for development."
+            ));
+        }
+        Ok(Self {
+            keys: vec![JwtKey::hmac("default".to_string(), secret)],
+            active: 0,
+        })
+    }
+
+    fn from_folder(folder: PathBuf) -> Result<Self> {
+        let mut keys = vec![];
+        let entries = fs::read_dir(&folder)
+            .with_context(|| format!("Error reading keyring {}", folder.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            let filename = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default();
+            let kid = match filename.strip_suffix(PUBLIC_KEY_SUFFIX) {
+                Some(kid) => kid.to_string(),
+                None => continue,
+            };
+
+            let verifying_key = fs::read(&path)
+                .with_context(|| format!("Error reading public key {}", path.display()))?;
+            let private_path = folder.join(format!("{}{}", &kid, PRIVATE_KEY_SUFFIX));
+            let signing_key = if private_path.exists() {
+                Some(fs::read(&private_path).with_context(|| {
+                    format!("Error reading private key {}", private_path.display())
+                })?)
+            } else {
+                None
+            };
+            keys.push(JwtKey::rsa(kid, signing_key, verifying_key)?);
+        }
+        keys.sort_by(|a, b| a.kid.cmp(&b.kid));
+
+        let active_kid = dotenv::var("JWT_ACTIVE_KEY").context("JWT_ACTIVE_KEY is not set")?;
+        let active = keys
+            .iter()
+            .position(|key| key.kid == active_kid)
+            .ok_or(anyhow!(
+                "Active key `{}` is not in the keyring",
+                &active_kid
+            ))?;
+        if keys[active].signing_key.is_none() {
+            return Err(anyhow!(
+                "Active key `{}` has no private key to sign with",
+                &active_kid
+            ));
+        }
+
+        Ok(Self { keys, active })
+    }
+
+    /// Sign the payload with the active key.
+    pub fn sign(&self, payload: &Value) -> Result<String> {
+        let key = &self.keys[self.active];
+        let signing_key = key
+            .signing_key
+            .as_ref()
+            .ok_or(anyhow!("Key `{}` cannot sign", &key.kid))?;
+        Ok(encode(
+            json!({ "kid": &key.kid }),
+            signing_key,
+            payload,
+            key.algorithm,
+        )?)
+    }
+
+    /// Verify the token with the key of its `kid`, returning the payload.
+    ///
+    /// Tokens without a `kid`, issued before the keyring, are checked with the active key.
+    pub fn verify(&self, token: &str) -> Result<Value> {
+        let kid = token_kid(token)?;
+        let key = match kid {
+            Some(kid) => self
+                .keys
+                .iter()
+                .find(|key| key.kid == kid)
+                .ok_or(anyhow!("Unknown key `{}`", kid))?,
+            None => &self.keys[self.active],
+        };
+        let (_, payload) = decode(
+            &token,
+            &key.verifying_key,
+            key.algorithm,
+            &ValidationOptions::default(),
+        )?;
+        Ok(payload)
+    }
+
+    /// Public keys in JWK Set format.
+    pub fn jwks(&self) -> Value {
+        json!({
+            "keys": self.keys.iter().filter_map(|key| key.jwk.clone()).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Whether running in development, set with `DEV_MODE`.
+fn dev_mode() -> bool {
+    dotenv::var("DEV_MODE")
+        .map(|s| s == "true" || s == "1")
+        .unwrap_or(false)
+}
+
+/// `kid` in the header of the token, if any.
+fn token_kid(token: &str) -> Result<Option<String>> {
+    let header = token.split('.').next().unwrap_or_default();
+    let header = base64::decode_config(header, base64::URL_SAFE_NO_PAD)?;
+    let header: Value = serde_json::from_slice(&header)?;
+    Ok(header
+        .get("kid")
+        .and_then(|kid| kid.as_str())
+        .map(|kid| kid.to_string()))
+}
+
+lazy_static! {
+    static ref KEYRING: Keyring = Keyring::from_env().expect("Error loading JWT keyring");
+}
+
+/// Load the keyring, so that a misconfiguration stops the server from starting.
+pub fn init() {
+    lazy_static::initialize(&KEYRING);
+}
+
+pub fn keyring() -> &'static Keyring {
+    &KEYRING
+}
+
+/// Public keys to verify tokens issued by this server.
+pub async fn jwks() -> HttpResponse {
+    HttpResponse::Ok().json(keyring().jwks())
+}
@@ -1,21 +1,35 @@
 use actix_web::{web, HttpRequest, HttpResponse, Result};
+use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::context::GlobalCtx;
+use crate::models::permission::user_permissions;
 use crate::models::User;
 
-use super::{error_response, get_jwt, AuthResponse};
+use super::cookie::{csrf_cookie, session_cookies, set_cookies};
+use super::session::start_session;
+use super::throttle::{self, ThrottleKind};
+use super::two_factor;
+use super::{client_ip, error_response, get_jwt, lockout_response, AuthResponse};
 
 #[derive(Deserialize)]
 pub struct LoginBody {
     username: String,
     password: String,
+    /// Keep the tokens in HttpOnly cookies instead of the response
+    #[serde(default)]
+    cookie: bool,
 }
 
 #[derive(Serialize, Default)]
 pub struct LoginResponse {
     error: Option<String>,
     data: Option<LoginResponseData>,
+    /// Seconds to wait when locked out for too many failed attempts
+    retry_after: Option<i64>,
+    /// Token to pass to `/login/two_factor` with a code, when two-factor authentication
+    /// is enabled
+    two_factor_token: Option<String>,
 }
 
 impl AuthResponse for LoginResponse {
@@ -28,13 +42,21 @@ impl AuthResponse for LoginResponse {
         self.error = Some(error);
         self
     }
+    fn retry_after(&mut self, seconds: i64) -> &mut Self {
+        self.retry_after = Some(seconds);
+        self
+    }
 }
 
 #[derive(Serialize)]
 pub struct LoginResponseData {
     id: i32,
     username: String,
-    auth_token: String,
+    /// Left out in cookie mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auth_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
 }
 
 pub async fn login(
@@ -42,16 +64,22 @@ pub async fn login(
     ctx: web::Data<GlobalCtx>,
     req: HttpRequest,
 ) -> Result<HttpResponse> {
-    let headers = req.headers();
-    let connection_info = req.connection_info();
-    let ip_addr = if let Some(header_real_ip) = dotenv::var("HEADER_REAL_IP").ok() {
-        headers
-            .get(header_real_ip)
-            .and_then(|ip| ip.to_str().ok())
-            .or_else(|| connection_info.peer_addr())
-    } else {
-        connection_info.peer_addr()
-    };
+    let ip_addr = client_ip(&req);
+    let ip_addr = ip_addr.as_deref();
+
+    let mut throttle_keys = vec![(ThrottleKind::LoginUsername, item.username.as_str())];
+    if let Some(ip_addr) = ip_addr {
+        throttle_keys.push((ThrottleKind::LoginIp, ip_addr));
+    }
+    if let Some(lockout) = throttle::check(&throttle_keys) {
+        warn!(
+            "({}) /login: Locked out by {:?}: username = '{}'",
+            ip_addr.unwrap_or_default(),
+            lockout.kind,
+            &item.username
+        );
+        return lockout_response::<LoginResponse>(&lockout);
+    }
 
     let conn = ctx.get_conn().expect("Error getting connection");
     let user: User = match User::local_auth(&item.username, &item.password, conn).await {
@@ -62,9 +90,36 @@ pub async fn login(
                 ip_addr.unwrap_or_default(),
                 &item.username
             );
+            // Count the failure for all keys, before responding with any lockout
+            let mut lockout = None;
+            for (kind, key) in throttle_keys.iter() {
+                if let Some(new_lockout) = throttle::record(*kind, key) {
+                    lockout = Some(new_lockout);
+                }
+            }
+            if let Some(lockout) = lockout {
+                return lockout_response::<LoginResponse>(&lockout);
+            }
             return error_response::<LoginResponse, _>(format!("{}", error));
         }
     };
+    let two_factor_enabled = match ctx
+        .get_conn()
+        .and_then(|mut conn| Ok(two_factor::is_enabled(&mut conn, user.id)?))
+    {
+        Ok(enabled) => enabled,
+        Err(error) => return error_response::<LoginResponse, _>(format!("{}", error)),
+    };
+    if two_factor_enabled {
+        info!(
+            "({}) /login: Two-factor challenge: User<{}:{}>",
+            ip_addr.unwrap_or_default(),
+            &user.id,
+            &user.nickname
+        );
+        return two_factor_response(&user, &item.username, item.cookie);
+    }
+    throttle::reset(ThrottleKind::LoginUsername, &item.username);
 
     // Logging
     info!(
@@ -74,13 +129,132 @@ pub async fn login(
         &user.nickname
     );
 
-    let jwt = get_jwt(&user, None);
+    start_login(&ctx, user, item.cookie)
+}
+
+#[derive(Deserialize)]
+pub struct LoginTwoFactorBody {
+    two_factor_token: String,
+    /// TOTP code from the device, or a backup code
+    code: String,
+}
+
+/// Second step of `/login` for users with two-factor authentication enabled.
+pub async fn login_two_factor(
+    item: web::Json<LoginTwoFactorBody>,
+    ctx: web::Data<GlobalCtx>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    use crate::schema::user;
+
+    let ip_addr = client_ip(&req);
+    let ip_addr = ip_addr.as_deref();
+
+    let (user_id, username, cookie) = match two_factor::find_challenge(&item.two_factor_token) {
+        Some(challenge) => challenge,
+        None => return error_response::<LoginResponse, _>("Login expired. Please login again."),
+    };
+
+    // Failed codes count towards the same lockouts as failed passwords
+    let mut throttle_keys = vec![(ThrottleKind::LoginUsername, username.as_str())];
+    if let Some(ip_addr) = ip_addr {
+        throttle_keys.push((ThrottleKind::LoginIp, ip_addr));
+    }
+    if let Some(lockout) = throttle::check(&throttle_keys) {
+        return lockout_response::<LoginResponse>(&lockout);
+    }
+
+    let result = ctx.get_conn().and_then(|mut conn| {
+        if !two_factor::verify(&mut conn, user_id, &item.code)? {
+            return Ok(None);
+        }
+        let user: User = user::table.filter(user::id.eq(user_id)).first(&mut conn)?;
+        Ok(Some(user))
+    });
+    let user = match result {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            info!(
+                "({}) /login/two_factor: Invalid code: User<{}>",
+                ip_addr.unwrap_or_default(),
+                user_id
+            );
+            let mut lockout = None;
+            for (kind, key) in throttle_keys.iter() {
+                if let Some(new_lockout) = throttle::record(*kind, key) {
+                    lockout = Some(new_lockout);
+                }
+            }
+            if let Some(lockout) = lockout {
+                two_factor::finish_challenge(&item.two_factor_token);
+                return lockout_response::<LoginResponse>(&lockout);
+            }
+            return error_response::<LoginResponse, _>("Invalid code");
+        }
+        Err(error) => return error_response::<LoginResponse, _>(format!("{}", error)),
+    };
+    two_factor::finish_challenge(&item.two_factor_token);
+    throttle::reset(ThrottleKind::LoginUsername, &username);
+
+    // Logging
+    info!(
+        "({}) /login/two_factor: User<{}:{}>",
+        ip_addr.unwrap_or_default(),
+        &user.id,
+        &user.nickname
+    );
+
+    start_login(&ctx, user, cookie)
+}
+
+/// Respond with the token to pass to `/login/two_factor`, instead of starting a session.
+///
+/// Failed codes are counted towards the lockout of `username`.
+pub(super) fn two_factor_response(
+    user: &User,
+    username: &str,
+    cookie: bool,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(LoginResponse {
+        two_factor_token: Some(two_factor::start_challenge(user.id, username, cookie)),
+        ..Default::default()
+    }))
+}
+
+/// Start a session for the authenticated user, responding with its tokens.
+///
+/// In cookie mode, the tokens are set in cookies along with a new CSRF token instead.
+pub(super) fn start_login(ctx: &GlobalCtx, user: User, cookie: bool) -> Result<HttpResponse> {
+    let (permissions, (session, refresh_token)) = match ctx.get_conn().and_then(|mut conn| {
+        Ok((
+            user_permissions(&mut conn, user.id)?,
+            start_session(&mut conn, user.id)?,
+        ))
+    }) {
+        Ok(result) => result,
+        Err(error) => return error_response::<LoginResponse, _>(format!("{}", error)),
+    };
+    let jwt = get_jwt(&user, None, &permissions, session.id);
+
+    if cookie {
+        let mut cookies = session_cookies(jwt, refresh_token);
+        cookies.push(csrf_cookie());
+        return Ok(set_cookies(&mut HttpResponse::Ok(), cookies).json(
+            LoginResponse::default().data(LoginResponseData {
+                id: user.id,
+                username: user.username,
+                auth_token: None,
+                refresh_token: None,
+            }),
+        ));
+    }
 
-    Ok(HttpResponse::Ok()
-        //.cookie(gen_cookie(&user))
-        .json(LoginResponse::default().data(LoginResponseData {
+    Ok(
+        HttpResponse::Ok().json(LoginResponse::default().data(LoginResponseData {
             id: user.id,
             username: user.username,
-            auth_token: jwt,
-        })))
+            auth_token: Some(jwt),
+            refresh_token: Some(refresh_token),
+        })),
+    )
 }
@@ -0,0 +1,92 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::context::GlobalCtx;
+
+use super::cookie::{self, removal_cookies, set_cookies};
+use super::session::{find_session, revoke_session, revoke_user_sessions};
+use super::{client_ip, AuthResponse};
+
+#[derive(Deserialize)]
+pub struct LogoutBody {
+    /// Taken from the cookie in cookie mode
+    refresh_token: Option<String>,
+    /// Log out all sessions of the user
+    #[serde(default)]
+    all: bool,
+}
+
+#[derive(Serialize, Default)]
+pub struct LogoutResponse {
+    error: Option<String>,
+    data: Option<LogoutResponseData>,
+}
+
+impl AuthResponse for LogoutResponse {
+    type Data = LogoutResponseData;
+    fn data(&mut self, data: Self::Data) -> &mut Self {
+        self.data = Some(data);
+        self
+    }
+    fn error(&mut self, error: String) -> &mut Self {
+        self.error = Some(error);
+        self
+    }
+}
+
+#[derive(Serialize)]
+pub struct LogoutResponseData {
+    /// Number of sessions revoked
+    revoked: usize,
+}
+
+/// Respond with the error, clearing the cookies all the same.
+fn clearing_error_response<E: Into<String>>(error: E) -> Result<HttpResponse> {
+    Ok(
+        set_cookies(&mut HttpResponse::BadRequest(), removal_cookies())
+            .json(LogoutResponse::default().error(error.into())),
+    )
+}
+
+pub async fn logout(
+    item: web::Json<LogoutBody>,
+    ctx: web::Data<GlobalCtx>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let ip_addr = client_ip(&req);
+    let ip_addr = ip_addr.as_deref();
+
+    let refresh_token = match item.refresh_token.as_ref() {
+        Some(refresh_token) => refresh_token.clone(),
+        None => match cookie::refresh_token(&req) {
+            Ok(refresh_token) => refresh_token,
+            Err(error) => return clearing_error_response(error),
+        },
+    };
+
+    let result = ctx.get_conn().and_then(|mut conn| {
+        let session = find_session(&mut conn, &refresh_token)?;
+        let revoked = if item.all {
+            revoke_user_sessions(&mut conn, session.user_id, None)?
+        } else {
+            revoke_session(&mut conn, session.id)?
+        };
+        Ok((session, revoked))
+    });
+    let (session, revoked) = match result {
+        Ok(result) => result,
+        Err(error) => return clearing_error_response(format!("{}", error)),
+    };
+
+    // Logging
+    info!(
+        "({}) /logout: User<{}> ({} session(s))",
+        ip_addr.unwrap_or_default(),
+        &session.user_id,
+        revoked
+    );
+
+    // Clear the cookies in any case, so that cookie mode is left as well
+    Ok(set_cookies(&mut HttpResponse::Ok(), removal_cookies())
+        .json(LogoutResponse::default().data(LogoutResponseData { revoked })))
+}
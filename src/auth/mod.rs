@@ -1,25 +1,48 @@
 use actix_web::cookie::time::{Duration, OffsetDateTime};
-use actix_web::{cookie::Cookie, HttpResponse, Result};
-use frank_jwt::{decode, encode, Algorithm, ValidationOptions};
+use actix_web::{HttpRequest, HttpResponse, Result};
 use serde::{Deserialize, Serialize};
 use std::fmt;
-use std::path::PathBuf;
+use uuid::Uuid;
 
 use crate::models::User;
+use keyring::keyring;
+use throttle::Lockout;
 
+pub mod access_token;
+pub mod challenge;
+pub mod cookie;
+pub mod email;
+pub mod invite_code;
+pub mod keyring;
 mod login;
+mod logout;
+pub mod oidc;
+mod password;
+mod refresh;
 mod role_switch;
+pub mod session;
 mod signup;
+pub mod throttle;
+pub mod two_factor;
 
-pub use login::login;
+pub use email::verify_email;
+pub use login::{login, login_two_factor};
+pub use logout::logout;
+pub use oidc::{oidc_callback, oidc_login};
+pub use password::{change_password, confirm_reset_password, reset_password};
+pub use refresh::refresh;
 pub use role_switch::role_switch;
-pub use signup::signup;
+pub use signup::{signup, signup_challenge};
 
 pub trait AuthResponse {
     type Data;
 
     fn data(&mut self, data: Self::Data) -> &mut Self;
     fn error(&mut self, error: String) -> &mut Self;
+    /// Seconds before a locked out client may try again
+    fn retry_after(&mut self, _seconds: i64) -> &mut Self {
+        self
+    }
 }
 
 const DEFAULT_SECRET: &'static str = "CINDYTHINK_HEYRICT";
@@ -71,6 +94,13 @@ pub struct JwtPayload {
     user: JwtPayloadUser,
     role: Role,
     allowed_roles: Vec<Role>,
+    /// Permissions granted to the user directly or by groups, e.g. `chatmessage.delete`
+    #[serde(default)]
+    permissions: Vec<String>,
+    /// Session the token is issued for, revoked on logout
+    jti: Option<Uuid>,
+    /// Expiry as a Unix timestamp
+    exp: Option<i64>,
 }
 
 impl JwtPayload {
@@ -89,28 +119,42 @@ impl JwtPayload {
     pub fn get_user_id(&self) -> crate::models::ID {
         self.user.id
     }
+
+    pub fn get_permissions(&self) -> &Vec<String> {
+        &self.permissions
+    }
+
+    pub fn get_session_id(&self) -> Option<Uuid> {
+        self.jti
+    }
+
+    pub fn get_exp(&self) -> Option<i64> {
+        self.exp
+    }
+
+    /// Payload standing for a personal access token of the user.
+    ///
+    /// The token acts as `User` without any permissions, and is not tied to a session.
+    pub fn for_access_token(user: &User) -> Self {
+        Self {
+            user: JwtPayloadUser {
+                id: user.id,
+                icon: user.icon.clone(),
+                username: user.username.clone(),
+                nickname: user.nickname.clone(),
+            },
+            role: Role::User,
+            allowed_roles: vec![Role::User],
+            permissions: vec![],
+            jti: None,
+            exp: None,
+        }
+    }
 }
 
 pub fn parse_jwt(token: &str) -> Result<JwtPayload, anyhow::Error> {
-    let result = if let Some(keypath) = dotenv::var("PUBLIC_KEY_PATH").ok() {
-        decode(
-            &token,
-            &PathBuf::from(keypath),
-            Algorithm::RS256,
-            &ValidationOptions::default(),
-        )
-    } else {
-        let secret = dotenv::var("SECRET").unwrap_or(DEFAULT_SECRET.to_string());
-        decode(
-            &token,
-            &secret,
-            Algorithm::HS256,
-            &ValidationOptions::default(),
-        )
-    };
-    result
-        .map(|(_, payload)| payload)
-        .map_err(anyhow::Error::from)
+    keyring()
+        .verify(token)
         .and_then(|val| serde_json::from_value(val).map_err(anyhow::Error::from))
 }
 
@@ -122,17 +166,26 @@ fn get_allowed_roles(user: &User) -> Vec<Role> {
     returns
 }
 
-pub fn get_jwt(user: &User, role: Option<Role>) -> String {
-    let max_age = Duration::days(
-        dotenv::var("LOGIN_MAX_AGE")
+/// Lifetime of access tokens, which are renewed with refresh tokens.
+fn access_token_max_age() -> Duration {
+    Duration::minutes(
+        dotenv::var("ACCESS_TOKEN_MAX_AGE")
             .ok()
             .and_then(|s| s.parse().ok())
-            .unwrap_or(30),
-    );
+            .unwrap_or(15),
+    )
+}
+
+pub fn get_jwt(
+    user: &User,
+    role: Option<Role>,
+    permissions: &[String],
+    session_id: Uuid,
+) -> String {
+    let max_age = access_token_max_age();
 
     let iat = OffsetDateTime::now_utc();
     let exp: OffsetDateTime = iat + max_age;
-    let header = json!({});
     let allowed_roles = get_allowed_roles(&user);
     let role = if let Some(role) = role {
         if allowed_roles.contains(&role) {
@@ -153,55 +206,36 @@ pub fn get_jwt(user: &User, role: Option<Role>) -> String {
             "nickname": user.nickname,
         },
         "role": role,
-        "allowed_roles": allowed_roles
+        "allowed_roles": allowed_roles,
+        "permissions": permissions,
+        "jti": session_id,
     });
 
-    if let Some(keypath) = dotenv::var("PRIVATE_KEY_PATH").ok() {
-        encode(header, &PathBuf::from(keypath), &payload, Algorithm::RS256)
-            .expect("Error encoding jwt with RS256.")
-    } else {
-        let secret = dotenv::var("SECRET").unwrap_or(DEFAULT_SECRET.to_string());
-        encode(header, &secret, &payload, Algorithm::HS256).expect("Error encoding jwt with HS256.")
-    }
+    keyring().sign(&payload).expect("Error encoding jwt.")
 }
 
-pub fn switch_jwt_role(payload: &JwtPayload, role: Role) -> String {
-    let max_age = Duration::days(
-        dotenv::var("LOGIN_MAX_AGE")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(30),
-    );
-
-    let iat = OffsetDateTime::now_utc();
-    let exp: OffsetDateTime = iat + max_age;
-    let header = json!({});
-    let user = &payload.user;
-    let allowed_roles = &payload.allowed_roles;
-    let role = if allowed_roles.contains(&role) {
-        role
+/// Check that the password is acceptable for a new credential.
+fn validate_password(password: &str) -> Result<(), &'static str> {
+    if password.is_empty() {
+        Err("Password cannot be blank!")
+    } else if password.len() < 6 {
+        Err("Password must be at least 6 characters long")
     } else {
-        Role::User
-    };
-    let payload = json!({
-        "iat": iat.unix_timestamp(),
-        "exp": exp.unix_timestamp(),
-        "user": {
-            "id": user.id,
-            "icon": user.icon,
-            "username": user.username,
-            "nickname": user.nickname,
-        },
-        "role": role,
-        "allowed_roles": allowed_roles
-    });
+        Ok(())
+    }
+}
 
-    if let Some(keypath) = dotenv::var("PRIVATE_KEY_PATH").ok() {
-        encode(header, &PathBuf::from(keypath), &payload, Algorithm::RS256)
-            .expect("Error encoding jwt with RS256.")
+/// IP address of the client, taken from the `HEADER_REAL_IP` header behind a proxy.
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    let connection_info = req.connection_info();
+    if let Some(header_real_ip) = dotenv::var("HEADER_REAL_IP").ok() {
+        req.headers()
+            .get(header_real_ip)
+            .and_then(|ip| ip.to_str().ok())
+            .or_else(|| connection_info.peer_addr())
+            .map(String::from)
     } else {
-        let secret = dotenv::var("SECRET").unwrap_or(DEFAULT_SECRET.to_string());
-        encode(header, &secret, &payload, Algorithm::HS256).expect("Error encoding jwt with HS256.")
+        connection_info.peer_addr().map(String::from)
     }
 }
 
@@ -213,18 +247,20 @@ where
     Ok(HttpResponse::BadRequest().json(T::default().error(error.into())))
 }
 
-fn gen_cookie(user: &User) -> Cookie {
-    let max_age = Duration::days(
-        dotenv::var("SUBSCRIPTION_MAX_CACHE_TIME")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(30),
-    );
-
-    Cookie::build("cindy-jwt-token", get_jwt(user, None))
-        .expires(OffsetDateTime::now_utc() + max_age)
-        .max_age(max_age)
-        .http_only(true)
-        .path("/")
-        .finish()
+/// Respond `429 Too Many Requests` to a locked out client.
+fn lockout_response<T>(lockout: &Lockout) -> Result<HttpResponse>
+where
+    T: Default + AuthResponse + Serialize,
+{
+    let retry_after = lockout.retry_after();
+    Ok(HttpResponse::TooManyRequests()
+        .insert_header(("Retry-After", retry_after.to_string()))
+        .json(
+            T::default()
+                .error(format!(
+                    "Too many attempts. Please try again in {} seconds.",
+                    retry_after
+                ))
+                .retry_after(retry_after),
+        ))
 }
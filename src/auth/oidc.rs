@@ -0,0 +1,695 @@
+use actix_web::{http::header, web, HttpRequest, HttpResponse, Result};
+use anyhow::{anyhow, Context};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use frank_jwt::{decode, Algorithm, ValidationOptions};
+use openssl::bn::BigNum;
+use openssl::rsa::Rsa;
+use rand::Rng;
+use ring::digest;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::context::GlobalCtx;
+use crate::http_client;
+use crate::models::user_suspension::{active_suspension_scopes, SuspensionScope};
+use crate::models::{Timestamptz, User, ID};
+use crate::schema::{user, user_identity};
+
+use super::login::{start_login, two_factor_response, LoginResponse};
+use super::session::gen_secret;
+use super::throttle::{self, ThrottleKind};
+use super::{client_ip, error_response, invite_code, lockout_response, two_factor};
+
+/// An OpenID Connect provider, configured by `OIDC_<NAME>_*` env vars.
+struct Provider {
+    name: String,
+    issuer: String,
+    client_id: String,
+    client_secret: String,
+    /// Callback URL registered with the provider, usually `/oidc/<name>/callback`
+    redirect_uri: String,
+    scopes: String,
+}
+
+impl Provider {
+    /// Provider of the name, if listed in `OIDC_PROVIDERS`.
+    fn from_env(name: &str) -> anyhow::Result<Self> {
+        let name = name.to_lowercase();
+        let listed = dotenv::var("OIDC_PROVIDERS")
+            .unwrap_or_default()
+            .split(',')
+            .any(|provider| provider.trim().to_lowercase() == name);
+        if !listed {
+            return Err(anyhow!("Unknown login provider `{}`", name));
+        }
+
+        let var = |key: &str| {
+            let var = format!("OIDC_{}_{}", name.to_uppercase(), key);
+            dotenv::var(&var).with_context(|| format!("{} is not set", var))
+        };
+        Ok(Self {
+            issuer: var("ISSUER")?.trim_end_matches('/').to_string(),
+            client_id: var("CLIENT_ID")?,
+            client_secret: var("CLIENT_SECRET").unwrap_or_default(),
+            redirect_uri: var("REDIRECT_URI")?,
+            scopes: var("SCOPES").unwrap_or("openid email profile".to_string()),
+            name,
+        })
+    }
+
+    /// Fetch the provider metadata, blocking the thread.
+    fn discover(&self) -> anyhow::Result<Discovery> {
+        let discovery: Discovery =
+            http_client::get(&format!("{}/.well-known/openid-configuration", self.issuer))?
+                .json()?;
+        if discovery.issuer.trim_end_matches('/') != self.issuer {
+            return Err(anyhow!("Issuer mismatch in provider metadata"));
+        }
+        Ok(discovery)
+    }
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::One(aud) => aud == client_id,
+            Audience::Many(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
+
+/// Claims of a validated ID token.
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    iss: String,
+    sub: String,
+    aud: Audience,
+    nonce: Option<String>,
+    email: Option<String>,
+    email_verified: Option<bool>,
+    preferred_username: Option<String>,
+    name: Option<String>,
+}
+
+/// A login redirected to the provider, waiting for the callback.
+struct PendingLogin {
+    provider: String,
+    nonce: String,
+    /// PKCE code verifier
+    verifier: String,
+    cookie: bool,
+    expires: Timestamptz,
+}
+
+lazy_static! {
+    static ref PENDING: Mutex<HashMap<String, PendingLogin>> = Default::default();
+}
+
+fn pending_max_age() -> Duration {
+    Duration::minutes(10)
+}
+
+/// Drop logins never coming back from the provider.
+pub fn cleanup() {
+    let mut map = PENDING.lock().unwrap();
+    let now = Utc::now();
+    map.retain(|_, pending| pending.expires > now);
+}
+
+fn base64_url(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+#[derive(Deserialize)]
+pub struct OidcLoginQuery {
+    /// Keep the tokens in HttpOnly cookies instead of the response
+    #[serde(default)]
+    cookie: bool,
+}
+
+/// Redirect to the authorization endpoint of the provider.
+pub async fn oidc_login(
+    provider: web::Path<String>,
+    query: web::Query<OidcLoginQuery>,
+) -> Result<HttpResponse> {
+    let provider = match Provider::from_env(&provider) {
+        Ok(provider) => provider,
+        Err(error) => return error_response::<LoginResponse, _>(format!("{}", error)),
+    };
+    let (provider, discovery) = match web::block(move || {
+        let discovery = provider.discover()?;
+        Ok::<_, anyhow::Error>((provider, discovery))
+    })
+    .await
+    {
+        Ok(Ok(result)) => result,
+        Ok(Err(error)) => {
+            error!("Error discovering OIDC provider: {:?}", error);
+            return error_response::<LoginResponse, _>(format!("{}", error));
+        }
+        Err(error) => return error_response::<LoginResponse, _>(format!("{}", error)),
+    };
+
+    let state = gen_secret();
+    let nonce = gen_secret();
+    let verifier = gen_secret();
+    let challenge = base64_url(digest::digest(&digest::SHA256, verifier.as_bytes()).as_ref());
+
+    let mut url = url::Url::parse(&discovery.authorization_endpoint)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &provider.client_id)
+        .append_pair("redirect_uri", &provider.redirect_uri)
+        .append_pair("scope", &provider.scopes)
+        .append_pair("state", &state)
+        .append_pair("nonce", &nonce)
+        .append_pair("code_challenge", &challenge)
+        .append_pair("code_challenge_method", "S256");
+
+    PENDING.lock().unwrap().insert(
+        state,
+        PendingLogin {
+            provider: provider.name,
+            nonce,
+            verifier,
+            cookie: query.cookie,
+            expires: Utc::now() + pending_max_age(),
+        },
+    );
+
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, url.to_string()))
+        .finish())
+}
+
+/// Exchange the code for an ID token and validate it, blocking the thread.
+fn authenticate(
+    provider: &Provider,
+    pending: &PendingLogin,
+    code: &str,
+) -> anyhow::Result<IdTokenClaims> {
+    let discovery = provider.discover()?;
+
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", provider.redirect_uri.as_str()),
+        ("client_id", provider.client_id.as_str()),
+        ("code_verifier", pending.verifier.as_str()),
+    ];
+    if !provider.client_secret.is_empty() {
+        form.push(("client_secret", provider.client_secret.as_str()));
+    }
+    let token: TokenResponse = http_client::post_form(&discovery.token_endpoint, &form)?.json()?;
+
+    // Only RS256 is accepted, whatever the header says
+    let kid = token
+        .id_token
+        .split('.')
+        .next()
+        .and_then(|header| base64::decode_config(header, base64::URL_SAFE_NO_PAD).ok())
+        .and_then(|header| serde_json::from_slice::<serde_json::Value>(&header).ok())
+        .and_then(|header| {
+            header
+                .get("kid")
+                .and_then(|kid| kid.as_str().map(String::from))
+        });
+    let jwks: Jwks = http_client::get(&discovery.jwks_uri)?.json()?;
+    let jwk = jwks
+        .keys
+        .iter()
+        .filter(|jwk| jwk.kty == "RSA")
+        .find(|jwk| kid.is_none() || jwk.kid == kid)
+        .ok_or(anyhow!("No key of the provider to verify the ID token"))?;
+    let (n, e) = match (&jwk.n, &jwk.e) {
+        (Some(n), Some(e)) => (
+            base64::decode_config(n, base64::URL_SAFE_NO_PAD)?,
+            base64::decode_config(e, base64::URL_SAFE_NO_PAD)?,
+        ),
+        _ => return Err(anyhow!("Invalid key of the provider")),
+    };
+    let public_key = Rsa::from_public_components(BigNum::from_slice(&n)?, BigNum::from_slice(&e)?)?
+        .public_key_to_pem()?;
+
+    let (_, claims) = decode(
+        &token.id_token,
+        &public_key,
+        Algorithm::RS256,
+        &ValidationOptions::default(),
+    )?;
+    let claims: IdTokenClaims = serde_json::from_value(claims)?;
+
+    if claims.iss.trim_end_matches('/') != provider.issuer {
+        return Err(anyhow!("ID token is issued by another issuer"));
+    }
+    if !claims.aud.contains(&provider.client_id) {
+        return Err(anyhow!("ID token is issued for another client"));
+    }
+    if claims.nonce.as_ref() != Some(&pending.nonce) {
+        return Err(anyhow!("ID token nonce mismatch"));
+    }
+
+    Ok(claims)
+}
+
+/// Username for a new user, made of allowed characters and not taken yet.
+fn unique_username(conn: &mut PgConnection, claims: &IdTokenClaims) -> anyhow::Result<String> {
+    let base = claims
+        .preferred_username
+        .as_deref()
+        .or_else(|| {
+            claims
+                .email
+                .as_deref()
+                .and_then(|email| email.split('@').next())
+        })
+        .unwrap_or("user");
+    let mut base: String = base
+        .chars()
+        .filter(|c| c.is_alphanumeric() || "_-.".contains(*c))
+        .take(24)
+        .collect();
+    if base.is_empty() {
+        base = "user".to_string();
+    }
+
+    let mut username = base.clone();
+    for _ in 0..5 {
+        let taken: i64 = user::table
+            .filter(user::username.eq(&username))
+            .count()
+            .get_result(conn)?;
+        if taken == 0 {
+            return Ok(username);
+        }
+        username = format!("{}_{:04}", &base, rand::thread_rng().gen_range(0, 10000));
+    }
+    Err(anyhow!("Failed to find an available username"))
+}
+
+/// Sign up a user for the external identity, linking them together.
+fn signup_identity(
+    conn: &mut PgConnection,
+    provider: &str,
+    claims: &IdTokenClaims,
+) -> anyhow::Result<User> {
+    let username = unique_username(conn, claims)?;
+    let nickname: String = claims
+        .name
+        .as_deref()
+        .unwrap_or(&username)
+        .trim()
+        .chars()
+        .take(31)
+        .collect();
+    let email = match claims.email_verified {
+        Some(true) => claims.email.clone().unwrap_or_default(),
+        _ => String::new(),
+    };
+
+    conn.transaction(|conn| {
+        // Unusable password as in Django, so that only the provider logs the user in
+        let usr: User = diesel::insert_into(user::table)
+            .values((
+                user::username.eq(&username),
+                user::nickname.eq(if nickname.is_empty() {
+                    &username
+                } else {
+                    &nickname
+                }),
+                user::password.eq(format!("!{}", gen_secret())),
+                user::email.eq(&email),
+                // Verified by the provider already
+                user::email_verified.eq(if email.is_empty() {
+                    None
+                } else {
+                    Some(Utc::now())
+                }),
+            ))
+            .get_result(conn)?;
+        link_identity(conn, usr.id, provider, claims)?;
+        Ok(usr)
+    })
+}
+
+fn link_identity(
+    conn: &mut PgConnection,
+    user_id: ID,
+    provider: &str,
+    claims: &IdTokenClaims,
+) -> QueryResult<usize> {
+    diesel::insert_into(user_identity::table)
+        .values((
+            user_identity::user_id.eq(user_id),
+            user_identity::provider.eq(provider),
+            user_identity::subject.eq(&claims.sub),
+            user_identity::email.eq(claims.email.as_deref().unwrap_or_default()),
+        ))
+        .execute(conn)
+}
+
+#[derive(Deserialize)]
+pub struct OidcCallbackQuery {
+    code: Option<String>,
+    state: String,
+    error: Option<String>,
+}
+
+/// Log in the user of a validated ID token, signing them up on their first login.
+pub async fn oidc_callback(
+    provider: web::Path<String>,
+    query: web::Query<OidcCallbackQuery>,
+    ctx: web::Data<GlobalCtx>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let ip_addr = client_ip(&req);
+    let ip_addr = ip_addr.as_deref();
+
+    // Consume the state, so that the callback is accepted only once
+    let pending = PENDING
+        .lock()
+        .unwrap()
+        .remove(&query.state)
+        .filter(|pending| pending.expires > Utc::now() && pending.provider == *provider);
+    let pending = match pending {
+        Some(pending) => pending,
+        None => return error_response::<LoginResponse, _>("Login expired. Please login again."),
+    };
+    if let Some(error) = query.error.as_ref() {
+        return error_response::<LoginResponse, _>(format!("Login failed: {}", error));
+    }
+    let code = match query.code.clone() {
+        Some(code) => code,
+        None => return error_response::<LoginResponse, _>("Authorization code is required"),
+    };
+    let provider = match Provider::from_env(&provider) {
+        Ok(provider) => provider,
+        Err(error) => return error_response::<LoginResponse, _>(format!("{}", error)),
+    };
+
+    let cookie = pending.cookie;
+    let (provider, claims) = match web::block(move || {
+        let claims = authenticate(&provider, &pending, &code)?;
+        Ok::<_, anyhow::Error>((provider, claims))
+    })
+    .await
+    {
+        Ok(Ok(result)) => result,
+        Ok(Err(error)) => {
+            info!(
+                "({}) /oidc/callback: Auth failed: {}",
+                ip_addr.unwrap_or_default(),
+                error
+            );
+            return error_response::<LoginResponse, _>(format!("{}", error));
+        }
+        Err(error) => return error_response::<LoginResponse, _>(format!("{}", error)),
+    };
+
+    let result = ctx.get_conn().and_then(|mut conn| {
+        let linked: Option<ID> = user_identity::table
+            .filter(user_identity::provider.eq(&provider.name))
+            .filter(user_identity::subject.eq(&claims.sub))
+            .select(user_identity::user_id)
+            .first(&mut conn)
+            .optional()?;
+        let usr: User = match linked {
+            Some(user_id) => user::table.filter(user::id.eq(user_id)).first(&mut conn)?,
+            None => {
+                // There is no way to enter an invite code along the provider
+                if invite_code::invite_only() {
+                    return Err(anyhow!("Signup is by invitation only. Please sign up with an invite code first."));
+                }
+                // New accounts count towards signups from the IP, as in `/signup`
+                if let Some(ip_addr) = ip_addr {
+                    if let Some(lockout) = throttle::check(&[(ThrottleKind::SignupIp, ip_addr)])
+                    {
+                        return Ok(Err(lockout));
+                    }
+                    throttle::record(ThrottleKind::SignupIp, ip_addr);
+                }
+                let usr = signup_identity(&mut conn, &provider.name, &claims)?;
+                info!(
+                    "({}) /oidc/callback: Signup via {}: User<{}:{}>",
+                    ip_addr.unwrap_or_default(),
+                    &provider.name,
+                    &usr.id,
+                    &usr.nickname
+                );
+                usr
+            }
+        };
+
+        if !usr.is_active {
+            return Err(anyhow!("User is not activated by administrator. Contact the administrator for more details."));
+        }
+        if active_suspension_scopes(&mut conn, usr.id)?
+            .iter()
+            .any(|scope| scope.covers(SuspensionScope::Full))
+        {
+            return Err(anyhow!(
+                "User is suspended. Contact the administrator for more details."
+            ));
+        }
+
+        let now = Utc::now();
+        diesel::update(user_identity::table)
+            .filter(user_identity::provider.eq(&provider.name))
+            .filter(user_identity::subject.eq(&claims.sub))
+            .set(user_identity::last_login.eq(Some(now)))
+            .execute(&mut conn)?;
+        diesel::update(&usr)
+            .set(user::last_login.eq(Some(now)))
+            .execute(&mut conn)?;
+
+        let two_factor_enabled = two_factor::is_enabled(&mut conn, usr.id)?;
+        Ok(Ok((usr, two_factor_enabled)))
+    });
+    let (usr, two_factor_enabled) = match result {
+        Ok(Ok(result)) => result,
+        Ok(Err(lockout)) => return lockout_response::<LoginResponse>(&lockout),
+        Err(error) => return error_response::<LoginResponse, _>(format!("{}", error)),
+    };
+
+    if two_factor_enabled {
+        info!(
+            "({}) /oidc/callback: Two-factor challenge: User<{}:{}>",
+            ip_addr.unwrap_or_default(),
+            &usr.id,
+            &usr.nickname
+        );
+        let username = usr.username.clone();
+        return two_factor_response(&usr, &username, cookie);
+    }
+
+    // Logging
+    info!(
+        "({}) /oidc/callback: User<{}:{}> via {}",
+        ip_addr.unwrap_or_default(),
+        &usr.id,
+        &usr.nickname,
+        &provider.name
+    );
+
+    start_login(&ctx, usr, cookie)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, HttpServer};
+    use frank_jwt::encode;
+    use std::net::TcpListener;
+
+    const CLIENT_ID: &str = "cindy";
+    const CODE: &str = "code";
+
+    struct MockIssuer {
+        issuer: String,
+        private_key: Vec<u8>,
+        jwks: serde_json::Value,
+        nonce: String,
+    }
+
+    async fn discovery(issuer: web::Data<MockIssuer>, req: HttpRequest) -> HttpResponse {
+        // The port is part of the host of the issuer
+        let host = req
+            .headers()
+            .get(header::HOST)
+            .and_then(|host| host.to_str().ok());
+        if host.map(|host| format!("http://{}", host)).as_ref() != Some(&issuer.issuer) {
+            return HttpResponse::BadRequest().finish();
+        }
+        HttpResponse::Ok().json(json!({
+            "issuer": &issuer.issuer,
+            "authorization_endpoint": format!("{}/authorize", &issuer.issuer),
+            "token_endpoint": format!("{}/token", &issuer.issuer),
+            "jwks_uri": format!("{}/jwks", &issuer.issuer),
+        }))
+    }
+
+    async fn jwks(issuer: web::Data<MockIssuer>) -> HttpResponse {
+        HttpResponse::Ok().json(&issuer.jwks)
+    }
+
+    async fn token(
+        issuer: web::Data<MockIssuer>,
+        form: web::Form<HashMap<String, String>>,
+    ) -> HttpResponse {
+        if form.get("code").map(String::as_str) != Some(CODE) || form.get("code_verifier").is_none()
+        {
+            return HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" }));
+        }
+        let id_token = encode(
+            json!({ "kid": "test" }),
+            &issuer.private_key,
+            &json!({
+                "iss": &issuer.issuer,
+                "sub": "alice",
+                "aud": CLIENT_ID,
+                "exp": (Utc::now() + Duration::minutes(5)).timestamp(),
+                "nonce": &issuer.nonce,
+                "email": "alice@example.com",
+                "email_verified": true,
+            }),
+            Algorithm::RS256,
+        )
+        .unwrap();
+        // Sent chunked, as streamed bodies are
+        HttpResponse::Ok().streaming(futures::stream::once(async move {
+            Ok::<_, actix_web::Error>(web::Bytes::from(
+                json!({ "id_token": id_token, "token_type": "Bearer" }).to_string(),
+            ))
+        }))
+    }
+
+    /// Serve a provider at a random local port, issuing ID tokens with the nonce.
+    fn mock_issuer(nonce: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+        let rsa = Rsa::generate(2048).unwrap();
+        let data = web::Data::new(MockIssuer {
+            issuer: issuer.clone(),
+            private_key: rsa.private_key_to_pem().unwrap(),
+            jwks: json!({
+                "keys": [{
+                    "kty": "RSA",
+                    "kid": "test",
+                    "n": base64_url(&rsa.n().to_vec()),
+                    "e": base64_url(&rsa.e().to_vec()),
+                }]
+            }),
+            nonce: nonce.to_string(),
+        });
+
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .route(
+                    "/.well-known/openid-configuration",
+                    web::get().to(discovery),
+                )
+                .route("/jwks", web::get().to(jwks))
+                .route("/token", web::post().to(token))
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+
+        issuer
+    }
+
+    fn provider(issuer: &str) -> Provider {
+        Provider {
+            name: "mock".to_string(),
+            issuer: issuer.to_string(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: String::new(),
+            redirect_uri: "http://localhost:8000/oidc/mock/callback".to_string(),
+            scopes: "openid email".to_string(),
+        }
+    }
+
+    fn pending(nonce: &str) -> PendingLogin {
+        PendingLogin {
+            provider: "mock".to_string(),
+            nonce: nonce.to_string(),
+            verifier: gen_secret(),
+            cookie: false,
+            expires: Utc::now() + pending_max_age(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn authenticate_with_mock_issuer() {
+        let issuer = mock_issuer("nonce");
+        let claims = web::block(move || authenticate(&provider(&issuer), &pending("nonce"), CODE))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(claims.sub, "alice");
+        assert_eq!(claims.email.as_deref(), Some("alice@example.com"));
+        assert_eq!(claims.email_verified, Some(true));
+    }
+
+    #[actix_web::test]
+    async fn authenticate_rejects_nonce_mismatch() {
+        let issuer = mock_issuer("nonce");
+        let result =
+            web::block(move || authenticate(&provider(&issuer), &pending("replayed"), CODE))
+                .await
+                .unwrap();
+
+        assert!(result.is_err());
+    }
+
+    #[actix_web::test]
+    async fn authenticate_rejects_invalid_code() {
+        let issuer = mock_issuer("nonce");
+        let result = web::block(move || authenticate(&provider(&issuer), &pending("nonce"), "bad"))
+            .await
+            .unwrap();
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,238 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::context::{GlobalCtx, RequestCtx};
+use crate::mailer::Mail;
+use crate::models::{Timestamptz, User};
+use crate::schema::{password_reset_token, user};
+
+use super::access_token::revoke_user_access_tokens;
+use super::cookie::request_token;
+use super::session::{digest_secret, gen_secret, revoke_user_sessions};
+use super::{error_response, validate_password, AuthResponse};
+
+#[derive(Serialize, Default)]
+pub struct PasswordResponse {
+    error: Option<String>,
+    data: Option<PasswordResponseData>,
+}
+
+impl AuthResponse for PasswordResponse {
+    type Data = PasswordResponseData;
+    fn data(&mut self, data: Self::Data) -> &mut Self {
+        self.data = Some(data);
+        self
+    }
+    fn error(&mut self, error: String) -> &mut Self {
+        self.error = Some(error);
+        self
+    }
+}
+
+#[derive(Serialize, Default)]
+pub struct PasswordResponseData {
+    ok: bool,
+}
+
+fn password_reset_max_age() -> chrono::Duration {
+    chrono::Duration::minutes(
+        dotenv::var("PASSWORD_RESET_MAX_AGE")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(60),
+    )
+}
+
+fn ok_response() -> Result<HttpResponse> {
+    Ok(
+        HttpResponse::Ok()
+            .json(PasswordResponse::default().data(PasswordResponseData { ok: true })),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordBody {
+    old_password: String,
+    new_password: String,
+}
+
+/// Change the password of the logged in user, logging out the other sessions.
+///
+/// Personal access tokens are revoked as well, since they may have been created by whoever
+/// knew the old password.
+pub async fn change_password(
+    item: web::Json<ChangePasswordBody>,
+    ctx: web::Data<GlobalCtx>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    // Authorization info
+    let token = match request_token(&req) {
+        Ok(token) => token.map(|(token, _)| token),
+        Err(error) => return error_response::<PasswordResponse, _>(error),
+    };
+    let reqctx = RequestCtx::default().with_token(token, &ctx);
+    // Personal access tokens are not tied to a session, and cannot change the password
+    let (user_id, session_id) = match (reqctx.get_user_id(), reqctx.get_session_id()) {
+        (Some(user_id), Some(session_id)) => (user_id, session_id),
+        _ => return error_response::<PasswordResponse, _>("Not logged in"),
+    };
+
+    if let Err(error) = validate_password(&item.new_password) {
+        return error_response::<PasswordResponse, _>(error);
+    }
+
+    let result = ctx.get_conn().and_then(|mut conn| {
+        let usr: User = user::table.filter(user::id.eq(user_id)).first(&mut conn)?;
+        usr.verify_password(&item.old_password)?;
+
+        conn.transaction::<_, anyhow::Error, _>(|conn| {
+            diesel::update(&usr)
+                .set(user::password.eq(User::derive_credential(&item.new_password)))
+                .execute(conn)?;
+            revoke_user_sessions(conn, usr.id, Some(session_id))?;
+            revoke_user_access_tokens(conn, usr.id)?;
+            Ok(())
+        })?;
+        Ok(usr)
+    });
+    let usr = match result {
+        Ok(usr) => usr,
+        Err(error) => return error_response::<PasswordResponse, _>(format!("{}", error)),
+    };
+
+    info!("/change_password: User<{}:{}>", &usr.id, &usr.nickname);
+
+    ok_response()
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordBody {
+    username: Option<String>,
+    email: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = password_reset_token)]
+struct CreatePasswordResetTokenData {
+    user_id: i32,
+    token: String,
+    expires: Timestamptz,
+}
+
+/// Mail a single-use token to reset the password.
+///
+/// Responds the same whether the user exists or not, so that usernames and emails are
+/// not revealed.
+pub async fn reset_password(
+    item: web::Json<ResetPasswordBody>,
+    ctx: web::Data<GlobalCtx>,
+) -> Result<HttpResponse> {
+    let mut query = user::table
+        .filter(user::is_active.eq(true))
+        .filter(user::email.ne(""))
+        .into_boxed();
+    match (&item.username, &item.email) {
+        (Some(username), _) => query = query.filter(user::username.eq(username.trim())),
+        (None, Some(email)) => query = query.filter(user::email.eq(email.trim())),
+        (None, None) => {
+            return error_response::<PasswordResponse, _>("Username or email is required")
+        }
+    }
+
+    let result = ctx.get_conn().and_then(|mut conn| {
+        let usr: Option<User> = query.first(&mut conn).optional()?;
+        let usr = match usr {
+            Some(usr) => usr,
+            None => return Ok(None),
+        };
+
+        let secret = gen_secret();
+        diesel::insert_into(password_reset_token::table)
+            .values(&CreatePasswordResetTokenData {
+                user_id: usr.id,
+                token: digest_secret(&secret),
+                expires: Utc::now() + password_reset_max_age(),
+            })
+            .execute(&mut conn)?;
+
+        Ok(Some((usr, secret)))
+    });
+    let (usr, secret) = match result {
+        Ok(Some(result)) => result,
+        Ok(None) => return ok_response(),
+        Err(error) => return error_response::<PasswordResponse, _>(format!("{}", error)),
+    };
+
+    let link = dotenv::var("PASSWORD_RESET_URL")
+        .map(|url| format!("{}{}", url, secret))
+        .unwrap_or(secret);
+    let mail = Mail {
+        to: usr.email.clone(),
+        subject: "Reset your password".to_string(),
+        body: format!(
+            "Hi {},\n\nUse the following to reset your password within {} minutes:\n\n{}\n\nIf you did not request it, just ignore this mail.",
+            &usr.nickname,
+            password_reset_max_age().num_minutes(),
+            link
+        ),
+    };
+    // Answered the same as unknown users, not to tell which users have a mailbox
+    if let Err(error) = ctx.get_mailer().send(&mail) {
+        error!("Error sending mail to User<{}>: {:?}", &usr.id, error);
+        return ok_response();
+    }
+
+    info!("/reset_password: User<{}:{}>", &usr.id, &usr.nickname);
+
+    ok_response()
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmResetPasswordBody {
+    token: String,
+    new_password: String,
+}
+
+/// Set a new password with the mailed token, logging out all sessions and revoking personal
+/// access tokens.
+pub async fn confirm_reset_password(
+    item: web::Json<ConfirmResetPasswordBody>,
+    ctx: web::Data<GlobalCtx>,
+) -> Result<HttpResponse> {
+    if let Err(error) = validate_password(&item.new_password) {
+        return error_response::<PasswordResponse, _>(error);
+    }
+
+    let result = ctx.get_conn().and_then(|mut conn| {
+        conn.transaction::<_, anyhow::Error, _>(|conn| {
+            // Consume the token, so that it can be used only once
+            let user_id: i32 = diesel::update(password_reset_token::table)
+                .filter(password_reset_token::token.eq(digest_secret(item.token.trim())))
+                .filter(password_reset_token::used.is_null())
+                .filter(password_reset_token::expires.gt(Utc::now()))
+                .set(password_reset_token::used.eq(Some(Utc::now())))
+                .returning(password_reset_token::user_id)
+                .get_result(conn)
+                .optional()?
+                .ok_or(anyhow::anyhow!("Invalid or expired token"))?;
+
+            diesel::update(user::table.filter(user::id.eq(user_id)))
+                .set(user::password.eq(User::derive_credential(&item.new_password)))
+                .execute(conn)?;
+            revoke_user_sessions(conn, user_id, None)?;
+            revoke_user_access_tokens(conn, user_id)?;
+
+            Ok(user_id)
+        })
+    });
+    let user_id = match result {
+        Ok(user_id) => user_id,
+        Err(error) => return error_response::<PasswordResponse, _>(format!("{}", error)),
+    };
+
+    info!("/reset_password/confirm: User<{}>", user_id);
+
+    ok_response()
+}
@@ -0,0 +1,147 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::context::GlobalCtx;
+use crate::models::permission::user_permissions;
+use crate::models::user_suspension::{active_suspension_scopes, SuspensionScope};
+use crate::models::User;
+
+use super::cookie::{self, session_cookies, set_cookies};
+use super::session::{revoke_session, rotate_session};
+use super::two_factor::may_assume_role;
+use super::{client_ip, error_response, get_jwt, AuthResponse, Role};
+
+#[derive(Deserialize)]
+pub struct RefreshBody {
+    /// Taken from the cookie in cookie mode
+    refresh_token: Option<String>,
+    /// Role of the new access token, `User` if not allowed
+    role: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct RefreshResponse {
+    error: Option<String>,
+    data: Option<RefreshResponseData>,
+}
+
+impl AuthResponse for RefreshResponse {
+    type Data = RefreshResponseData;
+    fn data(&mut self, data: Self::Data) -> &mut Self {
+        self.data = Some(data);
+        self
+    }
+    fn error(&mut self, error: String) -> &mut Self {
+        self.error = Some(error);
+        self
+    }
+}
+
+#[derive(Serialize)]
+pub struct RefreshResponseData {
+    id: i32,
+    username: String,
+    /// Left out in cookie mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auth_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
+}
+
+pub async fn refresh(
+    item: web::Json<RefreshBody>,
+    ctx: web::Data<GlobalCtx>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    use crate::schema::user;
+
+    let ip_addr = client_ip(&req);
+    let ip_addr = ip_addr.as_deref();
+
+    let (refresh_token, cookie) = match item.refresh_token.as_ref() {
+        Some(refresh_token) => (refresh_token.clone(), false),
+        None => match cookie::refresh_token(&req) {
+            Ok(refresh_token) => (refresh_token, true),
+            Err(error) => return error_response::<RefreshResponse, _>(error),
+        },
+    };
+
+    let mut conn = match ctx.get_conn() {
+        Ok(conn) => conn,
+        Err(error) => return error_response::<RefreshResponse, _>(format!("{}", error)),
+    };
+    let (session, refresh_token) = match rotate_session(&mut conn, &refresh_token) {
+        Ok(result) => result,
+        Err(error) => {
+            info!("({}) /refresh: {}", ip_addr.unwrap_or_default(), error);
+            return error_response::<RefreshResponse, _>(format!("{}", error));
+        }
+    };
+
+    // Reload the user, so that deactivation and demotion take effect
+    let result = user::table
+        .filter(user::id.eq(session.user_id))
+        .first::<User>(&mut conn)
+        .map_err(anyhow::Error::from)
+        .and_then(|user| {
+            let suspended = active_suspension_scopes(&mut conn, user.id)?
+                .iter()
+                .any(|scope| scope.covers(SuspensionScope::Full));
+            let permissions = user_permissions(&mut conn, user.id)?;
+            Ok((user, suspended, permissions))
+        });
+    let (user, permissions) = match result {
+        Ok((user, false, permissions)) if user.is_active => (user, permissions),
+        Ok(_) => {
+            if let Err(error) = revoke_session(&mut conn, session.id) {
+                error!("Error revoking session: {:?}", error);
+            }
+            return error_response::<RefreshResponse, _>(
+                "User is not active. Contact the administrator for more details.",
+            );
+        }
+        Err(error) => return error_response::<RefreshResponse, _>(format!("{}", error)),
+    };
+
+    // Logging
+    info!(
+        "({}) /refresh: User<{}:{}>",
+        ip_addr.unwrap_or_default(),
+        &user.id,
+        &user.nickname
+    );
+
+    // Roles requiring two-factor authentication fall back to `User`, as disallowed roles do
+    let role = match item.role.as_deref().map(Role::from) {
+        Some(role) => match may_assume_role(&mut conn, user.id, role) {
+            Ok(true) => Some(role),
+            Ok(false) => None,
+            Err(error) => return error_response::<RefreshResponse, _>(format!("{}", error)),
+        },
+        None => None,
+    };
+    let jwt = get_jwt(&user, role, &permissions, session.id);
+
+    if cookie {
+        return Ok(
+            set_cookies(&mut HttpResponse::Ok(), session_cookies(jwt, refresh_token)).json(
+                RefreshResponse::default().data(RefreshResponseData {
+                    id: user.id,
+                    username: user.username,
+                    auth_token: None,
+                    refresh_token: None,
+                }),
+            ),
+        );
+    }
+
+    Ok(
+        HttpResponse::Ok().json(RefreshResponse::default().data(RefreshResponseData {
+            id: user.id,
+            username: user.username,
+            auth_token: Some(jwt),
+            refresh_token: Some(refresh_token),
+        })),
+    )
+}
@@ -1,9 +1,16 @@
 use actix_web::{web, HttpRequest, HttpResponse, Result};
+use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::context::RequestCtx;
+use crate::context::{GlobalCtx, RequestCtx};
+use crate::models::permission::user_permissions;
+use crate::models::user_suspension::{active_suspension_scopes, SuspensionScope};
+use crate::models::User;
+use crate::schema::user;
 
-use super::{AuthResponse, Role};
+use super::cookie::{jwt_cookie, request_token};
+use super::two_factor::may_assume_role;
+use super::{client_ip, error_response, get_jwt, AuthResponse, Role};
 
 #[derive(Deserialize)]
 pub struct RoleBody {
@@ -30,35 +37,31 @@ impl AuthResponse for RoleResponse {
 
 #[derive(Serialize)]
 pub struct RoleResponseData {
-    auth_token: String,
+    /// Left out in cookie mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auth_token: Option<String>,
 }
 
-pub async fn role_switch(item: web::Json<RoleBody>, req: HttpRequest) -> Result<HttpResponse> {
-    let headers = req.headers();
-    let connection_info = req.connection_info();
-    let ip_addr = if let Some(header_real_ip) = dotenv::var("HEADER_REAL_IP").ok() {
-        headers
-            .get(header_real_ip)
-            .and_then(|ip| ip.to_str().ok())
-            .or_else(|| connection_info.peer_addr())
-    } else {
-        connection_info.peer_addr()
-    };
+pub async fn role_switch(
+    item: web::Json<RoleBody>,
+    global_ctx: web::Data<GlobalCtx>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let ip_addr = client_ip(&req);
+    let ip_addr = ip_addr.as_deref();
 
     // Authorization info
-    let token = headers.get("Authorization").and_then(|value| {
-        value
-            .to_str()
-            .ok()
-            // Drop `Bearer `
-            .and_then(|v| v.splitn(2, ' ').nth(1))
-            .map(|v| v.to_string())
-    });
-    let admin_secret = headers
+    let (token, cookie) = match request_token(&req) {
+        Ok(Some((token, cookie))) => (Some(token), cookie),
+        Ok(None) => (None, false),
+        Err(error) => return error_response::<RoleResponse, _>(error),
+    };
+    let admin_secret = req
+        .headers()
         .get("X-CINDY-ADMIN-SECRET")
         .and_then(|value| value.to_str().map(|v| v.to_owned()).ok());
     let ctx = RequestCtx::default()
-        .with_token(token)
+        .with_token(token, &global_ctx)
         .with_secret(admin_secret);
     let user = ctx.get_user();
     let role = ctx.get_role();
@@ -80,9 +83,56 @@ pub async fn role_switch(item: web::Json<RoleBody>, req: HttpRequest) -> Result<
         );
     }
 
-    let jwt = ctx.switch_role(Role::from(item.role.as_ref()))?;
+    // Personal access tokens are not tied to a session, and cannot switch roles
+    if ctx.get_token_scopes().is_some() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Cannot switch role with an access token",
+        ));
+    }
+    let session_id = ctx
+        .get_session_id()
+        .ok_or(actix_web::error::ErrorUnauthorized("Not logged in"))?;
+    let user_id = ctx
+        .get_user_id()
+        .ok_or(actix_web::error::ErrorUnauthorized("Not logged in"))?;
+
+    // Reload the user as `/refresh` does, so that deactivation and demotion take effect
+    let new_role = Role::from(item.role.as_ref());
+    let result = global_ctx.get_conn().and_then(|mut conn| {
+        let user: User = user::table.filter(user::id.eq(user_id)).first(&mut conn)?;
+        let suspended = active_suspension_scopes(&mut conn, user.id)?
+            .iter()
+            .any(|scope| scope.covers(SuspensionScope::Full));
+        if !user.is_active || suspended {
+            return Err(anyhow::anyhow!(
+                "User is not active. Contact the administrator for more details."
+            ));
+        }
+        if !may_assume_role(&mut conn, user.id, new_role)? {
+            return Err(anyhow::anyhow!(
+                "Two-factor authentication is required for {}",
+                new_role
+            ));
+        }
+        let permissions = user_permissions(&mut conn, user.id)?;
+        Ok((user, permissions))
+    });
+    let (user, permissions) = match result {
+        Ok(result) => result,
+        Err(error) => return error_response::<RoleResponse, _>(format!("{}", error)),
+    };
+
+    let jwt = get_jwt(&user, Some(new_role), &permissions, session_id);
+
+    if cookie {
+        return Ok(HttpResponse::Ok()
+            .cookie(jwt_cookie(jwt))
+            .json(RoleResponse::default().data(RoleResponseData { auth_token: None })));
+    }
 
-    Ok(HttpResponse::Ok()
-        //.cookie(gen_cookie(&user))
-        .json(RoleResponse::default().data(RoleResponseData { auth_token: jwt })))
+    Ok(
+        HttpResponse::Ok().json(RoleResponse::default().data(RoleResponseData {
+            auth_token: Some(jwt),
+        })),
+    )
 }
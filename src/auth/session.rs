@@ -0,0 +1,161 @@
+use actix_web::cookie::time::Duration;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use diesel::prelude::*;
+use rand::{distributions::Alphanumeric, Rng};
+use ring::digest;
+use uuid::Uuid;
+
+use crate::models::{Timestamptz, ID};
+use crate::schema::auth_session;
+
+const SECRET_LEN: usize = 48;
+
+/// A login session holding the current refresh token of the user.
+///
+/// Refresh tokens are handed out as `<session id>.<secret>`, with only the digest
+/// of the secret stored. Each refresh rotates the secret; presenting a rotated
+/// secret again revokes the whole session.
+#[derive(Queryable, Identifiable, Clone, Debug)]
+#[diesel(table_name = auth_session)]
+pub struct Session {
+    pub id: Uuid,
+    pub user_id: ID,
+    pub refresh_token: String,
+    pub created: Timestamptz,
+    pub refreshed: Timestamptz,
+    pub expires: Timestamptz,
+    pub revoked: Option<Timestamptz>,
+}
+
+/// Lifetime of a session since it is last refreshed.
+pub fn session_max_age() -> Duration {
+    Duration::days(
+        dotenv::var("LOGIN_MAX_AGE")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(30),
+    )
+}
+
+fn session_expires() -> Timestamptz {
+    Utc::now() + chrono::Duration::seconds(session_max_age().whole_seconds())
+}
+
+pub(super) fn gen_secret() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(SECRET_LEN)
+        .collect()
+}
+
+pub(super) fn digest_secret(secret: &str) -> String {
+    base64::encode(digest::digest(&digest::SHA256, secret.as_bytes()).as_ref())
+}
+
+/// Split a refresh token into the session id and the secret.
+fn parse_refresh_token(token: &str) -> Result<(Uuid, &str)> {
+    let mut parts = token.splitn(2, '.');
+    let id = parts
+        .next()
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or(anyhow!("Invalid refresh token"))?;
+    let secret = parts.next().ok_or(anyhow!("Invalid refresh token"))?;
+    Ok((id, secret))
+}
+
+/// Start a session for the user, returning it along with its refresh token.
+pub fn start_session(conn: &mut PgConnection, user_id: ID) -> Result<(Session, String)> {
+    let secret = gen_secret();
+    let session: Session = diesel::insert_into(auth_session::table)
+        .values((
+            auth_session::user_id.eq(user_id),
+            auth_session::refresh_token.eq(digest_secret(&secret)),
+            auth_session::expires.eq(session_expires()),
+        ))
+        .get_result(conn)?;
+    let refresh_token = format!("{}.{}", session.id, secret);
+
+    Ok((session, refresh_token))
+}
+
+/// Look up the session of a refresh token, which should be the latest one issued.
+pub fn find_session(conn: &mut PgConnection, token: &str) -> Result<Session> {
+    let (id, secret) = parse_refresh_token(token)?;
+    let session: Session = auth_session::table
+        .filter(auth_session::id.eq(id))
+        .first(conn)
+        .optional()?
+        .ok_or(anyhow!("Invalid refresh token"))?;
+
+    if session.revoked.is_some() || session.expires <= Utc::now() {
+        return Err(anyhow!("Session expired. Please login again."));
+    }
+    if session.refresh_token != digest_secret(secret) {
+        // A rotated token is replayed, which is likely stolen
+        revoke_session(conn, session.id)?;
+        return Err(anyhow!("Session expired. Please login again."));
+    }
+
+    Ok(session)
+}
+
+/// Rotate the refresh token of the session, extending its expiry.
+pub fn rotate_session(conn: &mut PgConnection, token: &str) -> Result<(Session, String)> {
+    let session = find_session(conn, token)?;
+    let secret = gen_secret();
+
+    // Only one of concurrent refreshes with the same token wins
+    let session: Session = diesel::update(auth_session::table)
+        .filter(auth_session::id.eq(session.id))
+        .filter(auth_session::refresh_token.eq(&session.refresh_token))
+        .filter(auth_session::revoked.is_null())
+        .set((
+            auth_session::refresh_token.eq(digest_secret(&secret)),
+            auth_session::refreshed.eq(Utc::now()),
+            auth_session::expires.eq(session_expires()),
+        ))
+        .get_result(conn)
+        .optional()?
+        .ok_or(anyhow!("Session expired. Please login again."))?;
+    let refresh_token = format!("{}.{}", session.id, secret);
+
+    Ok((session, refresh_token))
+}
+
+pub fn revoke_session(conn: &mut PgConnection, id: Uuid) -> QueryResult<usize> {
+    diesel::update(auth_session::table)
+        .filter(auth_session::id.eq(id))
+        .filter(auth_session::revoked.is_null())
+        .set(auth_session::revoked.eq(Some(Utc::now())))
+        .execute(conn)
+}
+
+/// Log out all sessions of the user, optionally keeping the current one.
+pub fn revoke_user_sessions(
+    conn: &mut PgConnection,
+    user_id: ID,
+    except: Option<Uuid>,
+) -> QueryResult<usize> {
+    let mut query = diesel::update(auth_session::table)
+        .filter(auth_session::user_id.eq(user_id))
+        .filter(auth_session::revoked.is_null())
+        .into_boxed();
+    if let Some(except) = except {
+        query = query.filter(auth_session::id.ne(except));
+    }
+    query
+        .set(auth_session::revoked.eq(Some(Utc::now())))
+        .execute(conn)
+}
+
+/// Whether access tokens issued for the session are still accepted.
+pub fn is_session_active(conn: &mut PgConnection, id: Uuid) -> QueryResult<bool> {
+    let count: i64 = auth_session::table
+        .filter(auth_session::id.eq(id))
+        .filter(auth_session::revoked.is_null())
+        .filter(auth_session::expires.gt(Utc::now()))
+        .count()
+        .get_result(conn)?;
+    Ok(count > 0)
+}
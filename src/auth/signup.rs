@@ -1,23 +1,45 @@
 use actix_web::{web, HttpRequest, HttpResponse, Result};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 use crate::context::GlobalCtx;
 use crate::models::User;
 
-use super::{error_response, get_jwt, AuthResponse};
+use super::cookie::{csrf_cookie, session_cookies, set_cookies};
+use super::email::{send_verification, validate_email};
+use super::invite_code::{consume_invite_code, invite_only};
+use super::session::start_session;
+use super::throttle::{self, ThrottleKind};
+use super::{
+    client_ip, error_response, get_jwt, lockout_response, validate_password, AuthResponse,
+};
 
 #[derive(Deserialize)]
 pub struct SignupBody {
     nickname: String,
     username: String,
     password: String,
+    /// Optional, mailed a token to verify it
+    #[serde(default)]
+    email: Option<String>,
+    /// Required when signup is invite-only
+    #[serde(default)]
+    invite_code: Option<String>,
+    /// Solution of the challenge from `/signup/challenge`
+    #[serde(default)]
+    challenge_response: Option<String>,
+    /// Keep the tokens in HttpOnly cookies instead of the response
+    #[serde(default)]
+    cookie: bool,
 }
 
 #[derive(Serialize, Default)]
 pub struct SignupResponse {
     error: Option<String>,
     data: Option<SignupResponseData>,
+    /// Seconds to wait when locked out for too many signups
+    retry_after: Option<i64>,
 }
 
 impl AuthResponse for SignupResponse {
@@ -30,13 +52,21 @@ impl AuthResponse for SignupResponse {
         self.error = Some(error);
         self
     }
+    fn retry_after(&mut self, seconds: i64) -> &mut Self {
+        self.retry_after = Some(seconds);
+        self
+    }
 }
 
 #[derive(Serialize)]
 pub struct SignupResponseData {
     id: i32,
     username: String,
-    auth_token: String,
+    /// Left out in cookie mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    auth_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
 }
 
 pub async fn signup(
@@ -46,9 +76,17 @@ pub async fn signup(
 ) -> Result<HttpResponse> {
     use crate::schema::user;
 
+    let ip_addr = client_ip(&req);
+    let ip_addr = ip_addr.as_deref();
+    if let Some(lockout) = ip_addr.and_then(|ip| throttle::check(&[(ThrottleKind::SignupIp, ip)])) {
+        warn!("({}) /signup: Locked out", ip_addr.unwrap_or_default());
+        return lockout_response::<SignupResponse>(&lockout);
+    }
+
     let username = item.username.trim();
     let nickname = item.nickname.trim();
     let password = &item.password;
+    let email = item.email.as_deref().unwrap_or_default().trim();
 
     if username.is_empty() {
         return error_response::<SignupResponse, _>("Username cannot be blank!");
@@ -56,8 +94,13 @@ pub async fn signup(
     if nickname.is_empty() {
         return error_response::<SignupResponse, _>("Nickname cannot be blank!");
     }
-    if password.is_empty() {
-        return error_response::<SignupResponse, _>("Password cannot be blank!");
+    if let Err(error) = validate_password(password) {
+        return error_response::<SignupResponse, _>(error);
+    }
+    if !email.is_empty() {
+        if let Err(error) = validate_email(email) {
+            return error_response::<SignupResponse, _>(error);
+        }
     }
 
     if username.len() >= 32 {
@@ -66,8 +109,31 @@ pub async fn signup(
     if nickname.len() >= 32 {
         return error_response::<SignupResponse, _>("Nickname should be at most 32 characters");
     }
-    if password.len() < 6 {
-        return error_response::<SignupResponse, _>("Password must be at least 6 characters long");
+    let invite_code = item.invite_code.as_deref().unwrap_or_default().trim();
+    if invite_only() && invite_code.is_empty() {
+        return error_response::<SignupResponse, _>("Invite code is required");
+    }
+
+    // Anti-spam challenge, which may call out to a CAPTCHA service
+    let challenge = ctx.get_signup_challenge();
+    let response = item.challenge_response.clone().unwrap_or_default();
+    let remote_ip = ip_addr.map(String::from);
+    match web::block(move || challenge.verify(&response, remote_ip.as_deref())).await {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => {
+            info!(
+                "({}) /signup: Challenge failed: {}",
+                ip_addr.unwrap_or_default(),
+                error
+            );
+            return error_response::<SignupResponse, _>(format!("{}", error));
+        }
+        Err(error) => return error_response::<SignupResponse, _>(format!("{}", error)),
+    }
+
+    // Count attempts reaching the database, so that usernames are not probed either
+    if let Some(ip_addr) = ip_addr {
+        throttle::record(ThrottleKind::SignupIp, ip_addr);
     }
 
     let mut conn = ctx.get_conn().expect("Error getting connection");
@@ -75,13 +141,19 @@ pub async fn signup(
     // Sign up the user
     let credential = User::derive_credential(password);
 
-    let user_query = diesel::insert_into(user::table)
-        .values((
-            user::username.eq(&username),
-            user::nickname.eq(&nickname),
-            user::password.eq(&credential),
-        ))
-        .get_results::<User>(&mut conn);
+    let user_query = conn.transaction::<_, anyhow::Error, _>(|conn| {
+        if invite_only() {
+            consume_invite_code(conn, invite_code)?;
+        }
+        Ok(diesel::insert_into(user::table)
+            .values((
+                user::username.eq(&username),
+                user::nickname.eq(&nickname),
+                user::password.eq(&credential),
+                user::email.eq(&email),
+            ))
+            .get_results::<User>(conn)?)
+    });
 
     let mut usr = match user_query {
         Ok(usr) => usr,
@@ -109,16 +181,6 @@ pub async fn signup(
     };
 
     // Logging
-    let headers = req.headers();
-    let connection_info = req.connection_info();
-    let ip_addr = if let Some(header_real_ip) = dotenv::var("HEADER_REAL_IP").ok() {
-        headers
-            .get(header_real_ip)
-            .and_then(|ip| ip.to_str().ok())
-            .or_else(|| connection_info.peer_addr())
-    } else {
-        connection_info.peer_addr()
-    };
     info!(
         "({}) /signup: User<{}:{}>",
         ip_addr.unwrap_or_default(),
@@ -126,13 +188,82 @@ pub async fn signup(
         &usr.nickname
     );
 
-    let jwt = get_jwt(&usr, None);
+    let (session, refresh_token) = match start_session(&mut conn, usr.id) {
+        Ok(result) => result,
+        Err(error) => return error_response::<SignupResponse, _>(format!("{}", error)),
+    };
+    if !usr.email.is_empty() {
+        // The user can ask for another mail later, so signing up goes on
+        if let Err(error) = send_verification(&mut conn, ctx.get_mailer(), &usr) {
+            error!("Error sending mail to User<{}>: {:?}", &usr.id, error);
+        }
+    }
+
+    let jwt = get_jwt(&usr, None, &[], session.id);
+
+    if item.cookie {
+        let mut cookies = session_cookies(jwt, refresh_token);
+        cookies.push(csrf_cookie());
+        return Ok(set_cookies(&mut HttpResponse::Ok(), cookies).json(
+            SignupResponse::default().data(SignupResponseData {
+                id: usr.id,
+                username: usr.username,
+                auth_token: None,
+                refresh_token: None,
+            }),
+        ));
+    }
 
-    Ok(HttpResponse::Ok()
-        //.cookie(gen_cookie(&usr))
-        .json(SignupResponse::default().data(SignupResponseData {
+    Ok(
+        HttpResponse::Ok().json(SignupResponse::default().data(SignupResponseData {
             id: usr.id,
             username: usr.username,
-            auth_token: jwt,
-        })))
+            auth_token: Some(jwt),
+            refresh_token: Some(refresh_token),
+        })),
+    )
+}
+
+#[derive(Serialize, Default)]
+pub struct SignupChallengeResponse {
+    error: Option<String>,
+    data: Option<SignupChallengeResponseData>,
+}
+
+impl AuthResponse for SignupChallengeResponse {
+    type Data = SignupChallengeResponseData;
+    fn data(&mut self, data: Self::Data) -> &mut Self {
+        self.data = Some(data);
+        self
+    }
+    fn error(&mut self, error: String) -> &mut Self {
+        self.error = Some(error);
+        self
+    }
+}
+
+#[derive(Serialize)]
+pub struct SignupChallengeResponseData {
+    kind: &'static str,
+    params: Value,
+    invite_only: bool,
+}
+
+/// Challenge to solve before signing up, and whether an invite code is required.
+pub async fn signup_challenge(ctx: web::Data<GlobalCtx>) -> Result<HttpResponse> {
+    let challenge = ctx.get_signup_challenge();
+    let params = match challenge.issue() {
+        Ok(params) => params,
+        Err(error) => return error_response::<SignupChallengeResponse, _>(format!("{}", error)),
+    };
+
+    Ok(
+        HttpResponse::Ok().json(SignupChallengeResponse::default().data(
+            SignupChallengeResponseData {
+                kind: challenge.kind(),
+                params,
+                invite_only: invite_only(),
+            },
+        )),
+    )
 }
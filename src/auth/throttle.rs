@@ -0,0 +1,208 @@
+use async_graphql::{Enum, SimpleObject};
+use chrono::{Duration, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use crate::models::Timestamptz;
+
+/// What attempts are counted by
+#[derive(Enum, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+pub enum ThrottleKind {
+    /// Failed logins from an IP address
+    LoginIp,
+    /// Failed logins to a username
+    LoginUsername,
+    /// Signups from an IP address
+    SignupIp,
+}
+
+impl ThrottleKind {
+    /// Max attempts in the window before locking out
+    fn max_attempts(&self) -> usize {
+        let (var, default) = match self {
+            ThrottleKind::LoginIp => ("LOGIN_MAX_FAILURES_PER_IP", 20),
+            ThrottleKind::LoginUsername => ("LOGIN_MAX_FAILURES_PER_USERNAME", 5),
+            ThrottleKind::SignupIp => ("SIGNUP_MAX_PER_IP", 5),
+        };
+        dotenv::var(var)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(default)
+    }
+
+    /// Sliding window the attempts are counted in
+    fn window(&self) -> Duration {
+        let (var, default) = match self {
+            ThrottleKind::LoginIp | ThrottleKind::LoginUsername => ("LOGIN_THROTTLE_WINDOW", 15),
+            ThrottleKind::SignupIp => ("SIGNUP_THROTTLE_WINDOW", 60),
+        };
+        Duration::minutes(
+            dotenv::var(var)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(default),
+        )
+    }
+}
+
+/// Duration of the n-th successive lockout, doubled each time up to `LOGIN_LOCKOUT_MAX`.
+fn lockout_duration(n: u32) -> Duration {
+    let base: i64 = dotenv::var("LOGIN_LOCKOUT")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(60);
+    let max: i64 = dotenv::var("LOGIN_LOCKOUT_MAX")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(24 * 60 * 60);
+    let factor = 1i64
+        .checked_shl(n.saturating_sub(1).min(32))
+        .unwrap_or(i64::MAX);
+    Duration::seconds(base.saturating_mul(factor).min(max))
+}
+
+type Key = (ThrottleKind, String);
+
+#[derive(Default)]
+struct Attempts {
+    attempts: VecDeque<Timestamptz>,
+    lockouts: u32,
+    locked_until: Option<Timestamptz>,
+}
+
+lazy_static! {
+    static ref ATTEMPTS: Mutex<HashMap<Key, Attempts>> = Default::default();
+}
+
+/// A key being locked out
+#[derive(SimpleObject, Clone, Debug)]
+pub struct Lockout {
+    pub kind: ThrottleKind,
+    pub key: String,
+    pub locked_until: Timestamptz,
+    /// Successive lockouts of the key, doubling the duration each time
+    pub lockouts: u32,
+}
+
+impl Lockout {
+    /// Seconds before the next attempt is accepted
+    pub fn retry_after(&self) -> i64 {
+        (self.locked_until - Utc::now()).num_seconds().max(1)
+    }
+}
+
+fn normalize(kind: ThrottleKind, key: &str) -> Key {
+    (kind, key.trim().to_lowercase())
+}
+
+/// Returns the lockout of the first key being locked out, if any.
+pub fn check(keys: &[(ThrottleKind, &str)]) -> Option<Lockout> {
+    let map = ATTEMPTS.lock().unwrap();
+    let now = Utc::now();
+    keys.iter().find_map(|(kind, key)| {
+        let key = normalize(*kind, key);
+        map.get(&key)
+            .and_then(|attempts| {
+                attempts
+                    .locked_until
+                    .map(|until| (attempts.lockouts, until))
+            })
+            .filter(|(_, until)| *until > now)
+            .map(|(lockouts, locked_until)| Lockout {
+                kind: key.0,
+                key: key.1,
+                locked_until,
+                lockouts,
+            })
+    })
+}
+
+/// Count an attempt, locking the key out when it exceeds the limit of its kind.
+pub fn record(kind: ThrottleKind, key: &str) -> Option<Lockout> {
+    let mut map = ATTEMPTS.lock().unwrap();
+    let now = Utc::now();
+    let key = normalize(kind, key);
+    let attempts = map.entry(key.clone()).or_default();
+
+    attempts.attempts.push_back(now);
+    while let Some(first) = attempts.attempts.front() {
+        if *first <= now - kind.window() {
+            attempts.attempts.pop_front();
+        } else {
+            break;
+        }
+    }
+
+    if attempts.attempts.len() >= kind.max_attempts() {
+        attempts.attempts.clear();
+        attempts.lockouts += 1;
+        let locked_until = now + lockout_duration(attempts.lockouts);
+        attempts.locked_until = Some(locked_until);
+        warn!(
+            "Locked out {:?} '{}' until {} ({} time(s))",
+            kind, &key.1, locked_until, attempts.lockouts
+        );
+        Some(Lockout {
+            kind,
+            key: key.1,
+            locked_until,
+            lockouts: attempts.lockouts,
+        })
+    } else {
+        None
+    }
+}
+
+/// Forget the attempts of the key, e.g. after a successful login.
+pub fn reset(kind: ThrottleKind, key: &str) {
+    let mut map = ATTEMPTS.lock().unwrap();
+    map.remove(&normalize(kind, key));
+}
+
+/// Keys being locked out now.
+pub fn lockouts() -> Vec<Lockout> {
+    let map = ATTEMPTS.lock().unwrap();
+    let now = Utc::now();
+    map.iter()
+        .filter_map(|((kind, key), attempts)| {
+            attempts
+                .locked_until
+                .filter(|until| *until > now)
+                .map(|locked_until| Lockout {
+                    kind: *kind,
+                    key: key.clone(),
+                    locked_until,
+                    lockouts: attempts.lockouts,
+                })
+        })
+        .collect()
+}
+
+/// Lift the lockout of the key. Returns whether the key is being locked out.
+pub fn lift(kind: ThrottleKind, key: &str) -> bool {
+    let mut map = ATTEMPTS.lock().unwrap();
+    let now = Utc::now();
+    map.remove(&normalize(kind, key))
+        .and_then(|attempts| attempts.locked_until)
+        .map(|until| until > now)
+        .unwrap_or(false)
+}
+
+/// Drop keys without recent attempts, whose backoff has cooled down.
+pub fn cleanup() {
+    let mut map = ATTEMPTS.lock().unwrap();
+    let now = Utc::now();
+    let cool_down = lockout_duration(u32::MAX);
+    map.retain(|(kind, _), attempts| {
+        let recent = attempts
+            .attempts
+            .back()
+            .map(|last| *last > now - kind.window())
+            .unwrap_or(false);
+        let cooling = attempts
+            .locked_until
+            .map(|until| until > now - cool_down)
+            .unwrap_or(false);
+        recent || cooling
+    });
+}
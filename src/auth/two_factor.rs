@@ -0,0 +1,437 @@
+use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use rand::{distributions::Alphanumeric, Rng};
+use ring::hmac;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::models::{Timestamptz, ID};
+use crate::schema::{user_totp, user_totp_backup_code};
+
+use super::session::{digest_secret, gen_secret};
+use super::Role;
+
+const SECRET_BYTES: usize = 20;
+const TOTP_DIGITS: u32 = 6;
+/// Seconds of each time step
+const TOTP_PERIOD: i64 = 30;
+/// Time steps accepted before and after the current one, for clock drift
+const TOTP_SKEW: i64 = 1;
+const BACKUP_CODE_COUNT: usize = 10;
+const BACKUP_CODE_LEN: usize = 10;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// TOTP (RFC 6238) secret of a user enrolled in two-factor authentication.
+///
+/// The enrollment only takes effect once confirmed with a code from the device.
+#[derive(Queryable, Identifiable, Clone, Debug)]
+#[diesel(table_name = user_totp, primary_key(user_id))]
+pub struct UserTotp {
+    pub user_id: ID,
+    pub secret: String,
+    pub created: Timestamptz,
+    pub confirmed: Option<Timestamptz>,
+    /// Latest time step accepted, so that a code cannot be replayed
+    pub last_step: i64,
+}
+
+fn base32_encode(data: &[u8]) -> String {
+    let mut encoded = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in data {
+        buffer = (buffer << 8 | *byte as u32) & 0xfff;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[(buffer >> bits & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[(buffer << (5 - bits) & 31) as usize] as char);
+    }
+    encoded
+}
+
+fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in encoded.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a as char == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5 | value as u32) & 0xfff;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((buffer >> bits & 0xff) as u8);
+        }
+    }
+    Some(data)
+}
+
+/// Percent-encode a component of the provisioning URI.
+fn uri_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// HOTP (RFC 4226) code of the counter.
+fn hotp(key: &[u8], counter: u64) -> u32 {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, key);
+    let tag = hmac::sign(&key, &counter.to_be_bytes());
+    let tag = tag.as_ref();
+    let offset = (tag[tag.len() - 1] & 0x0f) as usize;
+    let code = u32::from_be_bytes([
+        tag[offset] & 0x7f,
+        tag[offset + 1],
+        tag[offset + 2],
+        tag[offset + 3],
+    ]);
+    code % 10u32.pow(TOTP_DIGITS)
+}
+
+/// Time step around now matching the code, if any.
+fn totp_step(secret: &str, code: &str) -> Option<i64> {
+    totp_step_at(secret, code, Utc::now().timestamp())
+}
+
+/// Time step around the unix time matching the code, if any.
+fn totp_step_at(secret: &str, code: &str, time: i64) -> Option<i64> {
+    let key = base32_decode(secret)?;
+    if code.len() != TOTP_DIGITS as usize {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let now = time / TOTP_PERIOD;
+    (now - TOTP_SKEW..=now + TOTP_SKEW).find(|step| hotp(&key, *step as u64) == code)
+}
+
+fn totp_issuer() -> String {
+    dotenv::var("TOTP_ISSUER").unwrap_or("Cindy".to_string())
+}
+
+/// `otpauth://` URI to be scanned by authenticator apps.
+fn provisioning_uri(username: &str, secret: &str) -> String {
+    let issuer = totp_issuer();
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        uri_encode(&issuer),
+        uri_encode(username),
+        secret,
+        uri_encode(&issuer),
+        TOTP_DIGITS,
+        TOTP_PERIOD
+    )
+}
+
+/// Whether the user has confirmed two-factor authentication.
+pub fn is_enabled(conn: &mut PgConnection, user_id: ID) -> QueryResult<bool> {
+    let count: i64 = user_totp::table
+        .filter(user_totp::user_id.eq(user_id))
+        .filter(user_totp::confirmed.is_not_null())
+        .count()
+        .get_result(conn)?;
+    Ok(count > 0)
+}
+
+/// Whether Staff requires two-factor authentication, set with `STAFF_REQUIRE_TWO_FACTOR`.
+pub fn staff_requires_two_factor() -> bool {
+    dotenv::var("STAFF_REQUIRE_TWO_FACTOR")
+        .map(|s| s == "true" || s == "1")
+        .unwrap_or(false)
+}
+
+/// Whether the user may switch into the role with their two-factor setup.
+pub fn may_assume_role(conn: &mut PgConnection, user_id: ID, role: Role) -> QueryResult<bool> {
+    if role == Role::Staff && staff_requires_two_factor() {
+        is_enabled(conn, user_id)
+    } else {
+        Ok(true)
+    }
+}
+
+/// Start enrolling the user with a new secret, returning it with its provisioning URI.
+///
+/// Any unconfirmed enrollment is replaced.
+pub fn enroll(conn: &mut PgConnection, user_id: ID, username: &str) -> Result<(String, String)> {
+    if is_enabled(conn, user_id)? {
+        return Err(anyhow!("Two-factor authentication is already enabled"));
+    }
+
+    let secret = base32_encode(&rand::thread_rng().gen::<[u8; SECRET_BYTES]>());
+    diesel::insert_into(user_totp::table)
+        .values((
+            user_totp::user_id.eq(user_id),
+            user_totp::secret.eq(&secret),
+        ))
+        .on_conflict(user_totp::user_id)
+        .do_update()
+        .set((
+            user_totp::secret.eq(&secret),
+            user_totp::created.eq(Utc::now()),
+            user_totp::last_step.eq(0),
+        ))
+        .execute(conn)?;
+
+    let uri = provisioning_uri(username, &secret);
+    Ok((secret, uri))
+}
+
+/// Confirm the enrollment with a code from the device, returning fresh backup codes.
+pub fn confirm(conn: &mut PgConnection, user_id: ID, code: &str) -> Result<Vec<String>> {
+    let totp: UserTotp = user_totp::table
+        .filter(user_totp::user_id.eq(user_id))
+        .filter(user_totp::confirmed.is_null())
+        .first(conn)
+        .optional()?
+        .ok_or(anyhow!("Two-factor authentication is not being enrolled"))?;
+    let step = totp_step(&totp.secret, code.trim()).ok_or(anyhow!("Invalid code"))?;
+
+    conn.transaction(|conn| {
+        diesel::update(&totp)
+            .set((
+                user_totp::confirmed.eq(Some(Utc::now())),
+                user_totp::last_step.eq(step),
+            ))
+            .execute(conn)?;
+        regenerate_backup_codes(conn, user_id)
+    })
+}
+
+/// Check a TOTP or backup code of a user with two-factor authentication enabled.
+///
+/// Each code is accepted only once.
+pub fn verify(conn: &mut PgConnection, user_id: ID, code: &str) -> Result<bool> {
+    let code = code.trim();
+    let totp: UserTotp = user_totp::table
+        .filter(user_totp::user_id.eq(user_id))
+        .filter(user_totp::confirmed.is_not_null())
+        .first(conn)
+        .optional()?
+        .ok_or(anyhow!("Two-factor authentication is not enabled"))?;
+
+    if let Some(step) = totp_step(&totp.secret, code) {
+        // Only one of concurrent logins with the same code wins
+        let updated = diesel::update(&totp)
+            .filter(user_totp::last_step.lt(step))
+            .set(user_totp::last_step.eq(step))
+            .execute(conn)?;
+        return Ok(updated > 0);
+    }
+
+    let used = diesel::update(user_totp_backup_code::table)
+        .filter(user_totp_backup_code::user_id.eq(user_id))
+        .filter(user_totp_backup_code::code.eq(digest_secret(&code.to_lowercase())))
+        .filter(user_totp_backup_code::used.is_null())
+        .set(user_totp_backup_code::used.eq(Some(Utc::now())))
+        .execute(conn)?;
+    Ok(used > 0)
+}
+
+/// Replace the backup codes of the user, returning the new ones.
+pub fn regenerate_backup_codes(conn: &mut PgConnection, user_id: ID) -> Result<Vec<String>> {
+    let codes: Vec<String> = (0..BACKUP_CODE_COUNT)
+        .map(|_| {
+            rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(BACKUP_CODE_LEN)
+                .collect::<String>()
+                .to_lowercase()
+        })
+        .collect();
+
+    diesel::delete(user_totp_backup_code::table)
+        .filter(user_totp_backup_code::user_id.eq(user_id))
+        .execute(conn)?;
+    diesel::insert_into(user_totp_backup_code::table)
+        .values(
+            codes
+                .iter()
+                .map(|code| {
+                    (
+                        user_totp_backup_code::user_id.eq(user_id),
+                        user_totp_backup_code::code.eq(digest_secret(code)),
+                    )
+                })
+                .collect::<Vec<_>>(),
+        )
+        .execute(conn)?;
+
+    Ok(codes)
+}
+
+/// Number of backup codes left unused.
+pub fn remaining_backup_codes(conn: &mut PgConnection, user_id: ID) -> QueryResult<i64> {
+    user_totp_backup_code::table
+        .filter(user_totp_backup_code::user_id.eq(user_id))
+        .filter(user_totp_backup_code::used.is_null())
+        .count()
+        .get_result(conn)
+}
+
+/// Turn off two-factor authentication of the user. Returns whether it was enrolled.
+pub fn disable(conn: &mut PgConnection, user_id: ID) -> QueryResult<bool> {
+    conn.transaction(|conn| {
+        diesel::delete(user_totp_backup_code::table)
+            .filter(user_totp_backup_code::user_id.eq(user_id))
+            .execute(conn)?;
+        let deleted = diesel::delete(user_totp::table)
+            .filter(user_totp::user_id.eq(user_id))
+            .execute(conn)?;
+        Ok(deleted > 0)
+    })
+}
+
+/// A login waiting for the second factor after the password is accepted.
+struct Challenge {
+    user_id: ID,
+    username: String,
+    /// Whether the login asks for cookie mode
+    cookie: bool,
+    expires: Timestamptz,
+}
+
+lazy_static! {
+    static ref CHALLENGES: Mutex<HashMap<String, Challenge>> = Default::default();
+}
+
+fn challenge_max_age() -> Duration {
+    Duration::minutes(
+        dotenv::var("TWO_FACTOR_CHALLENGE_MAX_AGE")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(5),
+    )
+}
+
+/// Start a login challenge for the user, returning its token.
+pub fn start_challenge(user_id: ID, username: &str, cookie: bool) -> String {
+    let token = gen_secret();
+    let mut map = CHALLENGES.lock().unwrap();
+    map.insert(
+        digest_secret(&token),
+        Challenge {
+            user_id,
+            username: username.to_string(),
+            cookie,
+            expires: Utc::now() + challenge_max_age(),
+        },
+    );
+    token
+}
+
+/// User id, username and cookie mode of a pending login challenge.
+pub fn find_challenge(token: &str) -> Option<(ID, String, bool)> {
+    let map = CHALLENGES.lock().unwrap();
+    map.get(&digest_secret(token))
+        .filter(|challenge| challenge.expires > Utc::now())
+        .map(|challenge| {
+            (
+                challenge.user_id,
+                challenge.username.clone(),
+                challenge.cookie,
+            )
+        })
+}
+
+/// Drop a login challenge once passed.
+pub fn finish_challenge(token: &str) {
+    let mut map = CHALLENGES.lock().unwrap();
+    map.remove(&digest_secret(token));
+}
+
+/// Drop expired login challenges.
+pub fn cleanup() {
+    let mut map = CHALLENGES.lock().unwrap();
+    let now = Utc::now();
+    map.retain(|_, challenge| challenge.expires > now);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Secret of the test vectors in RFC 4226 and RFC 6238
+    const RFC_KEY: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn hotp_rfc4226_vectors() {
+        let expected = [
+            755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871, 520489,
+        ];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(hotp(RFC_KEY, counter as u64), *code, "counter {}", counter);
+        }
+    }
+
+    #[test]
+    fn totp_rfc6238_vectors() {
+        // Last 6 digits of the SHA-1 codes in RFC 6238, Appendix B
+        let secret = base32_encode(RFC_KEY);
+        let expected = [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ];
+        for (time, code) in expected {
+            assert_eq!(
+                totp_step_at(&secret, code, time),
+                Some(time / TOTP_PERIOD),
+                "time {}",
+                time
+            );
+        }
+    }
+
+    #[test]
+    fn totp_accepts_skew_only() {
+        let secret = base32_encode(RFC_KEY);
+        assert_eq!(
+            totp_step_at(&secret, "287082", 59 + TOTP_PERIOD),
+            Some(59 / TOTP_PERIOD)
+        );
+        assert_eq!(totp_step_at(&secret, "287082", 59 + 2 * TOTP_PERIOD), None);
+        assert_eq!(totp_step_at(&secret, "28708", 59), None);
+        assert_eq!(totp_step_at(&secret, "abcdef", 59), None);
+    }
+
+    #[test]
+    fn base32_rfc4648_vectors() {
+        let expected = [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ];
+        for (data, encoded) in expected {
+            assert_eq!(base32_encode(data.as_bytes()), encoded);
+            assert_eq!(base32_decode(encoded).unwrap(), data.as_bytes());
+        }
+        // Padded, lowercase and spaced as typed from authenticator apps
+        assert_eq!(base32_decode("mzxw 6ytb oi======").unwrap(), b"foobar");
+        assert_eq!(base32_decode("MZXW1"), None);
+    }
+
+    #[test]
+    fn base32_round_trip() {
+        for len in 0..=SECRET_BYTES {
+            let data: Vec<u8> = (0..len).map(|i| (i * 37 + 11) as u8).collect();
+            assert_eq!(base32_decode(&base32_encode(&data)).unwrap(), data);
+        }
+    }
+}
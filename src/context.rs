@@ -1,27 +1,61 @@
 use anyhow::{Context, Result};
+use chrono::Utc;
 use diesel::pg::PgConnection;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
+use std::sync::{Arc, RwLock};
 
 use super::ADMIN_SECRET;
-use crate::auth::{parse_jwt, switch_jwt_role, JwtPayload, JwtPayloadUser, Role};
+use crate::auth::access_token::{authenticate_access_token, is_access_token};
+use crate::auth::challenge::{challenge_from_env, SignupChallenge};
+use crate::auth::session::is_session_active;
+use crate::auth::{parse_jwt, JwtPayload, JwtPayloadUser, Role};
 use crate::db::{establish_connection, DbPool};
+use crate::mailer::{mailer_from_env, Mailer};
+use crate::models::user_suspension::{active_suspension_scopes, SuspensionScope};
+use crate::models::TokenScope;
 
 #[derive(Clone)]
 pub struct GlobalCtx {
     pool: DbPool,
+    mailer: Arc<dyn Mailer>,
+    signup_challenge: Arc<dyn SignupChallenge>,
 }
 
 impl Default for GlobalCtx {
     fn default() -> Self {
         let pool = establish_connection();
 
-        Self { pool }
+        Self::new(pool)
     }
 }
 
 impl GlobalCtx {
     pub fn new(pool: DbPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            mailer: Arc::from(mailer_from_env()),
+            signup_challenge: Arc::from(challenge_from_env()),
+        }
+    }
+
+    /// Replace the mailer configured by env vars.
+    pub fn with_mailer(mut self, mailer: impl Mailer + 'static) -> Self {
+        self.mailer = Arc::new(mailer);
+        self
+    }
+
+    pub fn get_mailer(&self) -> &dyn Mailer {
+        self.mailer.as_ref()
+    }
+
+    /// Replace the signup challenge configured by env vars.
+    pub fn with_signup_challenge(mut self, challenge: impl SignupChallenge + 'static) -> Self {
+        self.signup_challenge = Arc::new(challenge);
+        self
+    }
+
+    pub fn get_signup_challenge(&self) -> Arc<dyn SignupChallenge> {
+        self.signup_challenge.clone()
     }
 
     pub fn get_conn(&self) -> Result<PooledConnection<ConnectionManager<PgConnection>>> {
@@ -37,17 +71,73 @@ impl GlobalCtx {
 pub struct RequestCtx {
     jwt_payload: Option<JwtPayload>,
     admin_secret: Option<String>,
+    suspensions: RwLock<Vec<SuspensionScope>>,
+    /// Personal access token, if authorized with one instead of a JWT
+    access_token: Option<String>,
+    /// Scopes of the personal access token
+    token_scopes: Option<Vec<TokenScope>>,
+    /// Authorized by the session cookie without the CSRF token, which keeps it from mutations
+    csrf_missing: bool,
+    /// Shared by all operations of a WebSocket connection
+    persistent: bool,
 }
 
 impl RequestCtx {
-    pub fn with_token(mut self, token: Option<String>) -> Self {
-        self.jwt_payload = token.and_then(|token| match parse_jwt(&token) {
-            Ok(jwt) => Some(jwt),
-            Err(error) => {
-                debug!("parse_jwt: {}", error);
-                None
+    /// Parse the access token, rejecting it if its session is revoked.
+    ///
+    /// Personal access tokens are accepted as well, limited to their scopes.
+    pub fn with_token(mut self, token: Option<String>, global_ctx: &GlobalCtx) -> Self {
+        if let Some(token) = token.as_ref().filter(|token| is_access_token(token)) {
+            match global_ctx
+                .get_conn()
+                .and_then(|mut conn| authenticate_access_token(&mut conn, token))
+            {
+                Ok((user, scopes)) => {
+                    self.jwt_payload = Some(JwtPayload::for_access_token(&user));
+                    self.access_token = Some(token.clone());
+                    self.token_scopes = Some(scopes);
+                }
+                Err(error) => debug!("authenticate_access_token: {}", error),
             }
-        });
+            return self;
+        }
+
+        self.jwt_payload = token
+            .and_then(|token| match parse_jwt(&token) {
+                Ok(jwt) => Some(jwt),
+                Err(error) => {
+                    debug!("parse_jwt: {}", error);
+                    None
+                }
+            })
+            .filter(|jwt| {
+                let session_id = match jwt.get_session_id() {
+                    Some(session_id) => session_id,
+                    None => return false,
+                };
+                match global_ctx
+                    .get_conn()
+                    .and_then(|mut conn| Ok(is_session_active(&mut conn, session_id)?))
+                {
+                    Ok(active) => active,
+                    Err(error) => {
+                        error!("Error checking session {}: {:?}", session_id, error);
+                        false
+                    }
+                }
+            });
+        self
+    }
+
+    /// Mark whether the token is taken from the session cookie without a matching CSRF token.
+    pub fn with_csrf_missing(mut self, csrf_missing: bool) -> Self {
+        self.csrf_missing = csrf_missing;
+        self
+    }
+
+    /// Mark the context as shared by the operations of a WebSocket connection.
+    pub fn with_persistent(mut self, persistent: bool) -> Self {
+        self.persistent = persistent;
         self
     }
 
@@ -56,9 +146,34 @@ impl RequestCtx {
         self
     }
 
+    /// Load suspensions in effect for the user of the token.
+    ///
+    /// Must be called after `with_token`, so that suspensions apply to tokens already issued.
+    pub fn with_suspensions(mut self, global_ctx: &GlobalCtx) -> Self {
+        if let Some(user_id) = self.get_user_id() {
+            *self.suspensions.get_mut().unwrap() = match global_ctx
+                .get_conn()
+                .and_then(|mut conn| Ok(active_suspension_scopes(&mut conn, user_id)?))
+            {
+                Ok(suspensions) => suspensions,
+                Err(error) => {
+                    error!(
+                        "Error loading suspensions of User<{}>: {:?}",
+                        user_id, error
+                    );
+                    vec![]
+                }
+            };
+        }
+        self
+    }
+
     pub fn get_role(&self) -> Role {
         if self.admin_secret.as_ref() == Some(&ADMIN_SECRET) {
             Role::Admin
+        } else if self.is_suspended(SuspensionScope::Full) {
+            // Fully suspended users are no more than guests
+            Role::Guest
         } else if let Some(jwt) = self.jwt_payload.as_ref() {
             *jwt.get_role()
         } else {
@@ -82,12 +197,101 @@ impl RequestCtx {
         self.jwt_payload.as_ref().map(|jwt| jwt.get_user_id())
     }
 
-    pub fn switch_role(&self, role: Role) -> actix_web::Result<String> {
-        Ok(switch_jwt_role(
-            self.jwt_payload
-                .as_ref()
-                .ok_or(actix_web::error::ErrorUnauthorized("Not logged in"))?,
-            role,
-        ))
+    pub fn get_session_id(&self) -> Option<uuid::Uuid> {
+        self.jwt_payload
+            .as_ref()
+            .and_then(|jwt| jwt.get_session_id())
+    }
+
+    /// Whether the user is granted the permission when the token is issued.
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.jwt_payload
+            .as_ref()
+            .map(|jwt| jwt.get_permissions().iter().any(|p| p == permission))
+            .unwrap_or(false)
+    }
+
+    /// Scopes of the personal access token, or `None` if authorized with a JWT.
+    pub fn get_token_scopes(&self) -> Option<&[TokenScope]> {
+        self.token_scopes.as_deref()
+    }
+
+    /// Check that the mutation, by its GraphQL field name, is allowed for how the request is
+    /// authorized.
+    pub fn check_mutation(&self, mutation: &str) -> Result<(), String> {
+        if self.csrf_missing {
+            return Err("Invalid CSRF token".to_string());
+        }
+        match self.token_scopes.as_ref() {
+            Some(scopes) if !scopes.iter().any(|scope| scope.allows(mutation)) => Err(format!(
+                "`{}` is out of the scopes of the access token",
+                mutation
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the user is under a suspension covering the scope.
+    pub fn is_suspended(&self, scope: SuspensionScope) -> bool {
+        self.suspensions
+            .read()
+            .unwrap()
+            .iter()
+            .any(|suspension| suspension.covers(scope))
+    }
+
+    /// Check that the token is still accepted before an operation of a persistent context,
+    /// and reload the suspensions of the user.
+    ///
+    /// The checks of `with_token` and `with_suspensions` are made once per connection, which
+    /// would otherwise let it outlive a logout, the token expiry or a new suspension.
+    pub fn revalidate(&self, global_ctx: &GlobalCtx) -> Result<(), String> {
+        let jwt = match self.jwt_payload.as_ref() {
+            Some(jwt) if self.persistent => jwt,
+            _ => return Ok(()),
+        };
+
+        if let Some(token) = self.access_token.as_ref() {
+            global_ctx
+                .get_conn()
+                .and_then(|mut conn| authenticate_access_token(&mut conn, token))
+                .map_err(|error| {
+                    debug!("authenticate_access_token: {}", error);
+                    "Invalid or expired access token".to_string()
+                })?;
+        } else {
+            if jwt
+                .get_exp()
+                .is_none_or(|exp| exp <= Utc::now().timestamp())
+            {
+                return Err("Access token expired".to_string());
+            }
+            let session_id = jwt.get_session_id().ok_or("Session revoked".to_string())?;
+            let active = global_ctx
+                .get_conn()
+                .and_then(|mut conn| Ok(is_session_active(&mut conn, session_id)?))
+                .map_err(|error| {
+                    error!("Error checking session {}: {:?}", session_id, error);
+                    "Error checking session".to_string()
+                })?;
+            if !active {
+                return Err("Session revoked".to_string());
+            }
+        }
+
+        let user_id = jwt.get_user_id();
+        let suspensions = global_ctx
+            .get_conn()
+            .and_then(|mut conn| Ok(active_suspension_scopes(&mut conn, user_id)?))
+            .map_err(|error| {
+                error!(
+                    "Error loading suspensions of User<{}>: {:?}",
+                    user_id, error
+                );
+                "Error loading suspensions".to_string()
+            })?;
+        *self.suspensions.write().unwrap() = suspensions;
+
+        Ok(())
     }
 }
@@ -0,0 +1,303 @@
+use async_graphql::{self, Context, Object};
+use chrono::Utc;
+use diesel::{
+    prelude::*,
+    sql_types::{Array, Int4, Jsonb},
+};
+use std::collections::BTreeMap;
+
+use crate::auth::Role;
+use crate::context::{GlobalCtx, RequestCtx};
+use crate::models::audit_log::*;
+use crate::models::*;
+use crate::schema::{django_admin_log, django_content_type};
+
+/// Columns never written to the audit log
+const REDACTED_FIELDS: &[&str] = &["password", "email", "email_verified"];
+/// Fields naming the object in `object_repr`, by priority
+const REPR_FIELDS: &[&str] = &["title", "name", "nickname", "username"];
+const MAX_REPR_LEN: usize = 200;
+
+#[derive(Default)]
+pub struct AuditLogQuery;
+
+#[derive(QueryableByName)]
+struct Snapshot {
+    #[diesel(sql_type = Int4)]
+    id: ID,
+    #[diesel(sql_type = Jsonb)]
+    snapshot: serde_json::Value,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = django_admin_log)]
+struct AuditLogData {
+    action_time: Timestamptz,
+    object_id: Option<String>,
+    object_repr: String,
+    action_flag: i16,
+    change_message: String,
+    content_type_id: Option<ID>,
+    user_id: ID,
+}
+
+/// Records changes made by staff and admins to `django_admin_log`.
+///
+/// Rows are snapshotted as json before and after the mutation, and the
+/// changed fields are written as the change message. Nothing is recorded for
+/// users, unless authorized by a permission granted to them, or for admins
+/// authorized by the admin secret alone.
+///
+/// Begin and record inside the transaction of the mutation, so that a change
+/// is never committed without its entry.
+pub(crate) struct Audit {
+    actor: Option<ID>,
+    table: &'static str,
+    before: BTreeMap<ID, serde_json::Value>,
+}
+
+impl Audit {
+    /// Start auditing the rows of `table` with the given ids.
+    ///
+    /// Pass no ids when the rows are to be created.
+    pub fn begin(
+        ctx: &Context<'_>,
+        conn: &mut PgConnection,
+        table: &'static str,
+        ids: &[ID],
+    ) -> async_graphql::Result<Self> {
+        Self::start(actor(ctx, None)?, conn, table, ids)
+    }
+
+    /// Start auditing a mutation that users may be authorized for by the permission, as
+    /// checked by `PermissionGuard`.
+    pub fn begin_permitted(
+        ctx: &Context<'_>,
+        conn: &mut PgConnection,
+        table: &'static str,
+        ids: &[ID],
+        permission: &str,
+    ) -> async_graphql::Result<Self> {
+        Self::start(actor(ctx, Some(permission))?, conn, table, ids)
+    }
+
+    fn start(
+        actor: Option<ID>,
+        conn: &mut PgConnection,
+        table: &'static str,
+        ids: &[ID],
+    ) -> async_graphql::Result<Self> {
+        let before = if actor.is_some() {
+            snapshots(conn, table, ids)?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Self {
+            actor,
+            table,
+            before,
+        })
+    }
+
+    /// Write an entry for each audited row, and for the rows with the given ids.
+    ///
+    /// Pass no ids when the rows are deleted.
+    pub fn record(
+        self,
+        conn: &mut PgConnection,
+        action: AuditAction,
+        ids: &[ID],
+    ) -> async_graphql::Result<()> {
+        let actor = match self.actor {
+            Some(actor) => actor,
+            None => return Ok(()),
+        };
+
+        let content_type_id: Option<ID> = django_content_type::table
+            .filter(django_content_type::model.eq(self.table))
+            .select(django_content_type::id)
+            .first(conn)
+            .optional()?;
+        let mut after = snapshots(conn, self.table, ids)?;
+        let mut before = self.before;
+
+        let mut object_ids: Vec<ID> = before.keys().chain(after.keys()).copied().collect();
+        object_ids.sort_unstable();
+        object_ids.dedup();
+
+        let now = Utc::now();
+        let entries: Vec<AuditLogData> = object_ids
+            .into_iter()
+            .map(|object_id| {
+                let old = before.remove(&object_id);
+                let new = after.remove(&object_id);
+                AuditLogData {
+                    action_time: now,
+                    object_id: Some(object_id.to_string()),
+                    object_repr: object_repr(self.table, object_id, new.as_ref().or(old.as_ref())),
+                    action_flag: action as i16,
+                    change_message: diff(old.as_ref(), new.as_ref()).to_string(),
+                    content_type_id,
+                    user_id: actor,
+                }
+            })
+            .collect();
+
+        diesel::insert_into(django_admin_log::table)
+            .values(&entries)
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Write an entry for a change outside of the tables, like lifting a login lockout.
+    ///
+    /// `change` is in the format of the change message, `{ field: { old, new } }`.
+    pub fn note(
+        ctx: &Context<'_>,
+        conn: &mut PgConnection,
+        action: AuditAction,
+        repr: &str,
+        change: serde_json::Value,
+    ) -> async_graphql::Result<()> {
+        let actor = match actor(ctx, None)? {
+            Some(actor) => actor,
+            None => return Ok(()),
+        };
+
+        diesel::insert_into(django_admin_log::table)
+            .values(&AuditLogData {
+                action_time: Utc::now(),
+                object_id: None,
+                object_repr: repr.chars().take(MAX_REPR_LEN).collect(),
+                action_flag: action as i16,
+                change_message: change.to_string(),
+                content_type_id: None,
+                user_id: actor,
+            })
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
+
+/// Staff, admin or user granted the permission to record changes of, if any.
+fn actor(ctx: &Context<'_>, permission: Option<&str>) -> async_graphql::Result<Option<ID>> {
+    let reqctx = ctx.data::<RequestCtx>()?;
+    Ok(match reqctx.get_role() {
+        Role::Staff | Role::Admin => reqctx.get_user_id(),
+        Role::User if permission.is_some_and(|p| reqctx.has_permission(p)) => reqctx.get_user_id(),
+        Role::User | Role::Guest => None,
+    })
+}
+
+/// Load rows of the table as json objects, keyed by id.
+fn snapshots(
+    conn: &mut PgConnection,
+    table: &'static str,
+    ids: &[ID],
+) -> QueryResult<BTreeMap<ID, serde_json::Value>> {
+    if ids.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+
+    let rows: Vec<Snapshot> = diesel::sql_query(format!(
+        r#"SELECT t.id, to_jsonb(t) AS snapshot FROM "{}" t WHERE t.id = ANY($1)"#,
+        table
+    ))
+    .bind::<Array<Int4>, _>(ids)
+    .load(conn)?;
+
+    Ok(rows
+        .into_iter()
+        .map(|mut row| {
+            if let Some(fields) = row.snapshot.as_object_mut() {
+                for field in REDACTED_FIELDS {
+                    fields.remove(*field);
+                }
+            }
+            (row.id, row.snapshot)
+        })
+        .collect())
+}
+
+/// Fields that differ between the snapshots, as `{ field: { old, new } }`.
+fn diff(old: Option<&serde_json::Value>, new: Option<&serde_json::Value>) -> serde_json::Value {
+    let empty = serde_json::Map::new();
+    let old = old.and_then(|v| v.as_object()).unwrap_or(&empty);
+    let new = new.and_then(|v| v.as_object()).unwrap_or(&empty);
+
+    let mut changes = serde_json::Map::new();
+    for field in old.keys().chain(new.keys()) {
+        if changes.contains_key(field) {
+            continue;
+        }
+        let (old_value, new_value) = (old.get(field), new.get(field));
+        if old_value != new_value {
+            let mut change = serde_json::Map::new();
+            if let Some(old_value) = old_value {
+                change.insert("old".to_string(), old_value.clone());
+            }
+            if let Some(new_value) = new_value {
+                change.insert("new".to_string(), new_value.clone());
+            }
+            changes.insert(field.clone(), serde_json::Value::Object(change));
+        }
+    }
+
+    serde_json::Value::Object(changes)
+}
+
+fn object_repr(table: &str, id: ID, snapshot: Option<&serde_json::Value>) -> String {
+    let name = snapshot.and_then(|snapshot| {
+        REPR_FIELDS
+            .iter()
+            .find_map(|field| snapshot.get(*field).and_then(|v| v.as_str()))
+    });
+    let repr = match name {
+        Some(name) => format!("{} #{}: {}", table, id, name),
+        None => format!("{} #{}", table, id),
+    };
+    repr.chars().take(MAX_REPR_LEN).collect()
+}
+
+#[Object]
+impl AuditLogQuery {
+    /// Changes made by staff and admins, latest first unless ordered otherwise.
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn audit_log(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        filter: Option<Vec<AuditLogFilter>>,
+        order: Option<Vec<AuditLogOrder>>,
+    ) -> async_graphql::Result<Vec<AuditLog>> {
+        use crate::schema::django_admin_log::dsl::*;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let mut query = django_admin_log.into_boxed();
+        if let Some(order) = order {
+            query = AuditLogOrders::new(order).apply_order(query);
+        } else {
+            query = query.order(action_time.desc());
+        }
+        if let Some(filter) = filter {
+            if let Some(filter_exp) = filter.as_expression() {
+                query = query.filter(filter_exp)
+            }
+        }
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+        if let Some(offset) = offset {
+            query = query.offset(offset);
+        }
+
+        let audit_logs = query.load::<AuditLog>(&mut conn)?;
+
+        Ok(audit_logs)
+    }
+}
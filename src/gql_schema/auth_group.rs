@@ -0,0 +1,292 @@
+use async_graphql::{self, Context, Object};
+use diesel::prelude::*;
+
+use super::Audit;
+use crate::context::GlobalCtx;
+use crate::models::permission::*;
+use crate::models::*;
+use crate::schema::{
+    auth_group, auth_group_permissions, sui_hei_user_groups, sui_hei_user_user_permissions,
+};
+
+#[derive(Default)]
+pub struct AuthGroupQuery;
+#[derive(Default)]
+pub struct AuthGroupMutation;
+
+#[Object]
+impl AuthGroupQuery {
+    #[graphql(guard = "AdminRoleGuard::default()")]
+    pub async fn auth_group(&self, ctx: &Context<'_>, id: i32) -> async_graphql::Result<AuthGroup> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let auth_group = auth_group::table
+            .filter(auth_group::id.eq(id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(auth_group)
+    }
+
+    #[graphql(guard = "AdminRoleGuard::default()")]
+    pub async fn auth_groups(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<AuthGroup>> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let auth_groups = auth_group::table
+            .order(auth_group::name.asc())
+            .load(&mut conn)?;
+
+        Ok(auth_groups)
+    }
+
+    /// All permissions available to groups and users
+    #[graphql(guard = "AdminRoleGuard::default()")]
+    pub async fn permissions(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Permission>> {
+        use crate::schema::{auth_permission, django_content_type};
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let permissions = auth_permission::table
+            .inner_join(django_content_type::table)
+            .order((
+                django_content_type::model.asc(),
+                auth_permission::codename.asc(),
+            ))
+            .select(PERMISSION_COLUMNS)
+            .load(&mut conn)?;
+
+        Ok(permissions)
+    }
+}
+
+#[Object]
+impl AuthGroupMutation {
+    #[graphql(guard = "AdminRoleGuard::default()")]
+    pub async fn create_auth_group(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+    ) -> async_graphql::Result<AuthGroup> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let auth_group = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "auth_group", &[])?;
+
+            let auth_group: AuthGroup = diesel::insert_into(auth_group::table)
+                .values(auth_group::name.eq(name))
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Addition, &[auth_group.id])?;
+            Ok(auth_group)
+        })?;
+
+        Ok(auth_group)
+    }
+
+    #[graphql(guard = "AdminRoleGuard::default()")]
+    pub async fn update_auth_group(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        name: String,
+    ) -> async_graphql::Result<AuthGroup> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let auth_group = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "auth_group", &[id])?;
+
+            let auth_group: AuthGroup = diesel::update(auth_group::table)
+                .filter(auth_group::id.eq(id))
+                .set(auth_group::name.eq(name))
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Change, &[auth_group.id])?;
+            Ok(auth_group)
+        })?;
+
+        Ok(auth_group)
+    }
+
+    // Delete group along with its members and permissions
+    #[graphql(guard = "AdminRoleGuard::default()")]
+    pub async fn delete_auth_group(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> async_graphql::Result<AuthGroup> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let auth_group = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "auth_group", &[id])?;
+
+            diesel::delete(
+                auth_group_permissions::table.filter(auth_group_permissions::group_id.eq(id)),
+            )
+            .execute(conn)?;
+            diesel::delete(sui_hei_user_groups::table.filter(sui_hei_user_groups::group_id.eq(id)))
+                .execute(conn)?;
+            let auth_group =
+                diesel::delete(auth_group::table.filter(auth_group::id.eq(id))).get_result(conn)?;
+
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok(auth_group)
+        })?;
+
+        Ok(auth_group)
+    }
+
+    /// Grant a permission like `chatmessage.delete` to the group.
+    #[graphql(guard = "AdminRoleGuard::default()")]
+    pub async fn grant_group_permission(
+        &self,
+        ctx: &Context<'_>,
+        group_id: ID,
+        permission: String,
+    ) -> async_graphql::Result<AuthGroup> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let permission = Permission::find(&mut conn, &permission)?;
+        diesel::insert_into(auth_group_permissions::table)
+            .values((
+                auth_group_permissions::group_id.eq(group_id),
+                auth_group_permissions::permission_id.eq(permission.id),
+            ))
+            .on_conflict((
+                auth_group_permissions::group_id,
+                auth_group_permissions::permission_id,
+            ))
+            .do_nothing()
+            .execute(&mut conn)
+            .map_err(|err| async_graphql::Error::from(err))?;
+
+        let auth_group = auth_group::table
+            .filter(auth_group::id.eq(group_id))
+            .first(&mut conn)?;
+
+        Ok(auth_group)
+    }
+
+    #[graphql(guard = "AdminRoleGuard::default()")]
+    pub async fn revoke_group_permission(
+        &self,
+        ctx: &Context<'_>,
+        group_id: ID,
+        permission: String,
+    ) -> async_graphql::Result<AuthGroup> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let permission = Permission::find(&mut conn, &permission)?;
+        diesel::delete(
+            auth_group_permissions::table
+                .filter(auth_group_permissions::group_id.eq(group_id))
+                .filter(auth_group_permissions::permission_id.eq(permission.id)),
+        )
+        .execute(&mut conn)
+        .map_err(|err| async_graphql::Error::from(err))?;
+
+        let auth_group = auth_group::table
+            .filter(auth_group::id.eq(group_id))
+            .first(&mut conn)?;
+
+        Ok(auth_group)
+    }
+
+    /// Add the user to the group. Takes effect on the next login of the user.
+    #[graphql(guard = "AdminRoleGuard::default()")]
+    pub async fn add_user_to_group(
+        &self,
+        ctx: &Context<'_>,
+        user_id: ID,
+        group_id: ID,
+    ) -> async_graphql::Result<AuthGroup> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        diesel::insert_into(sui_hei_user_groups::table)
+            .values((
+                sui_hei_user_groups::user_id.eq(user_id),
+                sui_hei_user_groups::group_id.eq(group_id),
+            ))
+            .on_conflict((sui_hei_user_groups::user_id, sui_hei_user_groups::group_id))
+            .do_nothing()
+            .execute(&mut conn)
+            .map_err(|err| async_graphql::Error::from(err))?;
+
+        let auth_group = auth_group::table
+            .filter(auth_group::id.eq(group_id))
+            .first(&mut conn)?;
+
+        Ok(auth_group)
+    }
+
+    #[graphql(guard = "AdminRoleGuard::default()")]
+    pub async fn remove_user_from_group(
+        &self,
+        ctx: &Context<'_>,
+        user_id: ID,
+        group_id: ID,
+    ) -> async_graphql::Result<AuthGroup> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        diesel::delete(
+            sui_hei_user_groups::table
+                .filter(sui_hei_user_groups::user_id.eq(user_id))
+                .filter(sui_hei_user_groups::group_id.eq(group_id)),
+        )
+        .execute(&mut conn)
+        .map_err(|err| async_graphql::Error::from(err))?;
+
+        let auth_group = auth_group::table
+            .filter(auth_group::id.eq(group_id))
+            .first(&mut conn)?;
+
+        Ok(auth_group)
+    }
+
+    /// Grant a permission to the user directly, returning the permissions of the user.
+    #[graphql(guard = "AdminRoleGuard::default()")]
+    pub async fn grant_user_permission(
+        &self,
+        ctx: &Context<'_>,
+        user_id: ID,
+        permission: String,
+    ) -> async_graphql::Result<Vec<String>> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let permission = Permission::find(&mut conn, &permission)?;
+        diesel::insert_into(sui_hei_user_user_permissions::table)
+            .values((
+                sui_hei_user_user_permissions::user_id.eq(user_id),
+                sui_hei_user_user_permissions::permission_id.eq(permission.id),
+            ))
+            .on_conflict((
+                sui_hei_user_user_permissions::user_id,
+                sui_hei_user_user_permissions::permission_id,
+            ))
+            .do_nothing()
+            .execute(&mut conn)
+            .map_err(|err| async_graphql::Error::from(err))?;
+
+        Ok(user_permissions(&mut conn, user_id)?)
+    }
+
+    /// Revoke a permission granted to the user directly, returning the permissions of the user.
+    #[graphql(guard = "AdminRoleGuard::default()")]
+    pub async fn revoke_user_permission(
+        &self,
+        ctx: &Context<'_>,
+        user_id: ID,
+        permission: String,
+    ) -> async_graphql::Result<Vec<String>> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let permission = Permission::find(&mut conn, &permission)?;
+        diesel::delete(
+            sui_hei_user_user_permissions::table
+                .filter(sui_hei_user_user_permissions::user_id.eq(user_id))
+                .filter(sui_hei_user_user_permissions::permission_id.eq(permission.id)),
+        )
+        .execute(&mut conn)
+        .map_err(|err| async_graphql::Error::from(err))?;
+
+        Ok(user_permissions(&mut conn, user_id)?)
+    }
+}
@@ -1,6 +1,7 @@
 use async_graphql::{self, Context, InputObject, Object};
 use diesel::prelude::*;
 
+use super::Audit;
 use crate::auth::Role;
 use crate::context::GlobalCtx;
 use crate::models::award::*;
@@ -115,12 +116,18 @@ impl AwardMutation {
         set: UpdateAwardInput,
     ) -> async_graphql::Result<Award> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let award = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "award", &[id])?;
 
-        let award: Award = diesel::update(award::table)
-            .filter(award::id.eq(id))
-            .set(set)
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            let award: Award = diesel::update(award::table)
+                .filter(award::id.eq(id))
+                .set(set)
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Change, &[award.id])?;
+            Ok(award)
+        })?;
 
         Ok(award)
     }
@@ -133,11 +140,17 @@ impl AwardMutation {
         data: CreateAwardInput,
     ) -> async_graphql::Result<Award> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let award = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "award", &[])?;
 
-        let award: Award = diesel::insert_into(award::table)
-            .values(&data)
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            let award: Award = diesel::insert_into(award::table)
+                .values(&data)
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Addition, &[award.id])?;
+            Ok(award)
+        })?;
 
         Ok(award)
     }
@@ -146,10 +159,16 @@ impl AwardMutation {
     #[graphql(guard = "DenyRoleGuard::new(Role::User).and(DenyRoleGuard::new(Role::Guest))")]
     pub async fn delete_award(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<Award> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let award = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "award", &[id])?;
+
+            let award = diesel::delete(award::table.filter(award::id.eq(id)))
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
 
-        let award = diesel::delete(award::table.filter(award::id.eq(id)))
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok(award)
+        })?;
 
         Ok(award)
     }
@@ -0,0 +1,261 @@
+use async_graphql::{self, Context, InputObject, Object};
+use chrono::Utc;
+use diesel::prelude::*;
+
+use super::Audit;
+use crate::auth::Role;
+use crate::context::{GlobalCtx, RequestCtx};
+use crate::models::award_application::*;
+use crate::models::user_award::GrantUserAwardData;
+use crate::models::*;
+use crate::schema::{award_application, user_award};
+
+#[derive(Default)]
+pub struct AwardApplicationQuery;
+#[derive(Default)]
+pub struct AwardApplicationMutation;
+
+#[Object]
+impl AwardApplicationQuery {
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn award_application(
+        &self,
+        ctx: &Context<'_>,
+        id: i32,
+    ) -> async_graphql::Result<AwardApplication> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let award_application: AwardApplication = award_application::table
+            .filter(award_application::id.eq(id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        // Applications are private to the applier and staff
+        user_id_guard(ctx, award_application.applier_id)?;
+
+        Ok(award_application)
+    }
+
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn award_applications(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        filter: Option<Vec<AwardApplicationFilter>>,
+        order: Option<Vec<AwardApplicationOrder>>,
+    ) -> async_graphql::Result<Vec<AwardApplication>> {
+        use crate::schema::award_application::dsl::*;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reqctx = ctx.data::<RequestCtx>()?;
+
+        let mut query = award_application.into_boxed();
+        if let Role::User = reqctx.get_role() {
+            query = query.filter(applier_id.nullable().eq(reqctx.get_user_id()));
+        }
+        if let Some(order) = order {
+            query = AwardApplicationOrders::new(order).apply_order(query);
+        }
+        if let Some(filter) = filter {
+            if let Some(filter_exp) = filter.as_expression() {
+                query = query.filter(filter_exp)
+            }
+        }
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+        if let Some(offset) = offset {
+            query = query.offset(offset);
+        }
+
+        let award_applications = query.load::<AwardApplication>(&mut conn)?;
+
+        Ok(award_applications)
+    }
+
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn award_application_count(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<Vec<AwardApplicationFilter>>,
+    ) -> async_graphql::Result<i64> {
+        use crate::schema::award_application::dsl::*;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reqctx = ctx.data::<RequestCtx>()?;
+
+        let mut query = award_application.into_boxed();
+        if let Role::User = reqctx.get_role() {
+            query = query.filter(applier_id.nullable().eq(reqctx.get_user_id()));
+        }
+        if let Some(filter) = filter {
+            if let Some(filter_exp) = filter.as_expression() {
+                query = query.filter(filter_exp)
+            }
+        }
+
+        let result = query.count().get_result(&mut conn)?;
+
+        Ok(result)
+    }
+}
+
+#[derive(InputObject)]
+pub struct CreateAwardApplicationInput {
+    pub award_id: ID,
+    #[graphql(default)]
+    pub reason: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = award_application)]
+pub struct CreateAwardApplicationData {
+    pub status: AwardApplicationStatus,
+    pub comment: String,
+    pub created: Timestamptz,
+    pub applier_id: ID,
+    pub award_id: ID,
+    pub reason: String,
+}
+
+#[derive(InputObject)]
+pub struct ReviewAwardApplicationInput {
+    /// Either `APPROVED` or `REJECTED`
+    pub status: AwardApplicationStatus,
+    #[graphql(default)]
+    pub comment: String,
+}
+
+#[Object]
+impl AwardApplicationMutation {
+    /// Apply for an award the user does not have yet.
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn create_award_application(
+        &self,
+        ctx: &Context<'_>,
+        data: CreateAwardApplicationInput,
+    ) -> async_graphql::Result<AwardApplication> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let applier_id = ctx
+            .data::<RequestCtx>()?
+            .get_user_id()
+            .ok_or(async_graphql::Error::new("No user"))?;
+
+        let awarded: i64 = user_award::table
+            .filter(user_award::user_id.eq(applier_id))
+            .filter(user_award::award_id.eq(data.award_id))
+            .count()
+            .get_result(&mut conn)?;
+        if awarded > 0 {
+            return Err(async_graphql::Error::new("Award is already granted"));
+        }
+        let pending: i64 = award_application::table
+            .filter(award_application::applier_id.eq(applier_id))
+            .filter(award_application::award_id.eq(data.award_id))
+            .filter(award_application::status.eq(AwardApplicationStatus::Pending))
+            .count()
+            .get_result(&mut conn)?;
+        if pending > 0 {
+            return Err(async_graphql::Error::new(
+                "An application for the award is pending",
+            ));
+        }
+
+        let award_application: AwardApplication = diesel::insert_into(award_application::table)
+            .values(&CreateAwardApplicationData {
+                status: AwardApplicationStatus::Pending,
+                comment: String::new(),
+                created: Utc::now(),
+                applier_id,
+                award_id: data.award_id,
+                reason: data.reason,
+            })
+            .get_result(&mut conn)
+            .map_err(|err| async_graphql::Error::from(err))?;
+
+        Ok(award_application)
+    }
+
+    /// Approve or reject a pending application.
+    ///
+    /// Approving grants the award to the applier in the same transaction.
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn review_award_application(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        data: ReviewAwardApplicationInput,
+    ) -> async_graphql::Result<AwardApplication> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let award_application = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "award_application", &[id])?;
+            let reviewer_id = ctx.data::<RequestCtx>()?.get_user_id();
+
+            if data.status == AwardApplicationStatus::Pending {
+                return Err(async_graphql::Error::new(
+                    "Application should be either approved or rejected",
+                ));
+            }
+
+            let award_application: AwardApplication = diesel::update(award_application::table)
+                .filter(award_application::id.eq(id))
+                .filter(award_application::status.eq(AwardApplicationStatus::Pending))
+                .set((
+                    award_application::status.eq(data.status),
+                    award_application::comment.eq(data.comment),
+                    award_application::reviewed.eq(Utc::now()),
+                    award_application::reviewer_id.eq(reviewer_id),
+                ))
+                .get_result(conn)
+                .optional()?
+                .ok_or_else(|| async_graphql::Error::new("Application is not pending"))?;
+
+            if award_application.status == AwardApplicationStatus::Approved {
+                diesel::insert_into(user_award::table)
+                    .values(&GrantUserAwardData {
+                        created: Utc::now().date_naive(),
+                        award_id: award_application.award_id,
+                        user_id: award_application.applier_id,
+                    })
+                    .on_conflict((user_award::award_id, user_award::user_id))
+                    .do_nothing()
+                    .execute(conn)?;
+            }
+
+            audit.record(conn, AuditAction::Change, &[award_application.id])?;
+            Ok(award_application)
+        })?;
+
+        Ok(award_application)
+    }
+
+    /// Withdraw a pending application.
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn delete_award_application(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> async_graphql::Result<AwardApplication> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        // User should be the applier
+        let award_application_inst: AwardApplication = award_application::table
+            .filter(award_application::id.eq(id))
+            .limit(1)
+            .first(&mut conn)?;
+        user_id_guard(ctx, award_application_inst.applier_id)?;
+
+        let award_application = diesel::delete(
+            award_application::table
+                .filter(award_application::id.eq(id))
+                .filter(award_application::status.eq(AwardApplicationStatus::Pending)),
+        )
+        .get_result(&mut conn)
+        .optional()
+        .map_err(|err| async_graphql::Error::from(err))?
+        .ok_or_else(|| async_graphql::Error::new("Application is not pending"))?;
+
+        Ok(award_application)
+    }
+}
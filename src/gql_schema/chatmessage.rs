@@ -5,6 +5,7 @@ use chrono::Utc;
 use diesel::prelude::*;
 use futures::{Stream, StreamExt};
 
+use super::Audit;
 use crate::auth::Role;
 use crate::broker::CindyBroker;
 use crate::context::{GlobalCtx, RequestCtx};
@@ -190,6 +191,7 @@ impl ChatmessagesDeleteResult {
 
 #[Object]
 impl ChatmessageMutation {
+    #[graphql(guard = "SuspensionGuard::new(SuspensionScope::Chat)")]
     pub async fn update_chatmessage(
         &self,
         ctx: &Context<'_>,
@@ -227,6 +229,7 @@ impl ChatmessageMutation {
         Ok(chatmessage)
     }
 
+    #[graphql(guard = "SuspensionGuard::new(SuspensionScope::Chat)")]
     pub async fn create_chatmessage(
         &self,
         ctx: &Context<'_>,
@@ -259,18 +262,25 @@ impl ChatmessageMutation {
         Ok(chatmessage)
     }
 
-    // Delete chatmessage (admin/staff only)
-    #[graphql(guard = "StaffRoleGuard::default()")]
+    // Delete chatmessage (admin/staff/chat moderators only)
+    #[graphql(guard = "PermissionGuard::new(\"chatmessage.delete\")")]
     pub async fn delete_chatmessage(
         &self,
         ctx: &Context<'_>,
         id: ID,
     ) -> async_graphql::Result<Chatmessage> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let chatmessage = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit =
+                Audit::begin_permitted(ctx, conn, "chatmessage", &[id], "chatmessage.delete")?;
 
-        let chatmessage = diesel::delete(chatmessage::table.filter(chatmessage::id.eq(id)))
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            let chatmessage = diesel::delete(chatmessage::table.filter(chatmessage::id.eq(id)))
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok(chatmessage)
+        })?;
 
         Ok(chatmessage)
     }
@@ -285,16 +295,25 @@ impl ChatmessageMutation {
         use crate::schema::chatmessage::dsl::*;
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
 
-        let mut query = diesel::delete(chatmessage).into_boxed();
-        if let Some(filter) = filter {
-            if let Some(filter_exp) = filter.as_expression() {
-                query = query.filter(filter_exp)
+        let chatmessages = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let mut id_query = chatmessage.select(id).into_boxed();
+            if let Some(filter_exp) = filter.clone().and_then(|filter| filter.as_expression()) {
+                id_query = id_query.filter(filter_exp);
             }
-        }
+            let ids: Vec<ID> = id_query.load(conn)?;
+            let audit = Audit::begin(ctx, conn, "chatmessage", &ids)?;
 
-        let chatmessages: Vec<Chatmessage> = query
-            .get_results(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            let mut query = diesel::delete(chatmessage).into_boxed();
+            if let Some(filter) = filter {
+                if let Some(filter_exp) = filter.as_expression() {
+                    query = query.filter(filter_exp)
+                }
+            }
+
+            let chatmessages: Vec<Chatmessage> = query.get_results(conn)?;
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok(chatmessages)
+        })?;
 
         Ok(ChatmessagesDeleteResult::new(chatmessages))
     }
@@ -2,6 +2,7 @@ use async_graphql::{self, Context, InputObject, Object};
 use chrono::Utc;
 use diesel::prelude::*;
 
+use super::Audit;
 use crate::auth::Role;
 use crate::context::{GlobalCtx, RequestCtx};
 use crate::models::chatroom::*;
@@ -115,27 +116,33 @@ impl ChatroomMutation {
         set: UpdateChatroomInput,
     ) -> async_graphql::Result<Chatroom> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
-        let reqctx = ctx.data::<RequestCtx>()?;
-        let role = reqctx.get_role();
-
-        match role {
-            Role::User => {
-                // User should be the owner on update mutation
-                let chatroom_inst: Chatroom = chatroom::table
-                    .filter(chatroom::id.eq(id))
-                    .limit(1)
-                    .first(&mut conn)?;
-                user_id_guard(ctx, chatroom_inst.user_id)?;
-            }
-            Role::Guest => return Err(async_graphql::Error::new("User not logged in")),
-            _ => {}
-        };
-
-        let chatroom: Chatroom = diesel::update(chatroom::table)
-            .filter(chatroom::id.eq(id))
-            .set(set)
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+        let chatroom = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "chatroom", &[id])?;
+            let reqctx = ctx.data::<RequestCtx>()?;
+            let role = reqctx.get_role();
+
+            match role {
+                Role::User => {
+                    // User should be the owner on update mutation
+                    let chatroom_inst: Chatroom = chatroom::table
+                        .filter(chatroom::id.eq(id))
+                        .limit(1)
+                        .first(conn)?;
+                    user_id_guard(ctx, chatroom_inst.user_id)?;
+                }
+                Role::Guest => return Err(async_graphql::Error::new("User not logged in")),
+                _ => {}
+            };
+
+            let chatroom: Chatroom = diesel::update(chatroom::table)
+                .filter(chatroom::id.eq(id))
+                .set(set)
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Change, &[chatroom.id])?;
+            Ok(chatroom)
+        })?;
 
         Ok(chatroom)
     }
@@ -178,10 +185,16 @@ impl ChatroomMutation {
         id: ID,
     ) -> async_graphql::Result<Chatroom> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let chatroom = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "chatroom", &[id])?;
 
-        let chatroom = diesel::delete(chatroom::table.filter(chatroom::id.eq(id)))
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            let chatroom = diesel::delete(chatroom::table.filter(chatroom::id.eq(id)))
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok(chatroom)
+        })?;
 
         Ok(chatroom)
     }
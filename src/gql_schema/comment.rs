@@ -1,6 +1,7 @@
 use async_graphql::{self, Context, InputObject, Object};
 use diesel::prelude::*;
 
+use super::Audit;
 use crate::auth::Role;
 use crate::context::{GlobalCtx, RequestCtx};
 use crate::models::comment::*;
@@ -175,27 +176,33 @@ impl CommentMutation {
         set: UpdateCommentInput,
     ) -> async_graphql::Result<Comment> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
-        let reqctx = ctx.data::<RequestCtx>()?;
-        let role = reqctx.get_role();
-
-        match role {
-            Role::User => {
-                // User should be the owner on update mutation
-                let comment_inst: Comment = comment::table
-                    .filter(comment::id.eq(id))
-                    .limit(1)
-                    .first(&mut conn)?;
-                user_id_guard(ctx, comment_inst.user_id)?;
-            }
-            Role::Guest => return Err(async_graphql::Error::new("User not logged in")),
-            _ => {}
-        };
-
-        let comment: Comment = diesel::update(comment::table)
-            .filter(comment::id.eq(id))
-            .set(set)
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+        let comment = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "comment", &[id])?;
+            let reqctx = ctx.data::<RequestCtx>()?;
+            let role = reqctx.get_role();
+
+            match role {
+                Role::User => {
+                    // User should be the owner on update mutation
+                    let comment_inst: Comment = comment::table
+                        .filter(comment::id.eq(id))
+                        .limit(1)
+                        .first(conn)?;
+                    user_id_guard(ctx, comment_inst.user_id)?;
+                }
+                Role::Guest => return Err(async_graphql::Error::new("User not logged in")),
+                _ => {}
+            };
+
+            let comment: Comment = diesel::update(comment::table)
+                .filter(comment::id.eq(id))
+                .set(set)
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Change, &[comment.id])?;
+            Ok(comment)
+        })?;
 
         Ok(comment)
     }
@@ -240,10 +247,16 @@ impl CommentMutation {
         id: ID,
     ) -> async_graphql::Result<Comment> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let comment = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "comment", &[id])?;
 
-        let comment = diesel::delete(comment::table.filter(comment::id.eq(id)))
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            let comment = diesel::delete(comment::table.filter(comment::id.eq(id)))
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok(comment)
+        })?;
 
         Ok(comment)
     }
@@ -1,7 +1,11 @@
 use async_graphql::{self, Context, InputObject, MaybeUndefined, Object};
 use chrono::Utc;
-use diesel::{prelude::*, sql_types::Integer};
+use diesel::{
+    prelude::*,
+    sql_types::{Bool, Integer},
+};
 
+use super::Audit;
 use crate::auth::Role;
 use crate::broker::CindyBroker;
 use crate::context::{GlobalCtx, RequestCtx};
@@ -69,10 +73,12 @@ impl DialogueQuery {
         user_id: ID,
     ) -> async_graphql::Result<Option<UserMaxYamiDialogueCountResult>> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reveal_anonymous = user_id_guard(ctx, user_id).is_ok();
 
         let result: Option<UserMaxYamiDialogueCountResult> =
             diesel::sql_query(include_str!("../sql/user_max_yami_dialogue_count.sql"))
                 .bind::<Integer, _>(user_id)
+                .bind::<Bool, _>(reveal_anonymous)
                 .get_result(&mut conn)
                 .ok();
 
@@ -230,6 +236,9 @@ impl DialogueMutation {
         match role {
             Role::User => {
                 assert_eq_guard_msg(set.qno, None, "Setting qno explicitly is prohibited")?;
+                if set.question.is_some() && reqctx.is_suspended(SuspensionScope::Question) {
+                    return Err(async_graphql::Error::new("Forbidden: User is suspended"));
+                }
 
                 // Update edit times
                 if set.question.is_some() {
@@ -248,11 +257,15 @@ impl DialogueMutation {
             Role::Staff | Role::Admin => {}
         };
 
-        let dialogue: Dialogue = diesel::update(dialogue::table)
-            .filter(dialogue::id.eq(id))
-            .set(UpdateDialogueData::from(set))
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+        let dialogue = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "dialogue", &[id])?;
+            let dialogue: Dialogue = diesel::update(dialogue::table)
+                .filter(dialogue::id.eq(id))
+                .set(UpdateDialogueData::from(set))
+                .get_result(conn)?;
+            audit.record(conn, AuditAction::Change, &[dialogue.id])?;
+            Ok(dialogue)
+        })?;
 
         // Update PuzzleLogs
         let puzzle_id = dialogue.puzzle_id;
@@ -290,7 +303,9 @@ impl DialogueMutation {
         Ok(dialogue)
     }
 
-    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    #[graphql(
+        guard = "DenyRoleGuard::new(Role::Guest).and(SuspensionGuard::new(SuspensionScope::Question))"
+    )]
     pub async fn create_dialogue(
         &self,
         ctx: &Context<'_>,
@@ -354,10 +369,16 @@ impl DialogueMutation {
         id: ID,
     ) -> async_graphql::Result<Dialogue> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let dialogue = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "dialogue", &[id])?;
 
-        let dialogue = diesel::delete(dialogue::table.filter(dialogue::id.eq(id)))
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            let dialogue = diesel::delete(dialogue::table.filter(dialogue::id.eq(id)))
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok(dialogue)
+        })?;
 
         Ok(dialogue)
     }
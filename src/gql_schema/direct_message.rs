@@ -3,6 +3,7 @@ use chrono::Utc;
 use diesel::prelude::*;
 use futures::Stream;
 
+use super::Audit;
 use crate::auth::Role;
 use crate::broker::CindyBroker;
 use crate::context::{GlobalCtx, RequestCtx};
@@ -100,6 +101,7 @@ pub struct CreateDirectMessageInput {
 
 #[Object]
 impl DirectMessageMutation {
+    #[graphql(guard = "SuspensionGuard::new(SuspensionScope::Chat)")]
     pub async fn update_direct_message(
         &self,
         ctx: &Context<'_>,
@@ -150,6 +152,7 @@ impl DirectMessageMutation {
         Ok(direct_message)
     }
 
+    #[graphql(guard = "SuspensionGuard::new(SuspensionScope::Chat)")]
     pub async fn create_direct_message(
         &self,
         ctx: &Context<'_>,
@@ -202,11 +205,17 @@ impl DirectMessageMutation {
         id: ID,
     ) -> async_graphql::Result<DirectMessage> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let direct_message = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "direct_message", &[id])?;
 
-        let direct_message =
-            diesel::delete(direct_message::table.filter(direct_message::id.eq(id)))
-                .get_result(&mut conn)
-                .map_err(|err| async_graphql::Error::from(err))?;
+            let direct_message =
+                diesel::delete(direct_message::table.filter(direct_message::id.eq(id)))
+                    .get_result(conn)
+                    .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok(direct_message)
+        })?;
 
         Ok(direct_message)
     }
@@ -216,10 +225,13 @@ impl DirectMessageMutation {
 impl DirectMessageSubscription {
     pub async fn direct_message_sub(
         &self,
+        ctx: &Context<'_>,
         user_id: ID,
-    ) -> impl Stream<Item = Option<DirectMessageSub>> {
+    ) -> async_graphql::Result<impl Stream<Item = Option<DirectMessageSub>>> {
+        user_id_guard(ctx, user_id)?;
+
         let key = format!("dm<{}>", &user_id);
 
-        CindyBroker::<DirectMessageSub>::subscribe_to(key)
+        Ok(CindyBroker::<DirectMessageSub>::subscribe_to(key))
     }
 }
@@ -4,6 +4,7 @@ use diesel::{
     sql_types::{BigInt, Integer},
 };
 
+use super::Audit;
 use crate::auth::Role;
 use crate::context::{GlobalCtx, RequestCtx};
 use crate::models::dm_read::*;
@@ -231,10 +232,16 @@ impl DmReadMutation {
     #[graphql(guard = "DenyRoleGuard::new(Role::User).and(DenyRoleGuard::new(Role::Guest))")]
     pub async fn delete_dm_read(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<DmRead> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let dm_read = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "dm_read", &[id])?;
 
-        let dm_read = diesel::delete(dm_read::table.filter(dm_read::id.eq(id)))
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            let dm_read = diesel::delete(dm_read::table.filter(dm_read::id.eq(id)))
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok(dm_read)
+        })?;
 
         Ok(dm_read)
     }
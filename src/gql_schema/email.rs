@@ -0,0 +1,90 @@
+use async_graphql::{self, Context, Object};
+use diesel::prelude::*;
+
+use super::Audit;
+use crate::auth::email::{send_verification, validate_email};
+use crate::auth::Role;
+use crate::context::{GlobalCtx, RequestCtx};
+use crate::models::*;
+use crate::schema::user;
+
+#[derive(Default)]
+pub struct EmailMutation;
+
+#[Object]
+impl EmailMutation {
+    /// Change the email of the logged in user, mailing a token to verify it
+    ///
+    /// An empty email removes it.
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn update_email(
+        &self,
+        ctx: &Context<'_>,
+        email: String,
+    ) -> async_graphql::Result<User> {
+        let user_id = ctx
+            .data::<RequestCtx>()?
+            .get_user_id()
+            .ok_or(async_graphql::Error::new("No user"))?;
+        let ctx_global = ctx.data::<GlobalCtx>()?;
+        let mut conn = ctx_global.get_conn()?;
+
+        let email = email.trim();
+        if !email.is_empty() {
+            validate_email(email).map_err(async_graphql::Error::new)?;
+        }
+
+        let current: User = user::table.filter(user::id.eq(user_id)).first(&mut conn)?;
+        if current.email == email {
+            return Ok(current);
+        }
+
+        let usr = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "user", &[user_id])?;
+            let usr: User = diesel::update(&current)
+                .set((
+                    user::email.eq(email),
+                    user::email_verified.eq(None::<Timestamptz>),
+                ))
+                .get_result(conn)?;
+            audit.record(conn, AuditAction::Change, &[usr.id])?;
+            Ok(usr)
+        })?;
+
+        info!("updateEmail: User<{}:{}>", &usr.id, &usr.nickname);
+
+        if !usr.email.is_empty() {
+            if let Err(error) = send_verification(&mut conn, ctx_global.get_mailer(), &usr) {
+                error!("Error sending mail to User<{}>: {:?}", &usr.id, error);
+                return Err(async_graphql::Error::new(
+                    "Email is updated, but error sending the verification mail",
+                ));
+            }
+        }
+
+        Ok(usr)
+    }
+
+    /// Mail another token to verify the email of the logged in user
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn resend_email_verification(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<bool> {
+        let user_id = ctx
+            .data::<RequestCtx>()?
+            .get_user_id()
+            .ok_or(async_graphql::Error::new("No user"))?;
+        let ctx_global = ctx.data::<GlobalCtx>()?;
+        let mut conn = ctx_global.get_conn()?;
+
+        let usr: User = user::table.filter(user::id.eq(user_id)).first(&mut conn)?;
+        if usr.email_verified.is_some() {
+            return Err(async_graphql::Error::new("Email is already verified"));
+        }
+
+        send_verification(&mut conn, ctx_global.get_mailer(), &usr)?;
+
+        Ok(true)
+    }
+}
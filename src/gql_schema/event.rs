@@ -0,0 +1,304 @@
+use async_graphql::{self, Context, InputObject, Object};
+use chrono::Utc;
+use diesel::{dsl::not, prelude::*};
+
+use super::Audit;
+use crate::context::{GlobalCtx, RequestCtx};
+use crate::models::event::*;
+use crate::models::user_award::GrantUserAwardData;
+use crate::models::*;
+use crate::schema::{event, event_award, puzzle, user_award};
+
+#[derive(Default)]
+pub struct EventQuery;
+#[derive(Default)]
+pub struct EventMutation;
+
+/// Grant the awards of the event to the users qualifying for it.
+///
+/// A user qualifies by having posted a puzzle during the event that is not hidden.
+/// Anonymous puzzles still undergoing are left out, so that the award does not reveal the author.
+/// Users already holding an award are skipped.
+///
+/// Returns the number of granted user awards.
+pub(crate) fn grant_event_awards(conn: &mut PgConnection, event: &Event) -> QueryResult<usize> {
+    let award_ids: Vec<ID> = event_award::table
+        .filter(event_award::event_id.eq(event.id))
+        .select(event_award::award_id)
+        .load(conn)?;
+    if award_ids.is_empty() {
+        return Ok(0);
+    }
+
+    let user_ids: Vec<ID> = puzzle::table
+        .filter(puzzle::created.ge(event.start_time))
+        .filter(puzzle::created.le(event.end_time))
+        .filter(puzzle::status.eq_any(vec![Status::Undergoing, Status::Solved, Status::Dazed]))
+        .filter(not(
+            puzzle::anonymous.and(puzzle::status.eq(Status::Undergoing))
+        ))
+        .select(puzzle::user_id)
+        .distinct()
+        .load(conn)?;
+
+    let today = Utc::now().date_naive();
+    let grants: Vec<GrantUserAwardData> = award_ids
+        .iter()
+        .flat_map(|award_id| {
+            user_ids.iter().map(move |user_id| GrantUserAwardData {
+                created: today,
+                award_id: *award_id,
+                user_id: *user_id,
+            })
+        })
+        .collect();
+
+    diesel::insert_into(user_award::table)
+        .values(&grants)
+        .on_conflict((user_award::award_id, user_award::user_id))
+        .do_nothing()
+        .execute(conn)
+}
+
+/// Close the event if it is open, granting its awards.
+///
+/// Returns the closed event, or `None` if it has been closed already.
+pub(crate) fn close_event(conn: &mut PgConnection, event_id: ID) -> QueryResult<Option<Event>> {
+    conn.transaction(|conn| {
+        let closed: Option<Event> = diesel::update(event::table)
+            .filter(event::id.eq(event_id))
+            .filter(event::status.eq(EventStatus::Open))
+            .set(event::status.eq(EventStatus::Closed))
+            .get_result(conn)
+            .optional()?;
+
+        if let Some(event_inst) = closed.as_ref() {
+            grant_event_awards(conn, event_inst)?;
+        }
+
+        Ok(closed)
+    })
+}
+
+#[Object]
+impl EventQuery {
+    pub async fn event(&self, ctx: &Context<'_>, id: i32) -> async_graphql::Result<Event> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let event = event::table
+            .filter(event::id.eq(id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(event)
+    }
+
+    pub async fn events(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        filter: Option<Vec<EventFilter>>,
+        order: Option<Vec<EventOrder>>,
+    ) -> async_graphql::Result<Vec<Event>> {
+        use crate::schema::event::dsl::*;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let mut query = event.into_boxed();
+        if let Some(order) = order {
+            query = EventOrders::new(order).apply_order(query);
+        }
+        if let Some(filter) = filter {
+            if let Some(filter_exp) = filter.as_expression() {
+                query = query.filter(filter_exp)
+            }
+        }
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+        if let Some(offset) = offset {
+            query = query.offset(offset);
+        }
+
+        let events = query.load::<Event>(&mut conn)?;
+
+        Ok(events)
+    }
+
+    pub async fn event_count(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<Vec<EventFilter>>,
+    ) -> async_graphql::Result<i64> {
+        use crate::schema::event::dsl::*;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let mut query = event.into_boxed();
+        if let Some(filter) = filter {
+            if let Some(filter_exp) = filter.as_expression() {
+                query = query.filter(filter_exp)
+            }
+        }
+
+        let result = query.count().get_result(&mut conn)?;
+
+        Ok(result)
+    }
+}
+
+#[derive(InputObject, AsChangeset, Debug)]
+#[diesel(table_name = event)]
+pub struct UpdateEventInput {
+    pub title: Option<String>,
+    pub banner_img_url: Option<String>,
+    pub start_time: Option<Timestamptz>,
+    pub end_time: Option<Timestamptz>,
+    pub page_link: Option<String>,
+    pub page_src: Option<String>,
+}
+
+#[derive(InputObject)]
+pub struct CreateEventInput {
+    pub title: String,
+    #[graphql(default)]
+    pub banner_img_url: String,
+    pub start_time: Timestamptz,
+    pub end_time: Timestamptz,
+    #[graphql(default)]
+    pub page_link: String,
+    #[graphql(default)]
+    pub page_src: String,
+    pub user_id: Option<ID>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = event)]
+struct CreateEventData {
+    title: String,
+    banner_img_url: String,
+    status: EventStatus,
+    start_time: Timestamptz,
+    end_time: Timestamptz,
+    page_link: String,
+    page_src: String,
+    user_id: ID,
+}
+
+#[Object]
+impl EventMutation {
+    // Update event
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn update_event(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        set: UpdateEventInput,
+    ) -> async_graphql::Result<Event> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let event = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "event", &[id])?;
+
+            let event_inst: Event = event::table.filter(event::id.eq(id)).limit(1).first(conn)?;
+            if set.start_time.unwrap_or(event_inst.start_time)
+                > set.end_time.unwrap_or(event_inst.end_time)
+            {
+                return Err(async_graphql::Error::new(
+                    "Event should not end before it starts",
+                ));
+            }
+
+            let event: Event = diesel::update(event::table)
+                .filter(event::id.eq(id))
+                .set(set)
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Change, &[event.id])?;
+            Ok(event)
+        })?;
+
+        Ok(event)
+    }
+
+    // Create event
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn create_event(
+        &self,
+        ctx: &Context<'_>,
+        data: CreateEventInput,
+    ) -> async_graphql::Result<Event> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let event = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "event", &[])?;
+            let reqctx = ctx.data::<RequestCtx>()?;
+
+            if data.start_time > data.end_time {
+                return Err(async_graphql::Error::new(
+                    "Event should not end before it starts",
+                ));
+            }
+            let user_id = match data.user_id {
+                Some(user_id) => user_id,
+                None => reqctx
+                    .get_user_id()
+                    .ok_or(async_graphql::Error::new("No user"))?,
+            };
+
+            let event: Event = diesel::insert_into(event::table)
+                .values(&CreateEventData {
+                    title: data.title,
+                    banner_img_url: data.banner_img_url,
+                    status: EventStatus::Open,
+                    start_time: data.start_time,
+                    end_time: data.end_time,
+                    page_link: data.page_link,
+                    page_src: data.page_src,
+                    user_id,
+                })
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Addition, &[event.id])?;
+            Ok(event)
+        })?;
+
+        Ok(event)
+    }
+
+    /// Close the event ahead of its end time, granting its awards.
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn close_event(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<Event> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let event = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "event", &[id])?;
+
+            let event = close_event(conn, id)?
+                .ok_or_else(|| async_graphql::Error::new("Event is not open"))?;
+
+            audit.record(conn, AuditAction::Change, &[event.id])?;
+            Ok(event)
+        })?;
+
+        Ok(event)
+    }
+
+    // Delete event along with its event awards
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn delete_event(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<Event> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let event = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "event", &[id])?;
+
+            diesel::delete(event_award::table.filter(event_award::event_id.eq(id)))
+                .execute(conn)?;
+            let event = diesel::delete(event::table.filter(event::id.eq(id))).get_result(conn)?;
+
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok(event)
+        })?;
+
+        Ok(event)
+    }
+}
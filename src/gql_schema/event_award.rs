@@ -0,0 +1,151 @@
+use async_graphql::{self, Context, InputObject, Object};
+use diesel::prelude::*;
+
+use super::Audit;
+use crate::context::GlobalCtx;
+use crate::models::event_award::*;
+use crate::models::*;
+use crate::schema::event_award;
+
+#[derive(Default)]
+pub struct EventAwardQuery;
+#[derive(Default)]
+pub struct EventAwardMutation;
+
+#[Object]
+impl EventAwardQuery {
+    pub async fn event_award(
+        &self,
+        ctx: &Context<'_>,
+        id: i32,
+    ) -> async_graphql::Result<EventAward> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let event_award = event_award::table
+            .filter(event_award::id.eq(id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(event_award)
+    }
+
+    pub async fn event_awards(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        filter: Option<Vec<EventAwardFilter>>,
+        order: Option<Vec<EventAwardOrder>>,
+    ) -> async_graphql::Result<Vec<EventAward>> {
+        use crate::schema::event_award::dsl::*;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let mut query = event_award.into_boxed();
+        if let Some(order) = order {
+            query = EventAwardOrders::new(order).apply_order(query);
+        }
+        if let Some(filter) = filter {
+            if let Some(filter_exp) = filter.as_expression() {
+                query = query.filter(filter_exp)
+            }
+        }
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+        if let Some(offset) = offset {
+            query = query.offset(offset);
+        }
+
+        let event_awards = query.load::<EventAward>(&mut conn)?;
+
+        Ok(event_awards)
+    }
+}
+
+#[derive(InputObject, AsChangeset, Debug)]
+#[diesel(table_name = event_award)]
+pub struct UpdateEventAwardInput {
+    pub award_id: Option<ID>,
+    pub event_id: Option<ID>,
+}
+
+#[derive(InputObject, Insertable)]
+#[diesel(table_name = event_award)]
+pub struct CreateEventAwardInput {
+    pub award_id: ID,
+    pub event_id: ID,
+}
+
+#[Object]
+impl EventAwardMutation {
+    // Update event_award
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn update_event_award(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        set: UpdateEventAwardInput,
+    ) -> async_graphql::Result<EventAward> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let event_award = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "event_award", &[id])?;
+
+            let event_award: EventAward = diesel::update(event_award::table)
+                .filter(event_award::id.eq(id))
+                .set(set)
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Change, &[event_award.id])?;
+            Ok(event_award)
+        })?;
+
+        Ok(event_award)
+    }
+
+    // Create event_award
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn create_event_award(
+        &self,
+        ctx: &Context<'_>,
+        data: CreateEventAwardInput,
+    ) -> async_graphql::Result<EventAward> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let event_award = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "event_award", &[])?;
+
+            let event_award: EventAward = diesel::insert_into(event_award::table)
+                .values(&data)
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Addition, &[event_award.id])?;
+            Ok(event_award)
+        })?;
+
+        Ok(event_award)
+    }
+
+    // Delete event_award
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn delete_event_award(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> async_graphql::Result<EventAward> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let event_award = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "event_award", &[id])?;
+
+            let event_award = diesel::delete(event_award::table.filter(event_award::id.eq(id)))
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok(event_award)
+        })?;
+
+        Ok(event_award)
+    }
+}
@@ -2,6 +2,7 @@ use async_graphql::{self, Context, InputObject, MaybeUndefined, Object};
 use chrono::Utc;
 use diesel::prelude::*;
 
+use super::Audit;
 use crate::auth::Role;
 use crate::broker::CindyBroker;
 use crate::context::{GlobalCtx, RequestCtx};
@@ -153,39 +154,42 @@ impl HintMutation {
         use crate::schema::puzzle;
 
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
-        let reqctx = ctx.data::<RequestCtx>()?;
-        let role = reqctx.get_role();
-
-        let hint_inst: Hint = hint::table
-            .filter(hint::id.eq(id))
-            .limit(1)
-            .first(&mut conn)?;
-
-        match role {
-            Role::User => {
-                // User should be the owner on update mutation
-                let puzzle_inst: Puzzle = puzzle::table
-                    .filter(puzzle::id.eq(hint_inst.puzzle_id))
-                    .limit(1)
-                    .first(&mut conn)?;
-                user_id_guard(ctx, puzzle_inst.user_id)?;
-
-                // Set `modified` to the current time when edited
-                set.edit_times = Some(hint_inst.edit_times + 1);
-            }
-            Role::Guest => return Err(async_graphql::Error::new("User not logged in")),
-            _ => {}
-        };
-
-        debug!("update_hint: {:?}", &set);
-        let data = UpdateHintData::from(set);
-        debug!("update_hint: {:?}", &data);
-
-        let hint: Hint = diesel::update(hint::table)
-            .filter(hint::id.eq(id))
-            .set(data)
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+        let (hint_inst, hint) = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "hint", &[id])?;
+            let reqctx = ctx.data::<RequestCtx>()?;
+            let role = reqctx.get_role();
+
+            let hint_inst: Hint = hint::table.filter(hint::id.eq(id)).limit(1).first(conn)?;
+
+            match role {
+                Role::User => {
+                    // User should be the owner on update mutation
+                    let puzzle_inst: Puzzle = puzzle::table
+                        .filter(puzzle::id.eq(hint_inst.puzzle_id))
+                        .limit(1)
+                        .first(conn)?;
+                    user_id_guard(ctx, puzzle_inst.user_id)?;
+
+                    // Set `modified` to the current time when edited
+                    set.edit_times = Some(hint_inst.edit_times + 1);
+                }
+                Role::Guest => return Err(async_graphql::Error::new("User not logged in")),
+                _ => {}
+            };
+
+            debug!("update_hint: {:?}", &set);
+            let data = UpdateHintData::from(set);
+            debug!("update_hint: {:?}", &data);
+
+            let hint: Hint = diesel::update(hint::table)
+                .filter(hint::id.eq(id))
+                .set(data)
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Change, &[hint.id])?;
+            Ok((hint_inst, hint))
+        })?;
 
         let key_starts_with = format!("puzzleLog<{}", hint.puzzle_id);
         CindyBroker::publish(PuzzleLogSub::HintUpdated(hint_inst.clone(), hint.clone()));
@@ -241,10 +245,16 @@ impl HintMutation {
     #[graphql(guard = "DenyRoleGuard::new(Role::User).and(DenyRoleGuard::new(Role::Guest))")]
     pub async fn delete_hint(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<Hint> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let hint = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "hint", &[id])?;
 
-        let hint = diesel::delete(hint::table.filter(hint::id.eq(id)))
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            let hint = diesel::delete(hint::table.filter(hint::id.eq(id)))
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok(hint)
+        })?;
 
         Ok(hint)
     }
@@ -0,0 +1,104 @@
+use async_graphql::{self, Context, Object};
+use chrono::Utc;
+use diesel::prelude::*;
+
+use super::Audit;
+use crate::auth::invite_code::create_invite_code;
+use crate::context::{GlobalCtx, RequestCtx};
+use crate::models::*;
+use crate::schema::invite_code;
+
+#[derive(Default)]
+pub struct InviteCodeQuery;
+#[derive(Default)]
+pub struct InviteCodeMutation;
+
+#[Object]
+impl InviteCodeQuery {
+    /// Invite codes to sign up with, newest first
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn invite_codes(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+    ) -> async_graphql::Result<Vec<InviteCode>> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let mut query = invite_code::table
+            .order(invite_code::id.desc())
+            .into_boxed();
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+        if let Some(offset) = offset {
+            query = query.offset(offset);
+        }
+
+        let codes = query.load(&mut conn)?;
+
+        Ok(codes)
+    }
+}
+
+#[Object]
+impl InviteCodeMutation {
+    /// Create a code to sign up with, usable `maxUses` times (once by default)
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn create_invite_code(
+        &self,
+        ctx: &Context<'_>,
+        max_uses: Option<i32>,
+        expires: Option<Timestamptz>,
+    ) -> async_graphql::Result<InviteCode> {
+        let user = ctx
+            .data::<RequestCtx>()?
+            .get_user()
+            .ok_or(async_graphql::Error::new("No user"))?;
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let code = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "invite_code", &[])?;
+            let code = create_invite_code(conn, user.id, max_uses.unwrap_or(1), expires)?;
+            audit.record(conn, AuditAction::Addition, &[code.id])?;
+            Ok(code)
+        })?;
+
+        info!(
+            "createInviteCode: <{}> x{} by User<{}:{}>",
+            &code.id, &code.max_uses, &user.id, &user.nickname
+        );
+
+        Ok(code)
+    }
+
+    /// Revoke a code, so that it cannot be used anymore
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn revoke_invite_code(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> async_graphql::Result<InviteCode> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let code = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "invite_code", &[id])?;
+
+            let code: InviteCode = invite_code::table
+                .filter(invite_code::id.eq(id))
+                .first(conn)?;
+
+            let code = diesel::update(&code)
+                .filter(invite_code::revoked.is_null())
+                .set(invite_code::revoked.eq(Some(Utc::now())))
+                .get_result(conn)
+                .optional()?
+                .unwrap_or(code);
+
+            audit.record(conn, AuditAction::Change, &[code.id])?;
+            Ok(code)
+        })?;
+
+        Ok(code)
+    }
+}
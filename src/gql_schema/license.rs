@@ -1,6 +1,7 @@
 use async_graphql::{self, Context, InputObject, Object};
 use diesel::prelude::*;
 
+use super::Audit;
 use crate::auth::Role;
 use crate::context::GlobalCtx;
 use crate::models::license::*;
@@ -89,12 +90,18 @@ impl LicenseMutation {
         set: UpdateLicenseInput,
     ) -> async_graphql::Result<License> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let license = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "license", &[id])?;
 
-        let license: License = diesel::update(license::table)
-            .filter(license::id.eq(id))
-            .set(set)
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            let license: License = diesel::update(license::table)
+                .filter(license::id.eq(id))
+                .set(set)
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Change, &[license.id])?;
+            Ok(license)
+        })?;
 
         Ok(license)
     }
@@ -107,11 +114,17 @@ impl LicenseMutation {
         data: CreateLicenseInput,
     ) -> async_graphql::Result<License> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let license = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "license", &[])?;
 
-        let license: License = diesel::insert_into(license::table)
-            .values(&data)
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            let license: License = diesel::insert_into(license::table)
+                .values(&data)
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Addition, &[license.id])?;
+            Ok(license)
+        })?;
 
         Ok(license)
     }
@@ -124,10 +137,16 @@ impl LicenseMutation {
         id: ID,
     ) -> async_graphql::Result<License> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let license = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "license", &[id])?;
+
+            let license = diesel::delete(license::table.filter(license::id.eq(id)))
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
 
-        let license = diesel::delete(license::table.filter(license::id.eq(id)))
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok(license)
+        })?;
 
         Ok(license)
     }
@@ -0,0 +1,56 @@
+use async_graphql::{self, Context, Object};
+
+use super::Audit;
+use crate::auth::throttle::{self, Lockout, ThrottleKind};
+use crate::context::{GlobalCtx, RequestCtx};
+use crate::models::*;
+
+#[derive(Default)]
+pub struct LoginLockoutQuery;
+#[derive(Default)]
+pub struct LoginLockoutMutation;
+
+#[Object]
+impl LoginLockoutQuery {
+    /// IP addresses and usernames being locked out for too many attempts
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn login_lockouts(&self) -> Vec<Lockout> {
+        let mut lockouts = throttle::lockouts();
+        lockouts.sort_by_key(|lockout| std::cmp::Reverse(lockout.locked_until));
+        lockouts
+    }
+}
+
+#[Object]
+impl LoginLockoutMutation {
+    /// Lift the lockout of an IP address or username, returning whether it was locked out
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn lift_login_lockout(
+        &self,
+        ctx: &Context<'_>,
+        kind: ThrottleKind,
+        key: String,
+    ) -> async_graphql::Result<bool> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let lifted = throttle::lift(kind, &key);
+        if lifted {
+            Audit::note(
+                ctx,
+                &mut conn,
+                AuditAction::Change,
+                &format!("login lockout: {:?} {}", kind, &key),
+                json!({ "locked": { "old": true, "new": false } }),
+            )?;
+        }
+
+        if let Some(user) = ctx.data::<RequestCtx>()?.get_user() {
+            info!(
+                "liftLoginLockout: {:?} '{}' by User<{}:{}>",
+                kind, &key, &user.id, &user.nickname
+            );
+        }
+
+        Ok(lifted)
+    }
+}
@@ -1,11 +1,22 @@
-use async_graphql::{MergedObject, MergedSubscription, Object, Schema, SimpleObject, Subscription};
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextPrepareRequest, NextResolve, ResolveInfo,
+};
+use async_graphql::{
+    async_trait, MergedObject, MergedSubscription, Object, Request, Schema, ServerError,
+    ServerResult, SimpleObject, Subscription, Value,
+};
 //use futures::lock::Mutex;
 use futures::{Stream, StreamExt};
-//use std::sync::Arc;
+use std::sync::Arc;
 use tokio::time::Duration;
 use tokio_stream::wrappers::IntervalStream;
 
+use crate::context::{GlobalCtx, RequestCtx};
+
+mod audit_log;
+mod auth_group;
 mod award;
+mod award_application;
 mod bookmark;
 mod chatmessage;
 mod chatroom;
@@ -13,19 +24,36 @@ mod comment;
 mod dialogue;
 mod direct_message;
 mod dm_read;
+mod email;
+mod event;
+mod event_award;
 mod favchat;
 mod hint;
 mod image;
+mod invite_code;
 mod license;
+mod login_lockout;
+mod personal_access_token;
 mod puzzle;
 mod puzzle_log;
+mod puzzle_revision;
+mod puzzle_search;
 mod puzzle_tag;
+mod replay;
+mod replay_dialogue;
+mod schedule;
 mod star;
 mod tag;
+mod two_factor;
 mod user;
 mod user_award;
+mod user_suspension;
 
+pub(crate) use audit_log::Audit;
+pub use audit_log::AuditLogQuery;
+pub use auth_group::{AuthGroupMutation, AuthGroupQuery};
 pub use award::{AwardMutation, AwardQuery};
+pub use award_application::{AwardApplicationMutation, AwardApplicationQuery};
 pub use bookmark::{BookmarkMutation, BookmarkQuery};
 pub use chatmessage::{ChatmessageMutation, ChatmessageQuery, ChatmessageSubscription};
 pub use chatroom::{ChatroomMutation, ChatroomQuery};
@@ -33,23 +61,43 @@ pub use comment::{CommentMutation, CommentQuery};
 pub use dialogue::{DialogueMutation, DialogueQuery};
 pub use direct_message::{DirectMessageMutation, DirectMessageQuery, DirectMessageSubscription};
 pub use dm_read::{DmReadMutation, DmReadQuery};
+pub use email::EmailMutation;
+pub(crate) use event::close_event;
+pub use event::{EventMutation, EventQuery};
+pub use event_award::{EventAwardMutation, EventAwardQuery};
 pub use favchat::{FavchatMutation, FavchatQuery};
 pub use hint::{HintMutation, HintQuery};
 pub use image::{ImageMutation, ImageQuery};
+pub use invite_code::{InviteCodeMutation, InviteCodeQuery};
 pub use license::{LicenseMutation, LicenseQuery};
+pub use login_lockout::{LoginLockoutMutation, LoginLockoutQuery};
+pub use personal_access_token::{PersonalAccessTokenMutation, PersonalAccessTokenQuery};
+pub(crate) use puzzle::{assign_referred_images, DazedTimeCalc};
 pub use puzzle::{PuzzleMutation, PuzzleQuery, PuzzleSubscription};
 pub use puzzle_log::{PuzzleLogQuery, PuzzleLogSubscription};
+pub(crate) use puzzle_revision::record_revision;
+pub use puzzle_revision::PuzzleRevisionQuery;
+pub use puzzle_search::PuzzleSearchQuery;
+pub(crate) use puzzle_search::{drop_tokenize_cache, sync_tokenize_cache};
 pub use puzzle_tag::{PuzzleTagMutation, PuzzleTagQuery};
+pub use replay::{ReplayMutation, ReplayQuery};
+pub use replay_dialogue::{ReplayDialogueMutation, ReplayDialogueQuery};
+pub use schedule::{ScheduleMutation, ScheduleQuery};
 pub use star::{StarMutation, StarQuery};
 pub use tag::{TagMutation, TagQuery};
+pub use two_factor::TwoFactorMutation;
 pub use user::{UserMutation, UserQuery};
 pub use user_award::{UserAwardMutation, UserAwardQuery};
+pub use user_suspension::{UserSuspensionMutation, UserSuspensionQuery};
 
 pub type CindySchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
 
 #[derive(MergedObject, Default)]
 pub struct QueryRoot(
+    AuditLogQuery,
+    AuthGroupQuery,
     AwardQuery,
+    AwardApplicationQuery,
     BaseQuery,
     BookmarkQuery,
     ChatmessageQuery,
@@ -58,22 +106,35 @@ pub struct QueryRoot(
     DialogueQuery,
     DirectMessageQuery,
     DmReadQuery,
+    EventQuery,
+    EventAwardQuery,
     ImageQuery,
+    InviteCodeQuery,
     FavchatQuery,
     HintQuery,
     LicenseQuery,
+    LoginLockoutQuery,
+    PersonalAccessTokenQuery,
     PuzzleLogQuery,
     PuzzleQuery,
+    PuzzleRevisionQuery,
+    PuzzleSearchQuery,
     PuzzleTagQuery,
+    ReplayQuery,
+    ReplayDialogueQuery,
+    ScheduleQuery,
     StarQuery,
     TagQuery,
     UserQuery,
     UserAwardQuery,
+    UserSuspensionQuery,
 );
 
 #[derive(MergedObject, Default)]
 pub struct MutationRoot(
+    AuthGroupMutation,
     AwardMutation,
+    AwardApplicationMutation,
     BookmarkMutation,
     ChatmessageMutation,
     ChatroomMutation,
@@ -81,16 +142,27 @@ pub struct MutationRoot(
     DialogueMutation,
     DirectMessageMutation,
     DmReadMutation,
+    EmailMutation,
+    EventMutation,
+    EventAwardMutation,
     FavchatMutation,
     ImageMutation,
+    InviteCodeMutation,
     HintMutation,
     LicenseMutation,
+    LoginLockoutMutation,
+    PersonalAccessTokenMutation,
     PuzzleMutation,
     PuzzleTagMutation,
+    ReplayMutation,
+    ReplayDialogueMutation,
+    ScheduleMutation,
     StarMutation,
     TagMutation,
+    TwoFactorMutation,
     UserMutation,
     UserAwardMutation,
+    UserSuspensionMutation,
 );
 
 #[derive(MergedSubscription, Default)]
@@ -152,3 +224,53 @@ impl BaseSubscription {
         CindyBroker::<IntervalMsg>::subscribe()
     }
 }
+
+/// Reject mutations the request is not authorized for, beyond its role.
+///
+/// Personal access tokens are limited to their scopes, with mutations denied by default so
+/// that new ones are not opened to tokens by accident. Session cookies need the CSRF token.
+/// Operations on a WebSocket connection check its token and suspensions again first.
+pub struct MutationCheck;
+
+impl ExtensionFactory for MutationCheck {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(MutationCheckExtension)
+    }
+}
+
+struct MutationCheckExtension;
+
+#[async_trait::async_trait]
+impl Extension for MutationCheckExtension {
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        if let (Some(reqctx), Some(global_ctx)) =
+            (ctx.data_opt::<RequestCtx>(), ctx.data_opt::<GlobalCtx>())
+        {
+            if let Err(error) = reqctx.revalidate(global_ctx) {
+                return Err(ServerError::new(error, None));
+            }
+        }
+        next.run(ctx, request).await
+    }
+
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> async_graphql::ServerResult<Option<Value>> {
+        if info.parent_type == "MutationRoot" {
+            if let Some(reqctx) = ctx.data_opt::<RequestCtx>() {
+                if let Err(error) = reqctx.check_mutation(info.name) {
+                    return Err(ServerError::new(error, None));
+                }
+            }
+        }
+        next.run(ctx, info).await
+    }
+}
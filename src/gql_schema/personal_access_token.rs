@@ -0,0 +1,100 @@
+use async_graphql::{self, Context, Object, SimpleObject};
+use chrono::Utc;
+use diesel::prelude::*;
+
+use crate::auth::access_token::create_access_token;
+use crate::auth::Role;
+use crate::context::{GlobalCtx, RequestCtx};
+use crate::models::*;
+use crate::schema::personal_access_token;
+
+#[derive(Default)]
+pub struct PersonalAccessTokenQuery;
+#[derive(Default)]
+pub struct PersonalAccessTokenMutation;
+
+#[derive(SimpleObject)]
+pub struct CreatedPersonalAccessToken {
+    /// The token to put in the Authorization header, shown only this once
+    pub token: String,
+    pub personal_access_token: PersonalAccessToken,
+}
+
+#[Object]
+impl PersonalAccessTokenQuery {
+    /// Personal access tokens of the logged in user
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn personal_access_tokens(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Vec<PersonalAccessToken>> {
+        let user_id = ctx
+            .data::<RequestCtx>()?
+            .get_user_id()
+            .ok_or(async_graphql::Error::new("No user"))?;
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let tokens = personal_access_token::table
+            .filter(personal_access_token::user_id.eq(user_id))
+            .order(personal_access_token::id.desc())
+            .load(&mut conn)?;
+
+        Ok(tokens)
+    }
+}
+
+#[Object]
+impl PersonalAccessTokenMutation {
+    /// Create a token for bots and scripts, allowed to read and do what its scopes declare
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn create_personal_access_token(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        scopes: Vec<TokenScope>,
+        expires: Option<Timestamptz>,
+    ) -> async_graphql::Result<CreatedPersonalAccessToken> {
+        let user = ctx
+            .data::<RequestCtx>()?
+            .get_user()
+            .ok_or(async_graphql::Error::new("No user"))?;
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let (personal_access_token, token) =
+            create_access_token(&mut conn, user.id, &name, &scopes, expires)?;
+
+        info!(
+            "createPersonalAccessToken: '{}' {:?} by User<{}:{}>",
+            &personal_access_token.name, &personal_access_token.scopes, &user.id, &user.nickname
+        );
+
+        Ok(CreatedPersonalAccessToken {
+            token,
+            personal_access_token,
+        })
+    }
+
+    /// Revoke a token of the logged in user, or of any user by staff
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn revoke_personal_access_token(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> async_graphql::Result<PersonalAccessToken> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let token: PersonalAccessToken = personal_access_token::table
+            .filter(personal_access_token::id.eq(id))
+            .first(&mut conn)?;
+        user_id_guard(ctx, token.user_id)?;
+
+        let token = diesel::update(&token)
+            .filter(personal_access_token::revoked.is_null())
+            .set(personal_access_token::revoked.eq(Some(Utc::now())))
+            .get_result(&mut conn)
+            .optional()?
+            .unwrap_or(token);
+
+        Ok(token)
+    }
+}
@@ -2,12 +2,14 @@ use async_graphql::{self, Context, InputObject, MaybeUndefined, Object, Subscrip
 use chrono::{Duration, TimeZone, Utc};
 use diesel::{
     prelude::*,
-    sql_types::{self, BigInt, Integer},
+    sql_types::{self, BigInt, Bool, Integer},
 };
 use futures::{Stream, StreamExt};
 use regex::Regex;
 use std::str::FromStr;
 
+use super::Audit;
+use super::{drop_tokenize_cache, record_revision, sync_tokenize_cache};
 use crate::context::{GlobalCtx, RequestCtx};
 use crate::models::puzzle::*;
 use crate::models::*;
@@ -30,16 +32,42 @@ lazy_static! {
 }
 const INVALID_DATETIME: &'static str = "Invalid Datetime";
 
+/// Assign all uploaded images referred in `text` to the puzzle.
+pub(crate) fn assign_referred_images(conn: &mut PgConnection, text: &str, puzzle_id: ID) {
+    use crate::schema::image;
+
+    for image_id in UPLOAD_IMAGE_PAT.captures_iter(text) {
+        let uuid_str = match uuid::Uuid::from_str(&image_id[1]) {
+            Ok(uuid_str) => uuid_str,
+            Err(_) => {
+                continue;
+            }
+        };
+        let result = diesel::update(image::table.filter(image::id.eq(uuid_str)))
+            .set(image::puzzle_id.eq(puzzle_id))
+            .execute(conn);
+        if let Err(e) = result {
+            info!("{:?}", e);
+            continue;
+        }
+    }
+}
+
 #[Object]
 impl PuzzleQuery {
     pub async fn puzzle(&self, ctx: &Context<'_>, id: i32) -> async_graphql::Result<Puzzle> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
 
-        let puzzle = puzzle::table
+        let puzzle: Puzzle = puzzle::table
             .filter(puzzle::id.eq(id))
             .limit(1)
             .first(&mut conn)?;
 
+        // Scheduled drafts are reported as missing to anyone but the author
+        if !puzzle.visible(ctx.data_opt::<RequestCtx>()) {
+            return Err(diesel::result::Error::NotFound.into());
+        }
+
         Ok(puzzle)
     }
 
@@ -54,15 +82,14 @@ impl PuzzleQuery {
         use crate::schema::puzzle::dsl::*;
 
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reqctx = ctx.data_opt::<RequestCtx>();
 
         let mut query = puzzle.into_boxed();
         if let Some(order) = order {
             query = PuzzleOrders::new(order).apply_order(query);
         }
-        if let Some(filter) = filter {
-            if let Some(filter_exp) = filter.as_expression() {
-                query = query.filter(filter_exp)
-            }
+        if let Some(filter_exp) = guarded_filter_expression(filter, reqctx) {
+            query = query.filter(filter_exp)
         }
         if let Some(limit) = limit {
             query = query.limit(limit);
@@ -84,12 +111,11 @@ impl PuzzleQuery {
         use crate::schema::puzzle::dsl::*;
 
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reqctx = ctx.data_opt::<RequestCtx>();
 
         let mut query = puzzle.into_boxed();
-        if let Some(filter) = filter {
-            if let Some(filter_exp) = filter.as_expression() {
-                query = query.filter(filter_exp)
-            }
+        if let Some(filter_exp) = guarded_filter_expression(filter, reqctx) {
+            query = query.filter(filter_exp)
         }
 
         let result = query.count().get_result(&mut conn)?;
@@ -103,10 +129,12 @@ impl PuzzleQuery {
         user_id: ID,
     ) -> async_graphql::Result<Vec<PuzzleCountByGenre>> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reveal_anonymous = user_id_guard(ctx, user_id).is_ok();
 
         let results: Vec<PuzzleCountByGenre> =
             diesel::sql_query(include_str!("../sql/puzzle_count_by_genre.sql"))
                 .bind::<Integer, _>(user_id)
+                .bind::<Bool, _>(reveal_anonymous)
                 .get_results(&mut conn)?;
 
         Ok(results)
@@ -118,10 +146,12 @@ impl PuzzleQuery {
         user_id: ID,
     ) -> async_graphql::Result<Vec<PuzzleStarAggrGroup>> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reveal_anonymous = user_id_guard(ctx, user_id).is_ok();
 
         let results: Vec<PuzzleStarAggrGroup> =
             diesel::sql_query(include_str!("../sql/puzzle_star_count_groups.sql"))
                 .bind::<Integer, _>(user_id)
+                .bind::<Bool, _>(reveal_anonymous)
                 .get_results(&mut conn)?;
 
         Ok(results)
@@ -133,10 +163,12 @@ impl PuzzleQuery {
         user_id: ID,
     ) -> async_graphql::Result<Vec<PuzzleStarAggrGroup>> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reveal_anonymous = user_id_guard(ctx, user_id).is_ok();
 
         let results: Vec<PuzzleStarAggrGroup> =
             diesel::sql_query(include_str!("../sql/puzzle_star_sum_groups.sql"))
                 .bind::<Integer, _>(user_id)
+                .bind::<Bool, _>(reveal_anonymous)
                 .get_results(&mut conn)?;
 
         Ok(results)
@@ -149,11 +181,20 @@ impl PuzzleQuery {
     ) -> async_graphql::Result<Vec<PuzzleParticipant>> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
 
-        let results: Vec<PuzzleParticipant> =
+        let mut results: Vec<PuzzleParticipant> =
             diesel::sql_query(include_str!("../sql/puzzle_participants.sql"))
                 .bind::<Integer, _>(puzzle_id)
                 .get_results(&mut conn)?;
 
+        // Do not leak the author of an anonymous puzzle through participants
+        let puzzle_inst: Puzzle = puzzle::table
+            .filter(puzzle::id.eq(puzzle_id))
+            .limit(1)
+            .first(&mut conn)?;
+        if !puzzle_inst.author_visible(ctx.data_opt::<RequestCtx>()) {
+            results.retain(|participant| participant.id != puzzle_inst.user_id);
+        }
+
         Ok(results)
     }
 
@@ -165,11 +206,13 @@ impl PuzzleQuery {
         offset: i64,
     ) -> async_graphql::Result<Vec<Puzzle>> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reveal_anonymous = user_id_guard(ctx, user_id).is_ok();
 
         let results: Vec<Puzzle> = diesel::sql_query(include_str!("../sql/puzzle_footprints.sql"))
             .bind::<Integer, _>(user_id)
             .bind::<BigInt, _>(limit)
             .bind::<BigInt, _>(offset)
+            .bind::<Bool, _>(reveal_anonymous)
             .get_results(&mut conn)?;
 
         Ok(results)
@@ -181,10 +224,12 @@ impl PuzzleQuery {
         user_id: ID,
     ) -> async_graphql::Result<i64> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reveal_anonymous = user_id_guard(ctx, user_id).is_ok();
 
         let result: PuzzleFootprintCount =
             diesel::sql_query(include_str!("../sql/puzzle_footprint_count.sql"))
                 .bind::<Integer, _>(user_id)
+                .bind::<Bool, _>(reveal_anonymous)
                 .get_result(&mut conn)?;
 
         Ok(result.count)
@@ -289,7 +334,7 @@ impl From<UpdatePuzzleInput> for UpdatePuzzleData {
 
 /// Calculate dazing duration of a puzzle
 #[derive(Default)]
-struct DazedTimeCalc {
+pub(crate) struct DazedTimeCalc {
     yami: Option<Yami>,
     genre: Option<Genre>,
 }
@@ -481,6 +526,16 @@ impl PuzzleMutation {
                         "Further edits are blocked from a forced hidden puzzle",
                     ));
                 };
+
+                // Scheduled drafts are published by their schedule
+                if set.status.is_some()
+                    && (puzzle_inst.status == Status::Scheduled
+                        || set.status == Some(Status::Scheduled))
+                {
+                    return Err(async_graphql::Error::new(
+                        "Status of a scheduled puzzle is managed by its schedule",
+                    ));
+                };
             }
             Role::Staff | Role::Admin => {}
             Role::Guest => return Err(async_graphql::Error::new("User not logged in")),
@@ -499,35 +554,34 @@ impl PuzzleMutation {
             && set.status.is_some()
             && set.status != Some(Status::Undergoing)
         {
-            use crate::schema::image;
-            for image_id in UPLOAD_IMAGE_PAT.captures_iter(&puzzle_inst.solution) {
-                let uuid_str = match uuid::Uuid::from_str(&image_id[1]) {
-                    Ok(uuid_str) => uuid_str,
-                    Err(_) => {
-                        continue;
-                    }
-                };
-                let result = diesel::update(image::table.filter(image::id.eq(uuid_str)))
-                    .set(image::puzzle_id.eq(puzzle_inst.id))
-                    .execute(&mut conn);
-                if let Err(e) = result {
-                    info!("{:?}", e);
-                    continue;
-                }
-            }
+            assign_referred_images(&mut conn, &puzzle_inst.solution, puzzle_inst.id);
         }
 
         // When a puzzle is solved, close all realtime update channels
         let key_starts_with = format!("puzzleLog<{}", puzzle_inst.id);
         CindyBroker::<PuzzleLogSub>::cleaup_all(|key| key.starts_with(&key_starts_with));
 
-        let puzzle: Puzzle = diesel::update(puzzle::table)
-            .filter(puzzle::id.eq(id))
-            .set(UpdatePuzzleData::from(set))
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+        let puzzle: Puzzle = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "puzzle", &[id])?;
+            let puzzle: Puzzle = diesel::update(puzzle::table)
+                .filter(puzzle::id.eq(id))
+                .set(UpdatePuzzleData::from(set))
+                .get_result(conn)?;
+            record_revision(conn, &puzzle_inst, &puzzle, reqctx.get_user_id())?;
+            audit.record(conn, AuditAction::Change, &[puzzle.id])?;
+
+            Ok(puzzle)
+        })?;
+
+        if puzzle.title != puzzle_inst.title || puzzle.content != puzzle_inst.content {
+            if let Err(e) = sync_tokenize_cache(&mut conn, &puzzle) {
+                info!("{:?}", e);
+            }
+        }
 
-        CindyBroker::publish(PuzzleSub::Updated(puzzle_inst, puzzle.clone()));
+        if puzzle.status != Status::Scheduled {
+            CindyBroker::publish(PuzzleSub::Updated(puzzle_inst, puzzle.clone()));
+        }
 
         Ok(puzzle)
     }
@@ -541,20 +595,53 @@ impl PuzzleMutation {
         set: UpdatePuzzleInput,
     ) -> async_graphql::Result<Vec<Puzzle>> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let editor_id = ctx.data::<RequestCtx>()?.get_user_id();
 
-        let puzzles: Vec<Puzzle> =
-            if let Some(filter_exp) = filter.and_then(|filter| filter.as_expression()) {
-                diesel::update(puzzle::table)
-                    .filter(filter_exp)
-                    .set(UpdatePuzzleData::from(set))
-                    .get_results(&mut conn)
-                    .map_err(|err| async_graphql::Error::from(err))?
-            } else {
-                diesel::update(puzzle::table)
-                    .set(UpdatePuzzleData::from(set))
-                    .get_results(&mut conn)
-                    .map_err(|err| async_graphql::Error::from(err))?
-            };
+        let (puzzles, retokenize) = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let mut query = puzzle::table.into_boxed();
+            if let Some(filter_exp) = filter.clone().and_then(|filter| filter.as_expression()) {
+                query = query.filter(filter_exp);
+            }
+            let origs: Vec<Puzzle> = query.load(conn)?;
+            let ids: Vec<ID> = origs.iter().map(|orig| orig.id).collect();
+            let audit = Audit::begin(ctx, conn, "puzzle", &ids)?;
+
+            let puzzles: Vec<Puzzle> =
+                if let Some(filter_exp) = filter.and_then(|filter| filter.as_expression()) {
+                    diesel::update(puzzle::table)
+                        .filter(filter_exp)
+                        .set(UpdatePuzzleData::from(set))
+                        .get_results(conn)?
+                } else {
+                    diesel::update(puzzle::table)
+                        .set(UpdatePuzzleData::from(set))
+                        .get_results(conn)?
+                };
+
+            let mut retokenize = Vec::new();
+            for puzzle in puzzles.iter() {
+                if let Some(orig) = origs.iter().find(|orig| orig.id == puzzle.id) {
+                    record_revision(conn, orig, puzzle, editor_id)?;
+                    if puzzle.title != orig.title || puzzle.content != orig.content {
+                        retokenize.push(puzzle.id);
+                    }
+                }
+            }
+
+            let updated_ids: Vec<ID> = puzzles.iter().map(|puzzle| puzzle.id).collect();
+            audit.record(conn, AuditAction::Change, &updated_ids)?;
+            Ok((puzzles, retokenize))
+        })?;
+
+        // Failing to sync the tokenize cache is only logged, as in `update_puzzle`
+        for puzzle in puzzles
+            .iter()
+            .filter(|puzzle| retokenize.contains(&puzzle.id))
+        {
+            if let Err(e) = sync_tokenize_cache(&mut conn, puzzle) {
+                info!("{:?}", e);
+            }
+        }
 
         // TODO Publish to subscriptions
         Ok(puzzles)
@@ -575,6 +662,12 @@ impl PuzzleMutation {
                 // Assert that time-related are unset
                 assert_eq_guard(data.created, None)?;
                 assert_eq_guard(data.modified, None)?;
+                // Drafts are created along with their schedule
+                if data.status == Some(Status::Scheduled) {
+                    return Err(async_graphql::Error::new(
+                        "Use `createSchedule` to schedule a puzzle",
+                    ));
+                }
                 // Assert user_id is set to the user
                 let insert_data = if let Some(user_id) = data.user_id {
                     user_id_guard(ctx, user_id)?;
@@ -595,7 +688,6 @@ impl PuzzleMutation {
             .map_err(|err| async_graphql::Error::from(err))?;
 
         // When a puzzle is created, assign all referred images in puzzle content
-        use crate::schema::image;
         let concated_string;
         let referring_text = if puzzle.yami == Yami::Longterm {
             concated_string = puzzle.content.clone() + &puzzle.solution;
@@ -603,23 +695,15 @@ impl PuzzleMutation {
         } else {
             &puzzle.content
         };
-        for image_id in UPLOAD_IMAGE_PAT.captures_iter(&referring_text) {
-            let uuid_str = match uuid::Uuid::from_str(&image_id[1]) {
-                Ok(uuid_str) => uuid_str,
-                Err(_) => {
-                    continue;
-                }
-            };
-            let result = diesel::update(image::table.filter(image::id.eq(uuid_str)))
-                .set(image::puzzle_id.eq(puzzle.id))
-                .execute(&mut conn);
-            if let Err(e) = result {
-                info!("{:?}", e);
-                continue;
-            }
+        assign_referred_images(&mut conn, referring_text, puzzle.id);
+
+        if let Err(e) = sync_tokenize_cache(&mut conn, &puzzle) {
+            info!("{:?}", e);
         }
 
-        CindyBroker::publish(PuzzleSub::Created(puzzle.clone()));
+        if puzzle.status != Status::Scheduled {
+            CindyBroker::publish(PuzzleSub::Created(puzzle.clone()));
+        }
 
         Ok(puzzle)
     }
@@ -628,25 +712,27 @@ impl PuzzleMutation {
     #[graphql(guard = "DenyRoleGuard::new(Role::User).and(DenyRoleGuard::new(Role::Guest))")]
     pub async fn delete_puzzle(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<Puzzle> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
-
         // When a puzzle is deleted, delete all referred images
         use crate::schema::image;
-        let images: Vec<Image> = image::table
-            .filter(image::puzzle_id.eq(id))
-            .select(image::all_columns)
-            .get_results(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+        let (puzzle, images) = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "puzzle", &[id])?;
+
+            let images: Vec<Image> =
+                diesel::delete(image::table.filter(image::puzzle_id.eq(id))).get_results(conn)?;
+
+            // Deletes the puzzle instance
+            drop_tokenize_cache(conn, &[id])?;
+            let puzzle =
+                diesel::delete(puzzle::table.filter(puzzle::id.eq(id))).get_result(conn)?;
+
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok((puzzle, images))
+        })?;
+
+        // Files are removed only once the rows are gone for good
         for im in images {
             im.delete_file().await?;
         }
-        diesel::delete(image::table.filter(image::puzzle_id.eq(id)))
-            .execute(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
-
-        // Deletes the puzzle instance
-        let puzzle = diesel::delete(puzzle::table.filter(puzzle::id.eq(id)))
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
 
         Ok(puzzle)
     }
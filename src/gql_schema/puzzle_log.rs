@@ -156,8 +156,13 @@ impl RawFilter<Hint> for PuzzleLogSubFilter {
 impl PuzzleLogSubscription {
     pub async fn puzzle_log_sub(
         &self,
+        ctx: &Context<'_>,
         filter: Option<PuzzleLogSubFilter>,
-    ) -> impl Stream<Item = Option<PuzzleLogSub>> {
+    ) -> async_graphql::Result<impl Stream<Item = Option<PuzzleLogSub>>> {
+        if let Some(user_id) = filter.as_ref().and_then(|filter| filter.user_id) {
+            user_id_guard(ctx, user_id)?;
+        }
+
         let key = if let Some(filter) = filter.as_ref() {
             if let Some(user_id) = filter.user_id {
                 format!("puzzleLog<{}-{}>", filter.puzzle_id, user_id)
@@ -167,21 +172,23 @@ impl PuzzleLogSubscription {
         } else {
             "puzzleLog".to_string()
         };
-        CindyBroker::<PuzzleLogSub>::subscribe_to(key).filter(move |puzzle_log_sub| {
-            let check = if let Some(filter) = filter.as_ref() {
-                match puzzle_log_sub {
-                    Some(PuzzleLogSub::DialogueCreated(obj)) => filter.check(obj),
-                    Some(PuzzleLogSub::HintCreated(obj)) => filter.check(obj),
-                    Some(PuzzleLogSub::DialogueUpdated(orig, _)) => filter.check(orig),
-                    Some(PuzzleLogSub::HintUpdated(orig, _)) => filter.check(orig),
-                    None => false,
-                }
-            } else {
-                puzzle_log_sub.is_some()
-            };
-
-            async move { check }
-        })
+        Ok(
+            CindyBroker::<PuzzleLogSub>::subscribe_to(key).filter(move |puzzle_log_sub| {
+                let check = if let Some(filter) = filter.as_ref() {
+                    match puzzle_log_sub {
+                        Some(PuzzleLogSub::DialogueCreated(obj)) => filter.check(obj),
+                        Some(PuzzleLogSub::HintCreated(obj)) => filter.check(obj),
+                        Some(PuzzleLogSub::DialogueUpdated(orig, _)) => filter.check(orig),
+                        Some(PuzzleLogSub::HintUpdated(orig, _)) => filter.check(orig),
+                        None => false,
+                    }
+                } else {
+                    puzzle_log_sub.is_some()
+                };
+
+                async move { check }
+            }),
+        )
     }
 
     pub async fn unsolved_puzzle_stats_sub(
@@ -0,0 +1,193 @@
+use async_graphql::{self, Context, Object};
+use diesel::prelude::*;
+
+use crate::context::{GlobalCtx, RequestCtx};
+use crate::models::puzzle::{author_visible_expression, draft_visible_expression};
+use crate::models::puzzle_revision::*;
+use crate::models::*;
+use crate::schema::{puzzle, puzzle_revision};
+
+#[derive(Default)]
+pub struct PuzzleRevisionQuery;
+
+#[derive(Insertable)]
+#[diesel(table_name = puzzle_revision)]
+struct CreatePuzzleRevisionData<'a> {
+    puzzle_id: ID,
+    user_id: Option<ID>,
+    content: &'a str,
+    solution: &'a str,
+    memo: &'a str,
+    created: Option<Timestamptz>,
+}
+
+/// Record a revision if `content`, `solution` or `memo` is changed by an edit.
+///
+/// On the first edit of a puzzle, its original state is recorded as well.
+pub(crate) fn record_revision(
+    conn: &mut PgConnection,
+    orig: &Puzzle,
+    puzzle: &Puzzle,
+    editor_id: Option<ID>,
+) -> QueryResult<()> {
+    if orig.content == puzzle.content
+        && orig.solution == puzzle.solution
+        && orig.memo == puzzle.memo
+    {
+        return Ok(());
+    }
+
+    let has_revision: bool = diesel::select(diesel::dsl::exists(
+        puzzle_revision::table.filter(puzzle_revision::puzzle_id.eq(puzzle.id)),
+    ))
+    .get_result(conn)?;
+    if !has_revision {
+        diesel::insert_into(puzzle_revision::table)
+            .values(&CreatePuzzleRevisionData {
+                puzzle_id: orig.id,
+                user_id: Some(orig.user_id),
+                content: &orig.content,
+                solution: &orig.solution,
+                memo: &orig.memo,
+                created: Some(orig.created),
+            })
+            .execute(conn)?;
+    }
+
+    diesel::insert_into(puzzle_revision::table)
+        .values(&CreatePuzzleRevisionData {
+            puzzle_id: puzzle.id,
+            user_id: editor_id,
+            content: &puzzle.content,
+            solution: &puzzle.solution,
+            memo: &puzzle.memo,
+            created: None,
+        })
+        .execute(conn)?;
+
+    Ok(())
+}
+
+#[Object]
+impl PuzzleRevisionQuery {
+    pub async fn puzzle_revision(
+        &self,
+        ctx: &Context<'_>,
+        id: i32,
+    ) -> async_graphql::Result<PuzzleRevision> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let revision: PuzzleRevision = puzzle_revision::table
+            .filter(puzzle_revision::id.eq(id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        let puzzle_inst: Puzzle = puzzle::table
+            .filter(puzzle::id.eq(revision.puzzle_id))
+            .limit(1)
+            .first(&mut conn)?;
+        if !puzzle_inst.visible(ctx.data_opt::<RequestCtx>()) {
+            return Err(diesel::result::Error::NotFound.into());
+        }
+
+        Ok(revision)
+    }
+
+    pub async fn puzzle_revisions(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        filter: Option<Vec<PuzzleRevisionFilter>>,
+        order: Option<Vec<PuzzleRevisionOrder>>,
+    ) -> async_graphql::Result<Vec<PuzzleRevision>> {
+        use crate::schema::puzzle_revision::dsl::*;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let mut query = puzzle_revision.into_boxed();
+        if let Some(restriction) = draft_visible_expression(ctx.data_opt::<RequestCtx>()) {
+            query = query.filter(
+                puzzle_id.eq_any(
+                    puzzle::table
+                        .select(puzzle::id)
+                        .filter(restriction)
+                        .into_boxed(),
+                ),
+            );
+        }
+        if let Some(order) = order {
+            query = PuzzleRevisionOrders::new(order).apply_order(query);
+        }
+        if let Some(filter) = filter {
+            // Filtering by the editor would reveal the authors of anonymous puzzles
+            if filter.iter().any(|filt| filt.user_id.is_some()) {
+                if let Some(restriction) = author_visible_expression(ctx.data_opt::<RequestCtx>()) {
+                    query = query.filter(
+                        puzzle_id.eq_any(
+                            puzzle::table
+                                .select(puzzle::id)
+                                .filter(restriction)
+                                .into_boxed(),
+                        ),
+                    );
+                }
+            }
+            if let Some(filter_exp) = filter.as_expression() {
+                query = query.filter(filter_exp)
+            }
+        }
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+        if let Some(offset) = offset {
+            query = query.offset(offset);
+        }
+
+        let revisions = query.load::<PuzzleRevision>(&mut conn)?;
+
+        Ok(revisions)
+    }
+
+    /// Differences from revision `from_id` to revision `to_id` of the same puzzle.
+    pub async fn puzzle_revision_diff(
+        &self,
+        ctx: &Context<'_>,
+        from_id: ID,
+        to_id: ID,
+    ) -> async_graphql::Result<PuzzleRevisionDiff> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let from: PuzzleRevision = puzzle_revision::table
+            .filter(puzzle_revision::id.eq(from_id))
+            .limit(1)
+            .first(&mut conn)?;
+        let to: PuzzleRevision = puzzle_revision::table
+            .filter(puzzle_revision::id.eq(to_id))
+            .limit(1)
+            .first(&mut conn)?;
+        if from.puzzle_id != to.puzzle_id {
+            return Err(async_graphql::Error::new(
+                "Revisions should belong to the same puzzle",
+            ));
+        }
+
+        let puzzle_inst: Puzzle = puzzle::table
+            .filter(puzzle::id.eq(from.puzzle_id))
+            .limit(1)
+            .first(&mut conn)?;
+        let reqctx = ctx.data_opt::<RequestCtx>();
+        if !puzzle_inst.visible(reqctx) {
+            return Err(diesel::result::Error::NotFound.into());
+        }
+        let spoiler_visible = puzzle_inst.spoiler_visible(reqctx);
+
+        Ok(PuzzleRevisionDiff {
+            from_id,
+            to_id,
+            content: DiffChunk::diff(&from.content, &to.content),
+            solution: spoiler_visible.then(|| DiffChunk::diff(&from.solution, &to.solution)),
+            memo: spoiler_visible.then(|| DiffChunk::diff(&from.memo, &to.memo)),
+        })
+    }
+}
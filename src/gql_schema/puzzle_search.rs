@@ -0,0 +1,142 @@
+use async_graphql::{self, Context, Object};
+use diesel::{
+    dsl::sql,
+    prelude::*,
+    sql_types::{Array, Bool, Float, Integer, Text},
+};
+
+use crate::context::{GlobalCtx, RequestCtx};
+use crate::models::puzzle::*;
+use crate::models::puzzle_search::*;
+use crate::models::*;
+use crate::schema::puzzle;
+
+/// Characters kept around the first match in content snippets
+const SNIPPET_RADIUS: usize = 60;
+
+#[derive(Default)]
+pub struct PuzzleSearchQuery;
+
+/// Store the search tokens of the puzzle in the tokenize cache.
+pub(crate) fn sync_tokenize_cache(conn: &mut PgConnection, puzzle: &Puzzle) -> QueryResult<()> {
+    let tokens = tokenize(&format!("{}\n{}", puzzle.title, puzzle.content));
+
+    diesel::sql_query(include_str!("../sql/upsert_tokenize_cache.sql"))
+        .bind::<Integer, _>(puzzle.id)
+        .bind::<Array<Text>, _>(tokens)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Drop the tokenize cache rows of deleted puzzles, which the foreign key would otherwise refuse.
+pub(crate) fn drop_tokenize_cache(conn: &mut PgConnection, puzzle_ids: &[ID]) -> QueryResult<()> {
+    use crate::schema::sui_hei_puzzle_tokenize_cache;
+
+    diesel::delete(
+        sui_hei_puzzle_tokenize_cache::table
+            .filter(sui_hei_puzzle_tokenize_cache::puzzle_id.eq_any(puzzle_ids)),
+    )
+    .execute(conn)?;
+
+    Ok(())
+}
+
+/// Relevance of a puzzle to the search query.
+///
+/// Full-text rank of the title and content, plus a bonus for every verbatim occurrence of the
+/// query, which is what ranks texts that the `simple` configuration cannot split into words.
+fn rank_expression(query: &str) -> Box<dyn BoxableExpression<puzzle::table, DB, SqlType = Float>> {
+    Box::new(
+        sql::<Float>(
+            "(ts_rank(to_tsvector('simple', puzzle.title || ' ' || puzzle.content), \
+             plainto_tsquery('simple', ",
+        )
+        .bind::<Text, _>(query.to_owned())
+        .sql(
+            ")) + 0.1 * (length(lower(puzzle.title || ' ' || puzzle.content)) \
+             - length(replace(lower(puzzle.title || ' ' || puzzle.content), lower(",
+        )
+        .bind::<Text, _>(query.to_owned())
+        .sql("), ''))) / greatest(length(")
+        .bind::<Text, _>(query.to_owned())
+        .sql("), 1))::real"),
+    )
+}
+
+/// Puzzles whose title or content matches the search query.
+///
+/// A puzzle matches by full-text search, by containing the query verbatim, or by having all
+/// tokens of the query in its tokenize cache.
+fn match_expression(
+    query: &str,
+    tokens: Vec<String>,
+) -> Box<dyn BoxableExpression<puzzle::table, DB, SqlType = Bool>> {
+    Box::new(
+        sql::<Bool>(
+            "(to_tsvector('simple', puzzle.title || ' ' || puzzle.content) \
+             @@ plainto_tsquery('simple', ",
+        )
+        .bind::<Text, _>(query.to_owned())
+        .sql(") OR strpos(lower(puzzle.title || ' ' || puzzle.content), lower(")
+        .bind::<Text, _>(query.to_owned())
+        .sql(
+            ")) > 0 OR EXISTS (SELECT 1 FROM sui_hei_puzzle_tokenize_cache \
+             WHERE sui_hei_puzzle_tokenize_cache.puzzle_id = puzzle.id \
+             AND sui_hei_puzzle_tokenize_cache.tokens ?& ",
+        )
+        .bind::<Array<Text>, _>(tokens)
+        .sql("))"),
+    )
+}
+
+#[Object]
+impl PuzzleSearchQuery {
+    /// Search puzzles by title and content, ordered by relevance.
+    pub async fn search_puzzles(
+        &self,
+        ctx: &Context<'_>,
+        query: String,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        filter: Option<Vec<PuzzleFilter>>,
+    ) -> async_graphql::Result<Vec<PuzzleSearchResult>> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reqctx = ctx.data_opt::<RequestCtx>();
+
+        let query = query.trim();
+        let tokens = tokenize(query);
+        if tokens.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut search = puzzle::table
+            .select((puzzle::all_columns, rank_expression(query)))
+            .filter(match_expression(query, tokens))
+            .order((rank_expression(query).desc(), puzzle::id.desc()))
+            .into_boxed();
+        if let Some(filter_exp) = guarded_filter_expression(filter, reqctx) {
+            search = search.filter(filter_exp)
+        }
+        if let Some(limit) = limit {
+            search = search.limit(limit);
+        }
+        if let Some(offset) = offset {
+            search = search.offset(offset);
+        }
+
+        let rows: Vec<(Puzzle, f32)> = search.load(&mut conn)?;
+
+        let results = rows
+            .into_iter()
+            .map(|(puzzle, rank)| PuzzleSearchResult {
+                title: SnippetChunk::snippet(&puzzle.title, query, None),
+                content: SnippetChunk::snippet(&puzzle.content, query, Some(SNIPPET_RADIUS)),
+                puzzle,
+                rank,
+            })
+            .collect();
+
+        Ok(results)
+    }
+}
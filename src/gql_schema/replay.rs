@@ -0,0 +1,300 @@
+use async_graphql::{self, Context, InputObject, Object};
+use diesel::prelude::*;
+
+use crate::auth::Role;
+use crate::context::{GlobalCtx, RequestCtx};
+use crate::models::puzzle_search::tokenize;
+use crate::models::replay::*;
+use crate::models::*;
+use crate::schema::{dialogue, puzzle, replay, replay_dialogue};
+
+#[derive(Default)]
+pub struct ReplayQuery;
+#[derive(Default)]
+pub struct ReplayMutation;
+
+#[Object]
+impl ReplayQuery {
+    pub async fn replay(&self, ctx: &Context<'_>, id: i32) -> async_graphql::Result<Replay> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let replay = replay::table
+            .filter(replay::id.eq(id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(replay)
+    }
+
+    pub async fn replays(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        filter: Option<Vec<ReplayFilter>>,
+        order: Option<Vec<ReplayOrder>>,
+    ) -> async_graphql::Result<Vec<Replay>> {
+        use crate::schema::replay::dsl::*;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let mut query = replay.into_boxed();
+        if let Some(order) = order {
+            query = ReplayOrders::new(order).apply_order(query);
+        }
+        if let Some(filter) = filter {
+            if let Some(filter_exp) = filter.as_expression() {
+                query = query.filter(filter_exp)
+            }
+        }
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+        if let Some(offset) = offset {
+            query = query.offset(offset);
+        }
+
+        let replays = query.load::<Replay>(&mut conn)?;
+
+        Ok(replays)
+    }
+
+    pub async fn replay_count(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<Vec<ReplayFilter>>,
+    ) -> async_graphql::Result<i64> {
+        use crate::schema::replay::dsl::*;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let mut query = replay.into_boxed();
+        if let Some(filter) = filter {
+            if let Some(filter_exp) = filter.as_expression() {
+                query = query.filter(filter_exp)
+            }
+        }
+
+        let result = query.count().get_result(&mut conn)?;
+
+        Ok(result)
+    }
+}
+
+#[derive(InputObject)]
+pub struct UpdateReplayInput {
+    pub title: Option<String>,
+    pub milestones: Option<Vec<ReplayMilestone>>,
+}
+
+#[derive(AsChangeset, Debug)]
+#[diesel(table_name = replay)]
+pub struct UpdateReplayData {
+    pub title: Option<String>,
+    pub milestones: Option<serde_json::Value>,
+}
+
+impl TryFrom<UpdateReplayInput> for UpdateReplayData {
+    type Error = serde_json::Error;
+
+    fn try_from(data: UpdateReplayInput) -> Result<Self, Self::Error> {
+        Ok(Self {
+            title: data.title,
+            milestones: data.milestones.map(serde_json::to_value).transpose()?,
+        })
+    }
+}
+
+#[derive(InputObject)]
+pub struct CreateReplayInput {
+    pub title: String,
+    #[graphql(default)]
+    pub milestones: Vec<ReplayMilestone>,
+    pub puzzle_id: Option<ID>,
+    pub user_id: Option<ID>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = replay)]
+pub struct CreateReplayData {
+    pub title: String,
+    pub milestones: serde_json::Value,
+    pub puzzle_id: Option<ID>,
+    pub user_id: ID,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = replay_dialogue)]
+struct SeedReplayDialogueData {
+    replay_id: ID,
+    question: String,
+    answer: String,
+    good: bool,
+    true_: bool,
+    keywords: serde_json::Value,
+    milestones: serde_json::Value,
+    dependency: String,
+}
+
+/// Assert that a replay of the puzzle does not reveal it before it is solved.
+///
+/// Undergoing puzzles are refused even to their authors, whose replays would be public.
+fn replayable_guard(
+    conn: &mut PgConnection,
+    reqctx: &RequestCtx,
+    puzzle_id: ID,
+) -> async_graphql::Result<Puzzle> {
+    let puzzle_inst: Puzzle = puzzle::table
+        .filter(puzzle::id.eq(puzzle_id))
+        .limit(1)
+        .first(conn)?;
+
+    if puzzle_inst.status == Status::Undergoing || !puzzle_inst.spoiler_visible(Some(reqctx)) {
+        return Err(async_graphql::Error::new(
+            "Replays can only be made from solved puzzles",
+        ));
+    }
+
+    Ok(puzzle_inst)
+}
+
+#[Object]
+impl ReplayMutation {
+    // Update replay
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn update_replay(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        set: UpdateReplayInput,
+    ) -> async_graphql::Result<Replay> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        // User should be the owner on update mutation
+        let replay_inst: Replay = replay::table
+            .filter(replay::id.eq(id))
+            .limit(1)
+            .first(&mut conn)?;
+        user_id_guard(ctx, replay_inst.user_id)?;
+
+        let replay: Replay = diesel::update(replay::table)
+            .filter(replay::id.eq(id))
+            .set(UpdateReplayData::try_from(set)?)
+            .get_result(&mut conn)
+            .map_err(|err| async_graphql::Error::from(err))?;
+
+        Ok(replay)
+    }
+
+    // Create replay
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn create_replay(
+        &self,
+        ctx: &Context<'_>,
+        data: CreateReplayInput,
+    ) -> async_graphql::Result<Replay> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reqctx = ctx.data::<RequestCtx>()?;
+
+        // Assert that the replay belongs to the user
+        let user_id = match data.user_id {
+            Some(user_id) => user_id,
+            None => reqctx
+                .get_user_id()
+                .ok_or(async_graphql::Error::new("No user"))?,
+        };
+        user_id_guard(ctx, user_id)?;
+        if let Some(puzzle_id) = data.puzzle_id {
+            replayable_guard(&mut conn, reqctx, puzzle_id)?;
+        }
+
+        let replay: Replay = diesel::insert_into(replay::table)
+            .values(&CreateReplayData {
+                title: data.title,
+                milestones: serde_json::to_value(data.milestones)?,
+                puzzle_id: data.puzzle_id,
+                user_id,
+            })
+            .get_result(&mut conn)
+            .map_err(|err| async_graphql::Error::from(err))?;
+
+        Ok(replay)
+    }
+
+    /// Create a replay from the dialogues of a solved puzzle.
+    ///
+    /// Each dialogue becomes a replay dialogue keyed by the tokens of its question.
+    /// Milestones and dependencies are left for the author to fill in.
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn seed_replay(
+        &self,
+        ctx: &Context<'_>,
+        puzzle_id: ID,
+        title: Option<String>,
+    ) -> async_graphql::Result<Replay> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reqctx = ctx.data::<RequestCtx>()?;
+        let user_id = reqctx
+            .get_user_id()
+            .ok_or(async_graphql::Error::new("No user"))?;
+
+        let puzzle_inst = replayable_guard(&mut conn, reqctx, puzzle_id)?;
+        let dialogues: Vec<Dialogue> = dialogue::table
+            .filter(dialogue::puzzle_id.eq(puzzle_id))
+            .order(dialogue::qno.asc())
+            .load(&mut conn)?;
+
+        let replay = conn
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                let replay: Replay = diesel::insert_into(replay::table)
+                    .values(&CreateReplayData {
+                        title: title.unwrap_or(puzzle_inst.title),
+                        milestones: serde_json::json!([]),
+                        puzzle_id: Some(puzzle_id),
+                        user_id,
+                    })
+                    .get_result(conn)?;
+
+                let replay_dialogues: Vec<SeedReplayDialogueData> = dialogues
+                    .into_iter()
+                    .map(|dialogue| SeedReplayDialogueData {
+                        replay_id: replay.id,
+                        keywords: serde_json::json!(tokenize(&dialogue.question)),
+                        question: dialogue.question,
+                        answer: dialogue.answer,
+                        good: dialogue.is_good,
+                        true_: dialogue.is_true,
+                        milestones: serde_json::json!([]),
+                        dependency: String::new(),
+                    })
+                    .collect();
+                diesel::insert_into(replay_dialogue::table)
+                    .values(&replay_dialogues)
+                    .execute(conn)?;
+
+                Ok(replay)
+            })
+            .map_err(|err| async_graphql::Error::from(err))?;
+
+        Ok(replay)
+    }
+
+    // Delete replay
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn delete_replay(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<Replay> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        // User should be the owner
+        let replay_inst: Replay = replay::table
+            .filter(replay::id.eq(id))
+            .limit(1)
+            .first(&mut conn)?;
+        user_id_guard(ctx, replay_inst.user_id)?;
+
+        let replay = diesel::delete(replay::table.filter(replay::id.eq(id)))
+            .get_result(&mut conn)
+            .map_err(|err| async_graphql::Error::from(err))?;
+
+        Ok(replay)
+    }
+}
@@ -0,0 +1,311 @@
+use async_graphql::{self, Context, InputObject, Object};
+use diesel::prelude::*;
+use std::collections::HashSet;
+
+use crate::auth::Role;
+use crate::context::GlobalCtx;
+use crate::models::puzzle_search::tokenize;
+use crate::models::replay_dialogue::*;
+use crate::models::*;
+use crate::schema::{replay, replay_dialogue};
+
+const DEFAULT_SUGGESTION_LIMIT: usize = 5;
+
+#[derive(Default)]
+pub struct ReplayDialogueQuery;
+#[derive(Default)]
+pub struct ReplayDialogueMutation;
+
+/// Ratio of the keywords found in the question.
+///
+/// A keyword is found if the question contains it verbatim, or contains all of its tokens.
+fn keyword_score(question: &str, question_tokens: &HashSet<String>, keywords: &[String]) -> f64 {
+    if keywords.is_empty() {
+        return 0.0;
+    }
+
+    let matched = keywords
+        .iter()
+        .filter(|keyword| {
+            let keyword = keyword.to_lowercase();
+            if keyword.is_empty() {
+                return false;
+            }
+            if question.contains(&keyword) {
+                return true;
+            }
+            let tokens = tokenize(&keyword);
+            !tokens.is_empty() && tokens.iter().all(|token| question_tokens.contains(token))
+        })
+        .count();
+
+    matched as f64 / keywords.len() as f64
+}
+
+/// Assert that the requester owns the replay.
+fn replay_owner_guard(
+    ctx: &Context<'_>,
+    conn: &mut PgConnection,
+    replay_id: ID,
+) -> async_graphql::Result<()> {
+    let replay_inst: Replay = replay::table
+        .filter(replay::id.eq(replay_id))
+        .limit(1)
+        .first(conn)?;
+    user_id_guard(ctx, replay_inst.user_id)
+}
+
+#[Object]
+impl ReplayDialogueQuery {
+    pub async fn replay_dialogue(
+        &self,
+        ctx: &Context<'_>,
+        id: i32,
+    ) -> async_graphql::Result<ReplayDialogue> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let replay_dialogue = replay_dialogue::table
+            .filter(replay_dialogue::id.eq(id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(replay_dialogue)
+    }
+
+    pub async fn replay_dialogues(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        filter: Option<Vec<ReplayDialogueFilter>>,
+        order: Option<Vec<ReplayDialogueOrder>>,
+    ) -> async_graphql::Result<Vec<ReplayDialogue>> {
+        use crate::schema::replay_dialogue::dsl::*;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let mut query = replay_dialogue.into_boxed();
+        if let Some(order) = order {
+            query = ReplayDialogueOrders::new(order).apply_order(query);
+        }
+        if let Some(filter) = filter {
+            if let Some(filter_exp) = filter.as_expression() {
+                query = query.filter(filter_exp)
+            }
+        }
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+        if let Some(offset) = offset {
+            query = query.offset(offset);
+        }
+
+        let replay_dialogues = query.load::<ReplayDialogue>(&mut conn)?;
+
+        Ok(replay_dialogues)
+    }
+
+    /// Suggest dialogues of the replay whose keywords overlap with the question.
+    pub async fn suggest_replay_dialogues(
+        &self,
+        ctx: &Context<'_>,
+        replay_id: ID,
+        question: String,
+        limit: Option<i32>,
+    ) -> async_graphql::Result<Vec<ReplayDialogueSuggestion>> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let question = question.to_lowercase();
+        let question_tokens: HashSet<String> = tokenize(&question).into_iter().collect();
+
+        let replay_dialogues: Vec<ReplayDialogue> = replay_dialogue::table
+            .filter(replay_dialogue::replay_id.eq(replay_id))
+            .load(&mut conn)?;
+
+        let mut suggestions: Vec<ReplayDialogueSuggestion> = replay_dialogues
+            .into_iter()
+            .map(|replay_dialogue| ReplayDialogueSuggestion {
+                score: keyword_score(&question, &question_tokens, &replay_dialogue.keyword_list()),
+                replay_dialogue,
+            })
+            .filter(|suggestion| suggestion.score > 0.0)
+            .collect();
+        suggestions.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then(a.replay_dialogue.id.cmp(&b.replay_dialogue.id))
+        });
+        suggestions.truncate(
+            limit
+                .and_then(|limit| usize::try_from(limit).ok())
+                .unwrap_or(DEFAULT_SUGGESTION_LIMIT),
+        );
+
+        Ok(suggestions)
+    }
+}
+
+#[derive(InputObject)]
+pub struct UpdateReplayDialogueInput {
+    pub question: Option<String>,
+    pub answer: Option<String>,
+    #[graphql(name = "good")]
+    pub is_good: Option<bool>,
+    #[graphql(name = "true")]
+    pub is_true: Option<bool>,
+    pub keywords: Option<Vec<String>>,
+    pub milestones: Option<Vec<String>>,
+    pub dependency: Option<String>,
+}
+
+#[derive(AsChangeset, Debug)]
+#[diesel(table_name = replay_dialogue)]
+pub struct UpdateReplayDialogueData {
+    pub question: Option<String>,
+    pub answer: Option<String>,
+    #[diesel(column_name = good)]
+    pub is_good: Option<bool>,
+    #[diesel(column_name = true_)]
+    pub is_true: Option<bool>,
+    pub keywords: Option<serde_json::Value>,
+    pub milestones: Option<serde_json::Value>,
+    pub dependency: Option<String>,
+}
+
+impl From<UpdateReplayDialogueInput> for UpdateReplayDialogueData {
+    fn from(data: UpdateReplayDialogueInput) -> Self {
+        Self {
+            question: data.question,
+            answer: data.answer,
+            is_good: data.is_good,
+            is_true: data.is_true,
+            keywords: data.keywords.map(|keywords| serde_json::json!(keywords)),
+            milestones: data
+                .milestones
+                .map(|milestones| serde_json::json!(milestones)),
+            dependency: data.dependency,
+        }
+    }
+}
+
+#[derive(InputObject)]
+pub struct CreateReplayDialogueInput {
+    pub replay_id: ID,
+    pub question: String,
+    #[graphql(default)]
+    pub answer: String,
+    #[graphql(default, name = "good")]
+    pub is_good: bool,
+    #[graphql(default, name = "true")]
+    pub is_true: bool,
+    /// Defaults to the tokens of the question
+    pub keywords: Option<Vec<String>>,
+    #[graphql(default)]
+    pub milestones: Vec<String>,
+    #[graphql(default)]
+    pub dependency: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = replay_dialogue)]
+pub struct CreateReplayDialogueData {
+    pub replay_id: ID,
+    pub question: String,
+    pub answer: String,
+    #[diesel(column_name = good)]
+    pub is_good: bool,
+    #[diesel(column_name = true_)]
+    pub is_true: bool,
+    pub keywords: serde_json::Value,
+    pub milestones: serde_json::Value,
+    pub dependency: String,
+}
+
+impl From<CreateReplayDialogueInput> for CreateReplayDialogueData {
+    fn from(data: CreateReplayDialogueInput) -> Self {
+        let keywords = data.keywords.unwrap_or_else(|| tokenize(&data.question));
+        Self {
+            replay_id: data.replay_id,
+            question: data.question,
+            answer: data.answer,
+            is_good: data.is_good,
+            is_true: data.is_true,
+            keywords: serde_json::json!(keywords),
+            milestones: serde_json::json!(data.milestones),
+            dependency: data.dependency,
+        }
+    }
+}
+
+#[Object]
+impl ReplayDialogueMutation {
+    // Update replay dialogue
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn update_replay_dialogue(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        set: UpdateReplayDialogueInput,
+    ) -> async_graphql::Result<ReplayDialogue> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        // User should be the owner of the replay on update mutation
+        let replay_dialogue_inst: ReplayDialogue = replay_dialogue::table
+            .filter(replay_dialogue::id.eq(id))
+            .limit(1)
+            .first(&mut conn)?;
+        replay_owner_guard(ctx, &mut conn, replay_dialogue_inst.replay_id)?;
+
+        let replay_dialogue: ReplayDialogue = diesel::update(replay_dialogue::table)
+            .filter(replay_dialogue::id.eq(id))
+            .set(UpdateReplayDialogueData::from(set))
+            .get_result(&mut conn)
+            .map_err(|err| async_graphql::Error::from(err))?;
+
+        Ok(replay_dialogue)
+    }
+
+    // Create replay dialogue
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn create_replay_dialogue(
+        &self,
+        ctx: &Context<'_>,
+        data: CreateReplayDialogueInput,
+    ) -> async_graphql::Result<ReplayDialogue> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        // User should be the owner of the replay
+        replay_owner_guard(ctx, &mut conn, data.replay_id)?;
+
+        let replay_dialogue: ReplayDialogue = diesel::insert_into(replay_dialogue::table)
+            .values(&CreateReplayDialogueData::from(data))
+            .get_result(&mut conn)
+            .map_err(|err| async_graphql::Error::from(err))?;
+
+        Ok(replay_dialogue)
+    }
+
+    // Delete replay dialogue
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn delete_replay_dialogue(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> async_graphql::Result<ReplayDialogue> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        // User should be the owner of the replay
+        let replay_dialogue_inst: ReplayDialogue = replay_dialogue::table
+            .filter(replay_dialogue::id.eq(id))
+            .limit(1)
+            .first(&mut conn)?;
+        replay_owner_guard(ctx, &mut conn, replay_dialogue_inst.replay_id)?;
+
+        let replay_dialogue =
+            diesel::delete(replay_dialogue::table.filter(replay_dialogue::id.eq(id)))
+                .get_result(&mut conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+        Ok(replay_dialogue)
+    }
+}
@@ -0,0 +1,275 @@
+use async_graphql::{self, Context, InputObject, Object};
+use chrono::Utc;
+use diesel::prelude::*;
+
+use super::puzzle::{assign_referred_images, CreatePuzzleData, CreatePuzzleInput};
+use super::{drop_tokenize_cache, sync_tokenize_cache};
+use crate::auth::Role;
+use crate::context::{GlobalCtx, RequestCtx};
+use crate::models::schedule::*;
+use crate::models::*;
+use crate::schema::{puzzle, schedule};
+
+#[derive(Default)]
+pub struct ScheduleQuery;
+#[derive(Default)]
+pub struct ScheduleMutation;
+
+#[Object]
+impl ScheduleQuery {
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn schedule(&self, ctx: &Context<'_>, id: i32) -> async_graphql::Result<Schedule> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let schedule: Schedule = schedule::table
+            .filter(schedule::id.eq(id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        // Schedules are private to their owner
+        user_id_guard(ctx, schedule.user_id)?;
+
+        Ok(schedule)
+    }
+
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn schedules(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        filter: Option<Vec<ScheduleFilter>>,
+        order: Option<Vec<ScheduleOrder>>,
+    ) -> async_graphql::Result<Vec<Schedule>> {
+        use crate::schema::schedule::dsl::*;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reqctx = ctx.data::<RequestCtx>()?;
+
+        let mut query = schedule.into_boxed();
+        if let Role::User = reqctx.get_role() {
+            query = query.filter(user_id.nullable().eq(reqctx.get_user_id()));
+        }
+        if let Some(order) = order {
+            query = ScheduleOrders::new(order).apply_order(query);
+        }
+        if let Some(filter) = filter {
+            if let Some(filter_exp) = filter.as_expression() {
+                query = query.filter(filter_exp)
+            }
+        }
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+        if let Some(offset) = offset {
+            query = query.offset(offset);
+        }
+
+        let schedules = query.load::<Schedule>(&mut conn)?;
+
+        Ok(schedules)
+    }
+
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn schedule_count(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<Vec<ScheduleFilter>>,
+    ) -> async_graphql::Result<i64> {
+        use crate::schema::schedule::dsl::*;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reqctx = ctx.data::<RequestCtx>()?;
+
+        let mut query = schedule.into_boxed();
+        if let Role::User = reqctx.get_role() {
+            query = query.filter(user_id.nullable().eq(reqctx.get_user_id()));
+        }
+        if let Some(filter) = filter {
+            if let Some(filter_exp) = filter.as_expression() {
+                query = query.filter(filter_exp)
+            }
+        }
+
+        let result = query.count().get_result(&mut conn)?;
+
+        Ok(result)
+    }
+}
+
+#[derive(InputObject, AsChangeset, Debug)]
+#[diesel(table_name = schedule)]
+pub struct UpdateScheduleInput {
+    pub content: Option<String>,
+    pub scheduled: Option<Timestamptz>,
+}
+
+#[derive(InputObject)]
+pub struct CreateScheduleInput {
+    pub content: Option<String>,
+    pub scheduled: Timestamptz,
+    pub user_id: Option<ID>,
+    /// The draft puzzle to publish at `scheduled`
+    pub puzzle: CreatePuzzleInput,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = schedule)]
+pub struct CreateScheduleData {
+    pub content: String,
+    pub scheduled: Timestamptz,
+    pub user_id: ID,
+    pub puzzle_id: Option<ID>,
+}
+
+#[Object]
+impl ScheduleMutation {
+    // Update schedule
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn update_schedule(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        set: UpdateScheduleInput,
+    ) -> async_graphql::Result<Schedule> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        // User should be the owner on update mutation
+        let schedule_inst: Schedule = schedule::table
+            .filter(schedule::id.eq(id))
+            .limit(1)
+            .first(&mut conn)?;
+        user_id_guard(ctx, schedule_inst.user_id)?;
+
+        if let Some(scheduled) = set.scheduled {
+            if scheduled <= Utc::now() {
+                return Err(async_graphql::Error::new(
+                    "Scheduled time should be in future",
+                ));
+            }
+        }
+
+        let schedule: Schedule = diesel::update(schedule::table)
+            .filter(schedule::id.eq(id))
+            .set(set)
+            .get_result(&mut conn)
+            .map_err(|err| async_graphql::Error::from(err))?;
+
+        Ok(schedule)
+    }
+
+    // Create schedule along with its draft puzzle
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn create_schedule(
+        &self,
+        ctx: &Context<'_>,
+        data: CreateScheduleInput,
+    ) -> async_graphql::Result<Schedule> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reqctx = ctx.data::<RequestCtx>()?;
+
+        let CreateScheduleInput {
+            content,
+            scheduled,
+            user_id,
+            puzzle: mut puzzle_data,
+        } = data;
+
+        if scheduled <= Utc::now() {
+            return Err(async_graphql::Error::new(
+                "Scheduled time should be in future",
+            ));
+        }
+
+        // Assert that the schedule and its puzzle belong to the user
+        let user_id = match user_id.or(puzzle_data.user_id) {
+            Some(user_id) => user_id,
+            None => reqctx
+                .get_user_id()
+                .ok_or(async_graphql::Error::new("No user"))?,
+        };
+        user_id_guard(ctx, user_id)?;
+        if let Role::User = reqctx.get_role() {
+            // Assert that time-related are unset
+            assert_eq_guard(puzzle_data.created, None)?;
+            assert_eq_guard(puzzle_data.modified, None)?;
+        }
+
+        // The draft is hidden until the schedule publishes it
+        puzzle_data.user_id = Some(user_id);
+        puzzle_data.status = Some(Status::Scheduled);
+        let insert_puzzle = CreatePuzzleData::from(puzzle_data.set_default());
+
+        let (schedule, puzzle) = conn
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                let puzzle: Puzzle = diesel::insert_into(puzzle::table)
+                    .values(&insert_puzzle)
+                    .get_result(conn)?;
+                let schedule: Schedule = diesel::insert_into(schedule::table)
+                    .values(&CreateScheduleData {
+                        content: content.unwrap_or_default(),
+                        scheduled,
+                        user_id,
+                        puzzle_id: Some(puzzle.id),
+                    })
+                    .get_result(conn)?;
+
+                Ok((schedule, puzzle))
+            })
+            .map_err(|err| async_graphql::Error::from(err))?;
+
+        // Assign all referred images in puzzle content to the draft
+        let concated_string;
+        let referring_text = if puzzle.yami == Yami::Longterm {
+            concated_string = puzzle.content.clone() + &puzzle.solution;
+            &concated_string
+        } else {
+            &puzzle.content
+        };
+        assign_referred_images(&mut conn, referring_text, puzzle.id);
+
+        if let Err(e) = sync_tokenize_cache(&mut conn, &puzzle) {
+            info!("{:?}", e);
+        }
+
+        Ok(schedule)
+    }
+
+    // Delete schedule along with its unpublished draft
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn delete_schedule(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> async_graphql::Result<Schedule> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        // User should be the owner
+        let schedule_inst: Schedule = schedule::table
+            .filter(schedule::id.eq(id))
+            .limit(1)
+            .first(&mut conn)?;
+        user_id_guard(ctx, schedule_inst.user_id)?;
+
+        let schedule = conn
+            .transaction::<_, diesel::result::Error, _>(|conn| {
+                let schedule: Schedule =
+                    diesel::delete(schedule::table.filter(schedule::id.eq(id))).get_result(conn)?;
+                if let Some(puzzle_id) = schedule.puzzle_id {
+                    let deleted: Vec<ID> = diesel::delete(
+                        puzzle::table
+                            .filter(puzzle::id.eq(puzzle_id))
+                            .filter(puzzle::status.eq(Status::Scheduled)),
+                    )
+                    .returning(puzzle::id)
+                    .get_results(conn)?;
+                    drop_tokenize_cache(conn, &deleted)?;
+                }
+
+                Ok(schedule)
+            })
+            .map_err(|err| async_graphql::Error::from(err))?;
+
+        Ok(schedule)
+    }
+}
@@ -2,6 +2,7 @@ use async_graphql::{self, Context, InputObject, Object};
 use chrono::Utc;
 use diesel::prelude::*;
 
+use super::Audit;
 use crate::auth::Role;
 use crate::context::GlobalCtx;
 use crate::models::tag::*;
@@ -79,7 +80,7 @@ pub struct CreateTagInput {
 
 #[Object]
 impl TagMutation {
-    #[graphql(guard = "DenyRoleGuard::new(Role::User).and(DenyRoleGuard::new(Role::Guest))")]
+    #[graphql(guard = "PermissionGuard::new(\"tag.change\")")]
     pub async fn update_tag(
         &self,
         ctx: &Context<'_>,
@@ -87,12 +88,18 @@ impl TagMutation {
         set: UpdateTagInput,
     ) -> async_graphql::Result<Tag> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let tag = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin_permitted(ctx, conn, "tag", &[id], "tag.change")?;
 
-        let tag: Tag = diesel::update(tag::table)
-            .filter(tag::id.eq(id))
-            .set(set)
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            let tag: Tag = diesel::update(tag::table)
+                .filter(tag::id.eq(id))
+                .set(set)
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Change, &[tag.id])?;
+            Ok(tag)
+        })?;
 
         Ok(tag)
     }
@@ -113,14 +120,20 @@ impl TagMutation {
         Ok(tag)
     }
 
-    // Delete tag
-    #[graphql(guard = "DenyRoleGuard::new(Role::User).and(DenyRoleGuard::new(Role::Guest))")]
+    // Delete tag (admin/staff/tag moderators only)
+    #[graphql(guard = "PermissionGuard::new(\"tag.delete\")")]
     pub async fn delete_tag(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<Tag> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let tag = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin_permitted(ctx, conn, "tag", &[id], "tag.delete")?;
 
-        let tag = diesel::delete(tag::table.filter(tag::id.eq(id)))
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            let tag = diesel::delete(tag::table.filter(tag::id.eq(id)))
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok(tag)
+        })?;
 
         Ok(tag)
     }
@@ -0,0 +1,149 @@
+use async_graphql::{self, Context, Object, SimpleObject};
+use diesel::prelude::*;
+
+use super::Audit;
+use crate::auth::throttle::{self, Lockout, ThrottleKind};
+use crate::auth::{two_factor, Role};
+use crate::context::{GlobalCtx, RequestCtx};
+use crate::models::*;
+
+#[derive(Default)]
+pub struct TwoFactorMutation;
+
+#[derive(SimpleObject)]
+pub struct TwoFactorEnrollment {
+    /// Base32 secret, for entering into the authenticator app by hand
+    pub secret: String,
+    /// `otpauth://` URI, usually shown as a QR code
+    pub provisioning_uri: String,
+}
+
+fn lockout_error(lockout: &Lockout) -> async_graphql::Error {
+    async_graphql::Error::new(format!(
+        "Too many attempts. Please try again in {} seconds.",
+        lockout.retry_after()
+    ))
+}
+
+/// Check a code of the logged in user, who should have two-factor authentication enabled.
+///
+/// Failed codes count towards the same lockout as failed logins to the username.
+fn verify_code(ctx: &Context<'_>, code: &str) -> async_graphql::Result<ID> {
+    let user = ctx
+        .data::<RequestCtx>()?
+        .get_user()
+        .ok_or(async_graphql::Error::new("No user"))?;
+    if let Some(lockout) = throttle::check(&[(ThrottleKind::LoginUsername, &user.username)]) {
+        return Err(lockout_error(&lockout));
+    }
+    let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+    if two_factor::verify(&mut conn, user.id, code)? {
+        Ok(user.id)
+    } else if let Some(lockout) = throttle::record(ThrottleKind::LoginUsername, &user.username) {
+        Err(lockout_error(&lockout))
+    } else {
+        Err(async_graphql::Error::new("Invalid code"))
+    }
+}
+
+#[Object]
+impl TwoFactorMutation {
+    /// Start enrolling in two-factor authentication, to be confirmed with `confirmTwoFactor`
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn enroll_two_factor(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<TwoFactorEnrollment> {
+        let user = ctx
+            .data::<RequestCtx>()?
+            .get_user()
+            .ok_or(async_graphql::Error::new("No user"))?;
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let (secret, provisioning_uri) = two_factor::enroll(&mut conn, user.id, &user.username)?;
+
+        Ok(TwoFactorEnrollment {
+            secret,
+            provisioning_uri,
+        })
+    }
+
+    /// Enable two-factor authentication with a code from the device, returning backup codes
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn confirm_two_factor(
+        &self,
+        ctx: &Context<'_>,
+        code: String,
+    ) -> async_graphql::Result<Vec<String>> {
+        let user = ctx
+            .data::<RequestCtx>()?
+            .get_user()
+            .ok_or(async_graphql::Error::new("No user"))?;
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let backup_codes = two_factor::confirm(&mut conn, user.id, &code)?;
+
+        info!("confirmTwoFactor: User<{}:{}>", &user.id, &user.nickname);
+
+        Ok(backup_codes)
+    }
+
+    /// Replace the backup codes, returning the new ones
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn regenerate_two_factor_backup_codes(
+        &self,
+        ctx: &Context<'_>,
+        code: String,
+    ) -> async_graphql::Result<Vec<String>> {
+        let user_id = verify_code(ctx, &code)?;
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        Ok(two_factor::regenerate_backup_codes(&mut conn, user_id)?)
+    }
+
+    /// Turn off two-factor authentication with a TOTP or backup code
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn disable_two_factor(
+        &self,
+        ctx: &Context<'_>,
+        code: String,
+    ) -> async_graphql::Result<bool> {
+        let user_id = verify_code(ctx, &code)?;
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let disabled = two_factor::disable(&mut conn, user_id)?;
+
+        info!("disableTwoFactor: User<{}>", user_id);
+
+        Ok(disabled)
+    }
+
+    /// Turn off two-factor authentication of a user who lost the device and backup codes
+    #[graphql(guard = "AdminRoleGuard::default()")]
+    pub async fn reset_two_factor(
+        &self,
+        ctx: &Context<'_>,
+        user_id: ID,
+    ) -> async_graphql::Result<bool> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let reset = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let reset = two_factor::disable(conn, user_id)?;
+            if reset {
+                Audit::note(
+                    ctx,
+                    conn,
+                    AuditAction::Deletion,
+                    &format!("two-factor authentication of user #{}", user_id),
+                    json!({ "enabled": { "old": true, "new": false } }),
+                )?;
+            }
+            Ok(reset)
+        })?;
+
+        info!("resetTwoFactor: User<{}>", user_id);
+
+        Ok(reset)
+    }
+}
@@ -5,6 +5,7 @@ use diesel::{
     sql_types::{self, Integer},
 };
 
+use super::Audit;
 use crate::auth::Role;
 use crate::context::{GlobalCtx, RequestCtx};
 use crate::models::user::*;
@@ -203,6 +204,7 @@ pub struct UpdateUserData {
     pub hide_bookmark: Option<bool>,
     pub icon: Option<Option<String>>,
     pub default_license_id: Option<Option<ID>>,
+    pub email_verified: Option<Option<Timestamptz>>,
 }
 
 impl From<UpdateUserSet> for UpdateUserData {
@@ -224,6 +226,7 @@ impl From<UpdateUserSet> for UpdateUserData {
             hide_bookmark: x.hide_bookmark,
             icon: x.icon.as_options(),
             default_license_id: x.default_license_id.as_options(),
+            email_verified: None,
         }
     }
 }
@@ -242,6 +245,8 @@ impl UserMutation {
 
         match role {
             Role::User => {
+                // A user may only update themselves
+                user_id_guard(ctx, id)?;
                 // Some fields shouldn't be modified by a user
                 assert_eq_guard_msg(
                     &set.password,
@@ -258,15 +263,57 @@ impl UserMutation {
                     &MaybeUndefined::Undefined,
                     "Setting last_login explicitly is prohibited",
                 )?;
+                assert_eq_guard_msg(
+                    &set.email,
+                    &None,
+                    "Setting email explicitly is prohibited, use updateEmail instead",
+                )?;
             }
             Role::Guest => return Err(async_graphql::Error::new("User not logged in")),
             _ => {}
         };
 
-        diesel::update(user::table)
-            .filter(user::id.eq(id))
-            .set(&UpdateUserData::from(set))
-            .get_result(&mut conn)
-            .map_err(|err| err.into())
+        // Privileges and activation are granted by admins only
+        if role != Role::Admin {
+            assert_eq_guard_msg(
+                &set.is_staff,
+                &None,
+                "Setting is_staff explicitly is prohibited",
+            )?;
+            assert_eq_guard_msg(
+                &set.is_superuser,
+                &None,
+                "Setting is_superuser explicitly is prohibited",
+            )?;
+            assert_eq_guard_msg(
+                &set.is_active,
+                &None,
+                "Setting is_active explicitly is prohibited",
+            )?;
+        }
+
+        let mut data = UpdateUserData::from(set);
+        if let Some(email) = data.email.as_ref() {
+            let current: String = user::table
+                .filter(user::id.eq(id))
+                .select(user::email)
+                .first(&mut conn)?;
+            // The verification was of the previous email
+            if *email != current {
+                data.email_verified = Some(None);
+            }
+        }
+
+        let user = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "user", &[id])?;
+            let user: User = diesel::update(user::table)
+                .filter(user::id.eq(id))
+                .set(&data)
+                .get_result(conn)?;
+            audit.record(conn, AuditAction::Change, &[user.id])?;
+            Ok(user)
+        })?;
+
+        Ok(user)
     }
 }
@@ -2,8 +2,9 @@ use async_graphql::{self, Context, InputObject, Object};
 use chrono::Utc;
 use diesel::prelude::*;
 
+use super::Audit;
 use crate::auth::Role;
-use crate::context::{GlobalCtx, RequestCtx};
+use crate::context::GlobalCtx;
 use crate::models::user_award::*;
 use crate::models::*;
 use crate::schema::user_award;
@@ -97,7 +98,7 @@ pub struct CreateUserAwardInput {
     #[graphql(default_with = "Utc::today().naive_utc()")]
     pub created: Date,
     pub award_id: ID,
-    pub user_id: Option<ID>,
+    pub user_id: ID,
 }
 
 #[Object]
@@ -111,45 +112,41 @@ impl UserAwardMutation {
         set: UpdateUserAwardInput,
     ) -> async_graphql::Result<UserAward> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let user_award = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "user_award", &[id])?;
 
-        let user_award: UserAward = diesel::update(user_award::table)
-            .filter(user_award::id.eq(id))
-            .set(set)
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            let user_award: UserAward = diesel::update(user_award::table)
+                .filter(user_award::id.eq(id))
+                .set(set)
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Change, &[user_award.id])?;
+            Ok(user_award)
+        })?;
 
         Ok(user_award)
     }
 
-    // Create user_award
-    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    // Create user_award (staff only, users apply for awards instead)
+    #[graphql(guard = "StaffRoleGuard::default()")]
     pub async fn create_user_award(
         &self,
         ctx: &Context<'_>,
-        mut data: CreateUserAwardInput,
+        data: CreateUserAwardInput,
     ) -> async_graphql::Result<UserAward> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
-        let reqctx = ctx.data::<RequestCtx>()?;
-        let user_id = reqctx.get_user_id();
-        let role = reqctx.get_role();
-
-        match role {
-            Role::User => {
-                // Assert user_id is set to the user
-                if let Some(user_id) = data.user_id {
-                    user_id_guard(ctx, user_id)?;
-                } else {
-                    data.user_id = user_id
-                };
-            }
-            Role::Staff | Role::Admin => {}
-            Role::Guest => return Err(async_graphql::Error::new("User not logged in")),
-        };
+        let user_award = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "user_award", &[])?;
+
+            let user_award: UserAward = diesel::insert_into(user_award::table)
+                .values(&data)
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
 
-        let user_award: UserAward = diesel::insert_into(user_award::table)
-            .values(&data)
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            audit.record(conn, AuditAction::Addition, &[user_award.id])?;
+            Ok(user_award)
+        })?;
 
         Ok(user_award)
     }
@@ -162,10 +159,16 @@ impl UserAwardMutation {
         id: ID,
     ) -> async_graphql::Result<UserAward> {
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let user_award = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "user_award", &[id])?;
+
+            let user_award = diesel::delete(user_award::table.filter(user_award::id.eq(id)))
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
 
-        let user_award = diesel::delete(user_award::table.filter(user_award::id.eq(id)))
-            .get_result(&mut conn)
-            .map_err(|err| async_graphql::Error::from(err))?;
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok(user_award)
+        })?;
 
         Ok(user_award)
     }
@@ -0,0 +1,268 @@
+use async_graphql::{self, Context, InputObject, MaybeUndefined, Object};
+use chrono::Utc;
+use diesel::prelude::*;
+
+use super::Audit;
+use crate::auth::Role;
+use crate::context::{GlobalCtx, RequestCtx};
+use crate::models::user_suspension::*;
+use crate::models::*;
+use crate::schema::user_suspension;
+
+#[derive(Default)]
+pub struct UserSuspensionQuery;
+#[derive(Default)]
+pub struct UserSuspensionMutation;
+
+#[Object]
+impl UserSuspensionQuery {
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn user_suspension(
+        &self,
+        ctx: &Context<'_>,
+        id: i32,
+    ) -> async_graphql::Result<UserSuspension> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let user_suspension: UserSuspension = user_suspension::table
+            .filter(user_suspension::id.eq(id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        // Suspensions are private to the suspended user and staff
+        user_id_guard(ctx, user_suspension.user_id)?;
+
+        Ok(user_suspension)
+    }
+
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn user_suspensions(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        filter: Option<Vec<UserSuspensionFilter>>,
+        order: Option<Vec<UserSuspensionOrder>>,
+    ) -> async_graphql::Result<Vec<UserSuspension>> {
+        use crate::schema::user_suspension::dsl::*;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reqctx = ctx.data::<RequestCtx>()?;
+
+        let mut query = user_suspension.into_boxed();
+        if let Role::User = reqctx.get_role() {
+            query = query.filter(user_id.nullable().eq(reqctx.get_user_id()));
+        }
+        if let Some(order) = order {
+            query = UserSuspensionOrders::new(order).apply_order(query);
+        }
+        if let Some(filter) = filter {
+            if let Some(filter_exp) = filter.as_expression() {
+                query = query.filter(filter_exp)
+            }
+        }
+        if let Some(limit) = limit {
+            query = query.limit(limit);
+        }
+        if let Some(offset) = offset {
+            query = query.offset(offset);
+        }
+
+        let user_suspensions = query.load::<UserSuspension>(&mut conn)?;
+
+        Ok(user_suspensions)
+    }
+
+    #[graphql(guard = "DenyRoleGuard::new(Role::Guest)")]
+    pub async fn user_suspension_count(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<Vec<UserSuspensionFilter>>,
+    ) -> async_graphql::Result<i64> {
+        use crate::schema::user_suspension::dsl::*;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let reqctx = ctx.data::<RequestCtx>()?;
+
+        let mut query = user_suspension.into_boxed();
+        if let Role::User = reqctx.get_role() {
+            query = query.filter(user_id.nullable().eq(reqctx.get_user_id()));
+        }
+        if let Some(filter) = filter {
+            if let Some(filter_exp) = filter.as_expression() {
+                query = query.filter(filter_exp)
+            }
+        }
+
+        let result = query.count().get_result(&mut conn)?;
+
+        Ok(result)
+    }
+}
+
+#[derive(InputObject, Debug)]
+pub struct UpdateUserSuspensionInput {
+    pub reason: Option<String>,
+    pub scope: Option<SuspensionScope>,
+    pub expires: MaybeUndefined<Timestamptz>,
+}
+
+#[derive(AsChangeset, Debug)]
+#[diesel(table_name = user_suspension)]
+pub struct UpdateUserSuspensionData {
+    pub reason: Option<String>,
+    pub scope: Option<SuspensionScope>,
+    pub expires: Option<Option<Timestamptz>>,
+}
+
+impl From<UpdateUserSuspensionInput> for UpdateUserSuspensionData {
+    fn from(data: UpdateUserSuspensionInput) -> Self {
+        Self {
+            reason: data.reason,
+            scope: data.scope,
+            expires: data.expires.as_options(),
+        }
+    }
+}
+
+#[derive(InputObject)]
+pub struct CreateUserSuspensionInput {
+    pub user_id: ID,
+    pub reason: String,
+    #[graphql(default_with = "SuspensionScope::Full")]
+    pub scope: SuspensionScope,
+    /// Leave empty for a permanent ban
+    pub expires: Option<Timestamptz>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = user_suspension)]
+pub struct CreateUserSuspensionData {
+    pub user_id: ID,
+    pub actor_id: Option<ID>,
+    pub reason: String,
+    pub scope: SuspensionScope,
+    pub created: Timestamptz,
+    pub expires: Option<Timestamptz>,
+}
+
+#[Object]
+impl UserSuspensionMutation {
+    // Update user_suspension
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn update_user_suspension(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        set: UpdateUserSuspensionInput,
+    ) -> async_graphql::Result<UserSuspension> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let user_suspension = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "user_suspension", &[id])?;
+
+            let user_suspension: UserSuspension = diesel::update(user_suspension::table)
+                .filter(user_suspension::id.eq(id))
+                .set(UpdateUserSuspensionData::from(set))
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Change, &[user_suspension.id])?;
+            Ok(user_suspension)
+        })?;
+
+        Ok(user_suspension)
+    }
+
+    /// Suspend the user, taking effect from the next request of the user.
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn create_user_suspension(
+        &self,
+        ctx: &Context<'_>,
+        data: CreateUserSuspensionInput,
+    ) -> async_graphql::Result<UserSuspension> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let actor_id = ctx.data::<RequestCtx>()?.get_user_id();
+        let user_suspension = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "user_suspension", &[])?;
+
+            let now = Utc::now();
+            if let Some(expires) = data.expires {
+                if expires <= now {
+                    return Err(async_graphql::Error::new("Expiry should be in future"));
+                }
+            }
+
+            let user_suspension: UserSuspension = diesel::insert_into(user_suspension::table)
+                .values(&CreateUserSuspensionData {
+                    user_id: data.user_id,
+                    actor_id,
+                    reason: data.reason,
+                    scope: data.scope,
+                    created: now,
+                    expires: data.expires,
+                })
+                .get_result(conn)
+                .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Addition, &[user_suspension.id])?;
+            Ok(user_suspension)
+        })?;
+
+        Ok(user_suspension)
+    }
+
+    /// Lift the suspension now, keeping it in the history of the user.
+    #[graphql(guard = "StaffRoleGuard::default()")]
+    pub async fn lift_user_suspension(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> async_graphql::Result<UserSuspension> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let user_suspension = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "user_suspension", &[id])?;
+
+            let now = Utc::now();
+            let user_suspension: UserSuspension = diesel::update(user_suspension::table)
+                .filter(user_suspension::id.eq(id))
+                .filter(
+                    user_suspension::expires
+                        .is_null()
+                        .or(user_suspension::expires.gt(now).assume_not_null()),
+                )
+                .set(user_suspension::expires.eq(Some(now)))
+                .get_result(conn)
+                .optional()
+                .map_err(|err| async_graphql::Error::from(err))?
+                .ok_or_else(|| async_graphql::Error::new("Suspension is not in effect"))?;
+
+            audit.record(conn, AuditAction::Change, &[user_suspension.id])?;
+            Ok(user_suspension)
+        })?;
+
+        Ok(user_suspension)
+    }
+
+    // Delete user_suspension
+    #[graphql(guard = "AdminRoleGuard::default()")]
+    pub async fn delete_user_suspension(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+    ) -> async_graphql::Result<UserSuspension> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+        let user_suspension = conn.transaction::<_, async_graphql::Error, _>(|conn| {
+            let audit = Audit::begin(ctx, conn, "user_suspension", &[id])?;
+
+            let user_suspension =
+                diesel::delete(user_suspension::table.filter(user_suspension::id.eq(id)))
+                    .get_result(conn)
+                    .map_err(|err| async_graphql::Error::from(err))?;
+
+            audit.record(conn, AuditAction::Deletion, &[])?;
+            Ok(user_suspension)
+        })?;
+
+        Ok(user_suspension)
+    }
+}
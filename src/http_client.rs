@@ -0,0 +1,83 @@
+use anyhow::{anyhow, Context, Result};
+use std::io::Read;
+use std::sync::Arc;
+use std::time::Duration;
+use ureq::native_tls::TlsConnector;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Responses larger than this (in bytes) are refused
+const MAX_BODY_SIZE: u64 = 1024 * 1024;
+
+lazy_static! {
+    /// Agent shared between requests, following redirects and picking up the proxy
+    /// from env vars like `HTTPS_PROXY`.
+    static ref AGENT: std::result::Result<ureq::Agent, String> = TlsConnector::new()
+        .map(|tls| {
+            ureq::AgentBuilder::new()
+                .tls_connector(Arc::new(tls))
+                .try_proxy_from_env(true)
+                .timeout(TIMEOUT)
+                .build()
+        })
+        .map_err(|error| error.to_string());
+}
+
+fn agent() -> Result<&'static ureq::Agent> {
+    AGENT
+        .as_ref()
+        .map_err(|error| anyhow!("Error initializing TLS: {}", error))
+}
+
+/// Response of an HTTP request.
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Parse the body as JSON, failing on non-2xx statuses.
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T> {
+        if !(200..300).contains(&self.status) {
+            return Err(anyhow!(
+                "HTTP {}: {}",
+                self.status,
+                String::from_utf8_lossy(&self.body)
+            ));
+        }
+        serde_json::from_slice(&self.body).context("Invalid JSON response")
+    }
+}
+
+/// GET the URL, blocking the thread.
+pub fn get(url: &str) -> Result<Response> {
+    let request = agent()?.get(url).set("Accept", "application/json");
+    read_response(request.call(), url)
+}
+
+/// POST the form to the URL, blocking the thread.
+pub fn post_form(url: &str, form: &[(&str, &str)]) -> Result<Response> {
+    let request = agent()?.post(url).set("Accept", "application/json");
+    read_response(request.send_form(form), url)
+}
+
+/// Read the response of any status, up to `MAX_BODY_SIZE`.
+fn read_response(result: Result<ureq::Response, ureq::Error>, url: &str) -> Result<Response> {
+    let response = match result {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+        Err(error) => return Err(anyhow!("Error requesting {}: {}", url, error)),
+    };
+
+    let status = response.status();
+    let mut body = vec![];
+    response
+        .into_reader()
+        .take(MAX_BODY_SIZE + 1)
+        .read_to_end(&mut body)
+        .with_context(|| format!("Error reading response from {}", url))?;
+    if body.len() as u64 > MAX_BODY_SIZE {
+        return Err(anyhow!("Response from {} is too large", url));
+    }
+
+    Ok(Response { status, body })
+}
@@ -0,0 +1,164 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// A mail to be delivered to a user.
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivery backend of mails.
+///
+/// Set with `MAILER` env var, or plugged in with `GlobalCtx::with_mailer`.
+pub trait Mailer: Send + Sync {
+    fn send(&self, mail: &Mail) -> Result<()>;
+}
+
+/// Write mails to the log, for local testing.
+#[derive(Default)]
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send(&self, mail: &Mail) -> Result<()> {
+        info!("Mail to <{}>: {}\n{}", &mail.to, &mail.subject, &mail.body);
+        Ok(())
+    }
+}
+
+/// Write each mail to a file in the folder.
+pub struct FileMailer {
+    folder: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(folder: impl Into<PathBuf>) -> Self {
+        Self {
+            folder: folder.into(),
+        }
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, mail: &Mail) -> Result<()> {
+        fs::create_dir_all(&self.folder).context("Error creating mail folder")?;
+        let path = self
+            .folder
+            .join(format!("{}.eml", Utc::now().format("%Y%m%dT%H%M%S%.6f")));
+        let content = format!(
+            "To: {}\r\nSubject: {}\r\n\r\n{}\r\n",
+            &mail.to, &mail.subject, &mail.body
+        );
+        fs::write(&path, content)
+            .with_context(|| format!("Error writing mail to {}", path.display()))
+    }
+}
+
+/// Send mails over plain SMTP without authentication, e.g. to a local mail catcher
+/// like MailHog in development, or a relay on the same host.
+pub struct SmtpMailer {
+    host: String,
+    port: u16,
+    from: String,
+}
+
+impl SmtpMailer {
+    pub fn new(host: impl Into<String>, port: u16, from: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            from: from.into(),
+        }
+    }
+}
+
+/// Read a reply of the server, failing unless its code is the expected one.
+fn smtp_reply(reader: &mut impl BufRead, expected: u16) -> Result<()> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(anyhow!("SMTP connection closed"));
+        }
+        let code: u16 = line
+            .get(..3)
+            .and_then(|code| code.parse().ok())
+            .ok_or(anyhow!("Invalid SMTP reply: {}", line.trim_end()))?;
+        // `250-...` continues a multiline reply, `250 ...` ends it
+        if line.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+        if code != expected {
+            return Err(anyhow!("SMTP error: {}", line.trim_end()));
+        }
+        return Ok(());
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, mail: &Mail) -> Result<()> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port))
+            .with_context(|| format!("Error connecting to SMTP {}:{}", &self.host, self.port))?;
+        stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(10)))?;
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        smtp_reply(&mut reader, 220)?;
+
+        let mut command = |line: String, expected: u16| -> Result<()> {
+            writer.write_all(line.as_bytes())?;
+            writer.write_all(b"\r\n")?;
+            smtp_reply(&mut reader, expected)
+        };
+
+        // Lines starting with a dot are escaped, so that they do not end the data
+        let body = mail
+            .body
+            .lines()
+            .map(|line| {
+                if line.starts_with('.') {
+                    format!(".{}", line)
+                } else {
+                    line.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\r\n");
+
+        command("EHLO localhost".to_string(), 250)?;
+        command(format!("MAIL FROM:<{}>", &self.from), 250)?;
+        command(format!("RCPT TO:<{}>", &mail.to), 250)?;
+        command("DATA".to_string(), 354)?;
+        command(
+            format!(
+                "From: {}\r\nTo: {}\r\nSubject: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n.",
+                &self.from, &mail.to, &mail.subject, body
+            ),
+            250,
+        )?;
+        command("QUIT".to_string(), 221)
+    }
+}
+
+/// Mailer configured by env vars, falling back to `LogMailer`.
+pub fn mailer_from_env() -> Box<dyn Mailer> {
+    match dotenv::var("MAILER").unwrap_or_default().as_str() {
+        "file" => Box::new(FileMailer::new(
+            dotenv::var("MAIL_FOLDER").unwrap_or("mails".to_string()),
+        )),
+        "smtp" => Box::new(SmtpMailer::new(
+            dotenv::var("SMTP_HOST").unwrap_or("localhost".to_string()),
+            dotenv::var("SMTP_PORT")
+                .ok()
+                .and_then(|port| port.parse().ok())
+                .unwrap_or(1025),
+            dotenv::var("MAIL_FROM").unwrap_or("noreply@localhost".to_string()),
+        )),
+        _ => Box::new(LogMailer),
+    }
+}
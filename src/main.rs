@@ -25,12 +25,21 @@ mod broker;
 pub mod context;
 pub mod db;
 pub mod gql_schema;
+mod http_client;
+pub mod mailer;
 mod schema;
 mod schema_view;
+mod tasks;
 
-use auth::{login, role_switch, signup, Role};
+use auth::cookie::{bearer_token, check_csrf, csrf_matches, CSRF_COOKIE, CSRF_HEADER, JWT_COOKIE};
+use auth::keyring::jwks;
+use auth::{
+    change_password, client_ip, confirm_reset_password, login, login_two_factor, logout,
+    oidc_callback, oidc_login, refresh, reset_password, role_switch, signup, signup_challenge,
+    verify_email, Role,
+};
 use context::{GlobalCtx, RequestCtx};
-use gql_schema::{CindySchema, MutationRoot, QueryRoot, SubscriptionRoot};
+use gql_schema::{CindySchema, MutationCheck, MutationRoot, QueryRoot, SubscriptionRoot};
 
 lazy_static! {
     pub static ref ADMIN_SECRET: String =
@@ -44,29 +53,32 @@ lazy_static! {
 
 async fn index(
     schema: web::Data<CindySchema>,
+    global_ctx: web::Data<GlobalCtx>,
     req: HttpRequest,
     gql_req: GraphQLRequest,
 ) -> GraphQLResponse {
     const DEFAULT_OP_NAME: &str = "_";
 
     let headers = req.headers();
-    let connection_info = req.connection_info();
-
-    // Authorization info
-    let token = headers.get("Authorization").and_then(|value| {
-        value
-            .to_str()
-            .ok()
-            // Drop `Bearer `
-            .and_then(|v| v.splitn(2, ' ').nth(1))
-            .map(|v| v.to_string())
-    });
+
+    // Authorization info, falling back to the session cookie
+    let header_token = headers
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(bearer_token);
+    let cookie_token = req
+        .cookie(JWT_COOKIE)
+        .map(|cookie| cookie.value().to_string());
+    let csrf_missing = header_token.is_none() && cookie_token.is_some() && !check_csrf(&req);
+    let token = header_token.or(cookie_token);
     let admin_secret = headers
         .get("X-CINDY-ADMIN-SECRET")
         .and_then(|value| value.to_str().map(|v| v.to_owned()).ok());
     let ctx = RequestCtx::default()
-        .with_token(token)
-        .with_secret(admin_secret);
+        .with_token(token, &global_ctx)
+        .with_csrf_missing(csrf_missing)
+        .with_secret(admin_secret)
+        .with_suspensions(&global_ctx);
 
     // Logging the IP address
     let gql_req = gql_req.into_inner();
@@ -74,14 +86,7 @@ async fn index(
         .operation_name
         .clone()
         .unwrap_or(DEFAULT_OP_NAME.to_string());
-    let ip_addr = if let Some(header_real_ip) = dotenv::var("HEADER_REAL_IP").ok() {
-        headers
-            .get(header_real_ip)
-            .and_then(|ip| ip.to_str().ok())
-            .or_else(|| connection_info.peer_addr())
-    } else {
-        connection_info.peer_addr()
-    };
+    let ip_addr = client_ip(&req);
     let user = match ctx.get_role() {
         Role::Admin => {
             if let Some(user) = ctx.get_user() {
@@ -119,10 +124,63 @@ async fn index(
 
 async fn index_ws(
     schema: web::Data<CindySchema>,
+    global_ctx: web::Data<GlobalCtx>,
     req: HttpRequest,
     payload: web::Payload,
 ) -> Result<HttpResponse> {
-    GraphQLSubscription::new(Schema::clone(&*schema)).start(&req, payload)
+    let headers = req.headers();
+
+    // Authorization info from the upgrade request, used when the
+    // `connection_init` payload does not carry any.
+    let header_token = headers
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(bearer_token);
+    let header_secret = headers
+        .get("X-CINDY-ADMIN-SECRET")
+        .and_then(|value| value.to_str().map(|v| v.to_owned()).ok());
+    // Session cookie, whose CSRF token is sent in the `connection_init` payload
+    let cookie_token = req
+        .cookie(JWT_COOKIE)
+        .map(|cookie| cookie.value().to_string());
+    let csrf_cookie = req
+        .cookie(CSRF_COOKIE)
+        .map(|cookie| cookie.value().to_string());
+    let global_ctx = GlobalCtx::clone(&global_ctx);
+
+    GraphQLSubscription::new(Schema::clone(&*schema))
+        .on_connection_init(move |value: serde_json::Value| async move {
+            // Accept both `{ Authorization }` and `{ headers: { Authorization } }`
+            let init_payload = value.get("headers").unwrap_or(&value);
+            let get_field = |key: &str| {
+                init_payload
+                    .get(key)
+                    .or_else(|| init_payload.get(key.to_lowercase()))
+                    .and_then(|v| v.as_str())
+            };
+
+            let token = get_field("Authorization")
+                .and_then(bearer_token)
+                .or(header_token);
+            let csrf_missing = token.is_none()
+                && cookie_token.is_some()
+                && !csrf_matches(csrf_cookie.as_deref(), get_field(CSRF_HEADER));
+            let token = token.or(cookie_token);
+            let admin_secret = get_field("X-CINDY-ADMIN-SECRET")
+                .map(|v| v.to_owned())
+                .or(header_secret);
+            let ctx = RequestCtx::default()
+                .with_token(token, &global_ctx)
+                .with_csrf_missing(csrf_missing)
+                .with_secret(admin_secret)
+                .with_suspensions(&global_ctx)
+                .with_persistent(true);
+
+            let mut data = async_graphql::Data::default();
+            data.insert(ctx);
+            Ok(data)
+        })
+        .start(&req, payload)
 }
 
 #[actix_rt::main]
@@ -134,6 +192,10 @@ async fn main() -> std::io::Result<()> {
             sleep(Duration::from_secs(60 * 60)).await;
             debug!("Cleaning up cache");
             broker::cleanup();
+            auth::throttle::cleanup();
+            auth::two_factor::cleanup();
+            auth::oidc::cleanup();
+            auth::challenge::cleanup();
         }
     });
 
@@ -153,13 +215,81 @@ async fn main() -> std::io::Result<()> {
         .init();
 
     let endpoint = dotenv::var("ENDPOINT").unwrap_or("127.0.0.1:8000".to_string());
+    auth::keyring::init();
     let ctx = GlobalCtx::default();
+
+    // Spawn puzzle dazer
+    let daze_ctx = ctx.clone();
+    tokio::spawn(async move {
+        loop {
+            use tokio::time::{sleep, Duration};
+            match tasks::daze_expired_puzzles(&daze_ctx) {
+                Ok(0) => {}
+                Ok(count) => info!("Dazed {} puzzle(s)", count),
+                Err(error) => error!("Error dazing puzzles: {:?}", error),
+            }
+            sleep(Duration::from_secs(10 * 60)).await;
+        }
+    });
+
+    // Fill in the tokenize cache for puzzle search
+    let tokenize_ctx = ctx.clone();
+    tokio::spawn(async move {
+        match tasks::refresh_tokenize_cache(&tokenize_ctx) {
+            Ok(0) => {}
+            Ok(count) => info!("Refreshed tokenize cache of {} puzzle(s)", count),
+            Err(error) => error!("Error refreshing tokenize cache: {:?}", error),
+        }
+    });
+
+    // Spawn scheduled puzzle publisher
+    let schedule_ctx = ctx.clone();
+    tokio::spawn(async move {
+        loop {
+            use tokio::time::{sleep, Duration};
+            match tasks::publish_scheduled_puzzles(&schedule_ctx) {
+                Ok(0) => {}
+                Ok(count) => info!("Published {} scheduled puzzle(s)", count),
+                Err(error) => error!("Error publishing scheduled puzzles: {:?}", error),
+            }
+            sleep(Duration::from_secs(30)).await;
+        }
+    });
+
+    // Spawn event closer granting event awards
+    let event_ctx = ctx.clone();
+    tokio::spawn(async move {
+        loop {
+            use tokio::time::{sleep, Duration};
+            match tasks::close_ended_events(&event_ctx) {
+                Ok(0) => {}
+                Ok(count) => info!("Closed {} event(s)", count),
+                Err(error) => error!("Error closing events: {:?}", error),
+            }
+            sleep(Duration::from_secs(60)).await;
+        }
+    });
+
+    // Spawn award evaluator granting awards by their requisition rules
+    let award_ctx = ctx.clone();
+    tokio::spawn(async move {
+        loop {
+            use tokio::time::{sleep, Duration};
+            match tasks::grant_rule_awards(&award_ctx) {
+                Ok(0) => {}
+                Ok(count) => info!("Granted {} award(s) by rule", count),
+                Err(error) => error!("Error granting awards by rule: {:?}", error),
+            }
+            sleep(Duration::from_secs(60 * 60)).await;
+        }
+    });
     let schema = Schema::build(
         QueryRoot::default(),
         MutationRoot::default(),
         SubscriptionRoot::default(),
     )
     .data(ctx.clone())
+    .extension(MutationCheck)
     .finish();
 
     info!("Server started on: http://{}/graphql", &endpoint);
@@ -211,6 +341,7 @@ async fn main() -> std::io::Result<()> {
             })
             .allowed_methods(vec!["GET", "POST", "OPTIONS"])
             .allow_any_header()
+            .supports_credentials()
             .max_age(3600);
 
         App::new()
@@ -218,8 +349,55 @@ async fn main() -> std::io::Result<()> {
             .app_data(Data::new(schema.clone()))
             .app_data(Data::new(ctx.clone()))
             .service(web::resource("/graphql").guard(guard::Post()).to(index))
+            .service(
+                web::resource("/.well-known/jwks.json")
+                    .guard(guard::Get())
+                    .to(jwks),
+            )
             .service(web::resource("/login").guard(guard::Post()).to(login))
+            .service(
+                web::resource("/login/two_factor")
+                    .guard(guard::Post())
+                    .to(login_two_factor),
+            )
+            .service(
+                web::resource("/oidc/{provider}/login")
+                    .guard(guard::Get())
+                    .to(oidc_login),
+            )
+            .service(
+                web::resource("/oidc/{provider}/callback")
+                    .guard(guard::Get())
+                    .to(oidc_callback),
+            )
+            .service(web::resource("/refresh").guard(guard::Post()).to(refresh))
+            .service(web::resource("/logout").guard(guard::Post()).to(logout))
             .service(web::resource("/signup").guard(guard::Post()).to(signup))
+            .service(
+                web::resource("/signup/challenge")
+                    .guard(guard::Get())
+                    .to(signup_challenge),
+            )
+            .service(
+                web::resource("/change_password")
+                    .guard(guard::Post())
+                    .to(change_password),
+            )
+            .service(
+                web::resource("/reset_password")
+                    .guard(guard::Post())
+                    .to(reset_password),
+            )
+            .service(
+                web::resource("/reset_password/confirm")
+                    .guard(guard::Post())
+                    .to(confirm_reset_password),
+            )
+            .service(
+                web::resource("/verify_email")
+                    .guard(guard::Post())
+                    .to(verify_email),
+            )
             .service(
                 web::resource("/role_switch")
                     .guard(guard::Post())
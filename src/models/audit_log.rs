@@ -0,0 +1,207 @@
+use async_graphql::{self, Context, Enum, InputObject, Json, Object};
+use diesel::{prelude::*, query_dsl::QueryDsl, sql_types::Bool};
+
+use crate::context::GlobalCtx;
+use crate::schema::{django_admin_log, django_content_type};
+
+use super::*;
+
+/// Available orders for audit log query
+#[derive(InputObject, Clone)]
+pub struct AuditLogOrder {
+    id: Option<Ordering>,
+    action_time: Option<Ordering>,
+}
+
+/// Helper object to apply the order to the query
+pub struct AuditLogOrders(Vec<AuditLogOrder>);
+
+impl Default for AuditLogOrders {
+    fn default() -> Self {
+        Self(vec![])
+    }
+}
+
+impl AuditLogOrders {
+    pub fn new(orders: Vec<AuditLogOrder>) -> Self {
+        Self(orders)
+    }
+
+    pub fn apply_order<'a>(
+        self,
+        query_dsl: django_admin_log::BoxedQuery<'a, DB>,
+    ) -> django_admin_log::BoxedQuery<'a, DB> {
+        use crate::schema::django_admin_log::dsl::*;
+
+        let mut query = query_dsl;
+
+        for obj in self.0 {
+            gen_order!(obj, id, query);
+            gen_order!(obj, action_time, query);
+        }
+
+        query
+    }
+}
+
+/// Available filters for audit log query
+#[derive(InputObject, Clone, Default)]
+pub struct AuditLogFilter {
+    pub id: Option<I32Filtering>,
+    pub action_time: Option<TimestamptzFiltering>,
+    pub action_flag: Option<I16Filtering>,
+    pub content_type_id: Option<NullableI32Filtering>,
+    /// The staff member who made the change
+    pub user_id: Option<I32Filtering>,
+    /// Model name of the changed objects, e.g. `puzzle`
+    pub entity: Option<String>,
+    /// Id of the changed object
+    pub entity_id: Option<ID>,
+}
+
+impl CindyFilter<django_admin_log::table> for AuditLogFilter {
+    fn as_expression(
+        self,
+    ) -> Option<Box<dyn BoxableExpression<django_admin_log::table, DB, SqlType = Bool>>> {
+        use crate::schema::django_admin_log::dsl::*;
+
+        let mut filter: Option<Box<dyn BoxableExpression<django_admin_log, DB, SqlType = Bool>>> =
+            None;
+        let AuditLogFilter {
+            id: obj_id,
+            action_time: obj_action_time,
+            action_flag: obj_action_flag,
+            content_type_id: obj_content_type_id,
+            user_id: obj_user_id,
+            entity: obj_entity,
+            entity_id: obj_entity_id,
+        } = self;
+        gen_number_filter!(obj_id: I32Filtering, id, filter);
+        gen_number_filter!(obj_action_time: TimestamptzFiltering, action_time, filter);
+        gen_number_filter!(obj_action_flag: I16Filtering, action_flag, filter);
+        gen_nullable_number_filter!(
+            obj_content_type_id: NullableI32Filtering,
+            content_type_id,
+            filter
+        );
+        gen_number_filter!(obj_user_id: I32Filtering, user_id, filter);
+        if let Some(entity) = obj_entity {
+            let entity_exp = content_type_id.assume_not_null().eq_any(
+                django_content_type::table
+                    .filter(django_content_type::model.eq(entity))
+                    .select(django_content_type::id),
+            );
+            filter = Some(if let Some(filt_) = filter {
+                Box::new(filt_.and(entity_exp))
+            } else {
+                Box::new(entity_exp)
+            });
+        }
+        if let Some(entity_id) = obj_entity_id {
+            let entity_id_exp = object_id.assume_not_null().eq(entity_id.to_string());
+            filter = Some(if let Some(filt_) = filter {
+                Box::new(filt_.and(entity_id_exp))
+            } else {
+                Box::new(entity_id_exp)
+            });
+        }
+        filter
+    }
+}
+
+/// Action flags shared with the django admin
+#[repr(i16)]
+#[derive(Enum, Eq, PartialEq, Clone, Copy, Debug)]
+pub enum AuditAction {
+    Addition = 1,
+    Change = 2,
+    Deletion = 3,
+}
+
+impl AuditAction {
+    fn from_flag(flag: i16) -> Option<Self> {
+        match flag {
+            1 => Some(AuditAction::Addition),
+            2 => Some(AuditAction::Change),
+            3 => Some(AuditAction::Deletion),
+            _ => None,
+        }
+    }
+}
+
+/// Object for django_admin_log table
+#[derive(Queryable, Identifiable, Clone, Debug)]
+#[diesel(table_name = django_admin_log)]
+pub struct AuditLog {
+    pub id: ID,
+    pub action_time: Timestamptz,
+    pub object_id: Option<String>,
+    pub object_repr: String,
+    pub action_flag: i16,
+    pub change_message: String,
+    pub content_type_id: Option<ID>,
+    pub user_id: ID,
+}
+
+#[Object]
+impl AuditLog {
+    async fn id(&self) -> ID {
+        self.id
+    }
+    async fn action_time(&self) -> Timestamptz {
+        self.action_time
+    }
+    async fn object_id(&self) -> Option<&str> {
+        self.object_id.as_deref()
+    }
+    async fn object_repr(&self) -> &str {
+        &self.object_repr
+    }
+    async fn action(&self) -> Option<AuditAction> {
+        AuditAction::from_flag(self.action_flag)
+    }
+    /// Changed fields in the form of `{ field: { old, new } }`
+    ///
+    /// Entries written by the django admin are kept as is under `message`.
+    async fn diff(&self) -> Json<serde_json::Value> {
+        Json(
+            serde_json::from_str(&self.change_message)
+                .unwrap_or_else(|_| serde_json::json!({ "message": self.change_message })),
+        )
+    }
+    async fn user_id(&self) -> ID {
+        self.user_id
+    }
+
+    /// Model name of the changed object, e.g. `puzzle`
+    async fn entity(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<String>> {
+        use crate::schema::django_content_type;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let entity = if let Some(content_type_id) = self.content_type_id {
+            django_content_type::table
+                .filter(django_content_type::id.eq(content_type_id))
+                .select(django_content_type::model)
+                .first(&mut conn)
+                .optional()?
+        } else {
+            None
+        };
+
+        Ok(entity)
+    }
+
+    async fn user(&self, ctx: &Context<'_>) -> async_graphql::Result<User> {
+        use crate::schema::user;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let user_inst = user::table
+            .filter(user::id.eq(self.user_id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(user_inst)
+    }
+}
@@ -0,0 +1,245 @@
+use async_graphql::{self, Context, Enum, InputObject, Object};
+use byteorder::{NetworkEndian, WriteBytesExt};
+use diesel::{
+    backend::Backend,
+    deserialize::{self, FromSql},
+    expression::AsExpression,
+    prelude::*,
+    query_dsl::QueryDsl,
+    serialize::{self, IsNull, Output, ToSql},
+    sql_types::{Bool, Integer},
+};
+use std::error::Error;
+
+use crate::context::GlobalCtx;
+use crate::schema::award_application;
+
+use super::*;
+
+/// Available orders for award_application query
+#[derive(InputObject, Clone)]
+pub struct AwardApplicationOrder {
+    id: Option<Ordering>,
+    created: Option<Ordering>,
+    reviewed: Option<Ordering>,
+}
+
+/// Helper object to apply the order to the query
+pub struct AwardApplicationOrders(Vec<AwardApplicationOrder>);
+
+impl Default for AwardApplicationOrders {
+    fn default() -> Self {
+        Self(vec![])
+    }
+}
+
+impl AwardApplicationOrders {
+    pub fn new(orders: Vec<AwardApplicationOrder>) -> Self {
+        Self(orders)
+    }
+
+    pub fn apply_order<'a>(
+        self,
+        query_dsl: award_application::BoxedQuery<'a, DB>,
+    ) -> award_application::BoxedQuery<'a, DB> {
+        use crate::schema::award_application::dsl::*;
+
+        let mut query = query_dsl;
+
+        for obj in self.0 {
+            gen_order!(obj, id, query);
+            gen_order!(obj, created, query);
+            gen_order!(obj, reviewed, query);
+        }
+
+        query
+    }
+}
+
+/// Available filters for award_application query
+#[derive(InputObject, Clone, Default)]
+pub struct AwardApplicationFilter {
+    pub id: Option<I32Filtering>,
+    pub status: Option<AwardApplicationStatusFiltering>,
+    pub created: Option<TimestamptzFiltering>,
+    pub reviewed: Option<NullableTimestamptzFiltering>,
+    pub applier_id: Option<I32Filtering>,
+    pub award_id: Option<I32Filtering>,
+    pub reviewer_id: Option<NullableI32Filtering>,
+}
+
+impl CindyFilter<award_application::table> for AwardApplicationFilter {
+    fn as_expression(
+        self,
+    ) -> Option<Box<dyn BoxableExpression<award_application::table, DB, SqlType = Bool>>> {
+        use crate::schema::award_application::dsl::*;
+
+        let mut filter: Option<Box<dyn BoxableExpression<award_application, DB, SqlType = Bool>>> =
+            None;
+        let AwardApplicationFilter {
+            id: obj_id,
+            status: obj_status,
+            created: obj_created,
+            reviewed: obj_reviewed,
+            applier_id: obj_applier_id,
+            award_id: obj_award_id,
+            reviewer_id: obj_reviewer_id,
+        } = self;
+        gen_number_filter!(obj_id: I32Filtering, id, filter);
+        gen_enum_filter!(obj_status: AwardApplicationStatusFiltering, status, filter);
+        gen_number_filter!(obj_created: TimestamptzFiltering, created, filter);
+        gen_nullable_number_filter!(obj_reviewed: NullableTimestamptzFiltering, reviewed, filter);
+        gen_number_filter!(obj_applier_id: I32Filtering, applier_id, filter);
+        gen_number_filter!(obj_award_id: I32Filtering, award_id, filter);
+        gen_nullable_number_filter!(obj_reviewer_id: NullableI32Filtering, reviewer_id, filter);
+        filter
+    }
+}
+
+#[repr(i32)]
+#[derive(Enum, Eq, PartialEq, Clone, Copy, Debug, FromSqlRow, AsExpression)]
+#[diesel(sql_type = Integer)]
+pub enum AwardApplicationStatus {
+    Pending = 0,
+    Approved = 1,
+    Rejected = 2,
+}
+
+#[derive(InputObject, Eq, PartialEq, Clone)]
+pub struct AwardApplicationStatusFiltering {
+    pub eq: Option<AwardApplicationStatus>,
+    pub ne: Option<AwardApplicationStatus>,
+    pub eq_any: Option<Vec<AwardApplicationStatus>>,
+    pub ne_all: Option<Vec<AwardApplicationStatus>>,
+}
+
+impl RawFilter<AwardApplicationStatus> for AwardApplicationStatusFiltering {
+    fn check(&self, item: &AwardApplicationStatus) -> bool {
+        if let Some(eq) = self.eq.as_ref() {
+            item == eq
+        } else if let Some(ne) = self.ne.as_ref() {
+            item != ne
+        } else if let Some(eq_any) = self.eq_any.as_ref() {
+            eq_any.iter().any(|u| u == item)
+        } else if let Some(ne_all) = self.ne_all.as_ref() {
+            ne_all.iter().all(|u| u != item)
+        } else {
+            true
+        }
+    }
+}
+
+impl ToSql<Integer, DB> for AwardApplicationStatus {
+    fn to_sql(&self, out: &mut Output<DB>) -> serialize::Result {
+        out.write_i32::<NetworkEndian>(*self as i32)
+            .map(|_| IsNull::No)
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)
+    }
+}
+
+impl<DB> FromSql<Integer, DB> for AwardApplicationStatus
+where
+    DB: Backend,
+    i32: FromSql<Integer, DB>,
+{
+    fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+        match i32::from_sql(bytes)? {
+            0 => Ok(AwardApplicationStatus::Pending),
+            1 => Ok(AwardApplicationStatus::Approved),
+            2 => Ok(AwardApplicationStatus::Rejected),
+            v => Err(format!("Invalid value `{}` for award application status", &v).into()),
+        }
+    }
+}
+
+/// Object for award_application table
+#[derive(Queryable, Identifiable, Clone, Debug)]
+#[diesel(table_name = award_application)]
+pub struct AwardApplication {
+    pub id: ID,
+    pub status: AwardApplicationStatus,
+    pub comment: String,
+    pub created: Timestamptz,
+    pub reviewed: Option<Timestamptz>,
+    pub applier_id: ID,
+    pub award_id: ID,
+    pub reviewer_id: Option<ID>,
+    pub reason: String,
+}
+
+#[Object]
+impl AwardApplication {
+    async fn id(&self) -> ID {
+        self.id
+    }
+    async fn status(&self) -> AwardApplicationStatus {
+        self.status
+    }
+    /// Comment of the reviewer
+    async fn comment(&self) -> &str {
+        &self.comment
+    }
+    async fn created(&self) -> Timestamptz {
+        self.created
+    }
+    async fn reviewed(&self) -> Option<Timestamptz> {
+        self.reviewed
+    }
+    async fn applier_id(&self) -> ID {
+        self.applier_id
+    }
+    async fn award_id(&self) -> ID {
+        self.award_id
+    }
+    async fn reviewer_id(&self) -> Option<ID> {
+        self.reviewer_id
+    }
+    /// Reason of the applier
+    async fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    async fn applier(&self, ctx: &Context<'_>) -> async_graphql::Result<User> {
+        use crate::schema::user;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let user_inst = user::table
+            .filter(user::id.eq(self.applier_id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(user_inst)
+    }
+
+    async fn award(&self, ctx: &Context<'_>) -> async_graphql::Result<Award> {
+        use crate::schema::award;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let award_inst = award::table
+            .filter(award::id.eq(self.award_id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(award_inst)
+    }
+
+    async fn reviewer(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<User>> {
+        use crate::schema::user;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let user_inst = if let Some(id) = self.reviewer_id {
+            user::table
+                .filter(user::id.eq(id))
+                .limit(1)
+                .first(&mut conn)
+                .ok()
+        } else {
+            None
+        };
+
+        Ok(user_inst)
+    }
+}
@@ -0,0 +1,195 @@
+use async_graphql::{Enum, SimpleObject};
+use diesel::{
+    prelude::*,
+    sql_types::{BigInt, Int4, Nullable},
+};
+
+use super::*;
+
+/// User stats that award requisitions can refer to
+#[derive(Enum, Eq, PartialEq, Clone, Copy, Debug)]
+pub enum AwardStat {
+    PuzzleCount,
+    YamiPuzzleCount,
+    ReceivedStarSum,
+    ReceivedStarCount,
+    ReceivedCommentCount,
+    DialogueCount,
+    GoodQuestionCount,
+    TrueAnswerCount,
+    StarCount,
+    CommentCount,
+}
+
+impl AwardStat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "puzzle_count" => Some(AwardStat::PuzzleCount),
+            "yami_puzzle_count" => Some(AwardStat::YamiPuzzleCount),
+            "received_star_sum" => Some(AwardStat::ReceivedStarSum),
+            "received_star_count" => Some(AwardStat::ReceivedStarCount),
+            "received_comment_count" => Some(AwardStat::ReceivedCommentCount),
+            "dialogue_count" => Some(AwardStat::DialogueCount),
+            "good_question_count" => Some(AwardStat::GoodQuestionCount),
+            "true_answer_count" => Some(AwardStat::TrueAnswerCount),
+            "star_count" => Some(AwardStat::StarCount),
+            "comment_count" => Some(AwardStat::CommentCount),
+            _ => None,
+        }
+    }
+}
+
+/// A condition requiring a stat to reach the threshold
+#[derive(Clone, Copy, Debug)]
+pub struct AwardCondition {
+    pub stat: AwardStat,
+    pub threshold: i64,
+}
+
+impl AwardCondition {
+    /// Parse a condition like `puzzle_count >= 50`.
+    ///
+    /// Only `>=` and `>` are accepted, as stats never decrease in practice.
+    fn parse(text: &str) -> Option<Self> {
+        let (name, op, value) = if let Some((name, value)) = text.split_once(">=") {
+            (name, ">=", value)
+        } else if let Some((name, value)) = text.split_once('>') {
+            (name, ">", value)
+        } else {
+            return None;
+        };
+        let stat = AwardStat::from_name(name.trim())?;
+        let value: i64 = value.trim().parse().ok()?;
+        let threshold = if op == ">" {
+            value.checked_add(1)?
+        } else {
+            value
+        };
+
+        Some(Self { stat, threshold })
+    }
+}
+
+/// Machine-readable requisition of an award
+///
+/// A rule consists of conditions joined by `&&`, e.g.
+/// `puzzle_count >= 50 && received_star_sum >= 1000`.
+/// Requisitions in free text are not rules, and such awards are granted by hand.
+#[derive(Clone, Debug)]
+pub struct AwardRule(pub Vec<AwardCondition>);
+
+impl AwardRule {
+    pub fn parse(requisition: &str) -> Option<Self> {
+        let conditions = requisition
+            .split("&&")
+            .map(AwardCondition::parse)
+            .collect::<Option<Vec<_>>>()?;
+        if conditions.is_empty() {
+            return None;
+        }
+        Some(Self(conditions))
+    }
+
+    pub fn is_met(&self, stats: &UserStats) -> bool {
+        self.0
+            .iter()
+            .all(|condition| stats.value(condition.stat) >= condition.threshold)
+    }
+}
+
+/// Stats of a user evaluated against award rules
+///
+/// Anonymous puzzles count towards their author once they are no longer undergoing,
+/// so that granted awards do not give them away.
+#[derive(QueryableByName, Clone, Debug)]
+pub struct UserStats {
+    #[diesel(sql_type = Int4)]
+    pub id: ID,
+    #[diesel(sql_type = BigInt)]
+    pub puzzle_count: i64,
+    #[diesel(sql_type = BigInt)]
+    pub yami_puzzle_count: i64,
+    #[diesel(sql_type = BigInt)]
+    pub received_star_sum: i64,
+    #[diesel(sql_type = BigInt)]
+    pub received_star_count: i64,
+    #[diesel(sql_type = BigInt)]
+    pub received_comment_count: i64,
+    #[diesel(sql_type = BigInt)]
+    pub dialogue_count: i64,
+    #[diesel(sql_type = BigInt)]
+    pub good_question_count: i64,
+    #[diesel(sql_type = BigInt)]
+    pub true_answer_count: i64,
+    #[diesel(sql_type = BigInt)]
+    pub star_count: i64,
+    #[diesel(sql_type = BigInt)]
+    pub comment_count: i64,
+}
+
+impl UserStats {
+    /// Load stats of the given user, or of all users if `user_id` is `None`.
+    pub fn load(conn: &mut PgConnection, user_id: Option<ID>) -> QueryResult<Vec<Self>> {
+        diesel::sql_query(include_str!("../sql/user_award_stats.sql"))
+            .bind::<Nullable<Int4>, _>(user_id)
+            .load(conn)
+    }
+
+    pub fn value(&self, stat: AwardStat) -> i64 {
+        match stat {
+            AwardStat::PuzzleCount => self.puzzle_count,
+            AwardStat::YamiPuzzleCount => self.yami_puzzle_count,
+            AwardStat::ReceivedStarSum => self.received_star_sum,
+            AwardStat::ReceivedStarCount => self.received_star_count,
+            AwardStat::ReceivedCommentCount => self.received_comment_count,
+            AwardStat::DialogueCount => self.dialogue_count,
+            AwardStat::GoodQuestionCount => self.good_question_count,
+            AwardStat::TrueAnswerCount => self.true_answer_count,
+            AwardStat::StarCount => self.star_count,
+            AwardStat::CommentCount => self.comment_count,
+        }
+    }
+}
+
+/// Progress of a user on a condition of an award rule
+#[derive(SimpleObject, Clone)]
+pub struct AwardConditionProgress {
+    pub stat: AwardStat,
+    pub current: i64,
+    pub target: i64,
+    /// Distance left to reach the target, zero if reached
+    pub remaining: i64,
+}
+
+/// Progress of a user towards an award granted by rule
+#[derive(SimpleObject, Clone)]
+pub struct AwardProgress {
+    pub award: Award,
+    /// Whether the user has been granted the award
+    pub granted: bool,
+    pub conditions: Vec<AwardConditionProgress>,
+}
+
+impl AwardProgress {
+    pub fn new(award: Award, rule: &AwardRule, stats: &UserStats, granted: bool) -> Self {
+        let conditions = rule
+            .0
+            .iter()
+            .map(|condition| {
+                let current = stats.value(condition.stat);
+                AwardConditionProgress {
+                    stat: condition.stat,
+                    current,
+                    target: condition.threshold,
+                    remaining: (condition.threshold - current).max(0),
+                }
+            })
+            .collect();
+
+        Self {
+            award,
+            granted,
+            conditions,
+        }
+    }
+}
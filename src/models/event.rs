@@ -0,0 +1,295 @@
+use async_graphql::{self, Context, Enum, InputObject, Object};
+use byteorder::{NetworkEndian, WriteBytesExt};
+use diesel::{
+    backend::Backend,
+    deserialize::{self, FromSql},
+    expression::AsExpression,
+    prelude::*,
+    query_dsl::QueryDsl,
+    serialize::{self, IsNull, Output, ToSql},
+    sql_types::{Bool, Integer},
+};
+use std::error::Error;
+
+use crate::context::GlobalCtx;
+use crate::schema::event;
+
+use super::event_award::{EventAward, EventAwardFilter, EventAwardOrder};
+use super::puzzle::{PuzzleFilter, PuzzleOrder};
+use super::*;
+
+/// Available orders for event query
+#[derive(InputObject, Clone)]
+pub struct EventOrder {
+    id: Option<Ordering>,
+    start_time: Option<Ordering>,
+    end_time: Option<Ordering>,
+}
+
+/// Helper object to apply the order to the query
+pub struct EventOrders(Vec<EventOrder>);
+
+impl Default for EventOrders {
+    fn default() -> Self {
+        Self(vec![])
+    }
+}
+
+impl EventOrders {
+    pub fn new(orders: Vec<EventOrder>) -> Self {
+        Self(orders)
+    }
+
+    pub fn apply_order<'a>(
+        self,
+        query_dsl: event::BoxedQuery<'a, DB>,
+    ) -> event::BoxedQuery<'a, DB> {
+        use crate::schema::event::dsl::*;
+
+        let mut query = query_dsl;
+
+        for obj in self.0 {
+            gen_order!(obj, id, query);
+            gen_order!(obj, start_time, query);
+            gen_order!(obj, end_time, query);
+        }
+
+        query
+    }
+}
+
+/// Available filters for event query
+#[derive(InputObject, Clone, Default)]
+pub struct EventFilter {
+    pub id: Option<I32Filtering>,
+    pub title: Option<StringFiltering>,
+    pub status: Option<EventStatusFiltering>,
+    pub start_time: Option<TimestamptzFiltering>,
+    pub end_time: Option<TimestamptzFiltering>,
+    pub user_id: Option<I32Filtering>,
+}
+
+impl CindyFilter<event::table> for EventFilter {
+    fn as_expression(self) -> Option<Box<dyn BoxableExpression<event::table, DB, SqlType = Bool>>> {
+        use crate::schema::event::dsl::*;
+
+        let mut filter: Option<Box<dyn BoxableExpression<event, DB, SqlType = Bool>>> = None;
+        let EventFilter {
+            id: obj_id,
+            title: obj_title,
+            status: obj_status,
+            start_time: obj_start_time,
+            end_time: obj_end_time,
+            user_id: obj_user_id,
+        } = self;
+        gen_number_filter!(obj_id: I32Filtering, id, filter);
+        gen_string_filter!(obj_title, title, filter);
+        gen_enum_filter!(obj_status: EventStatusFiltering, status, filter);
+        gen_number_filter!(obj_start_time: TimestamptzFiltering, start_time, filter);
+        gen_number_filter!(obj_end_time: TimestamptzFiltering, end_time, filter);
+        gen_number_filter!(obj_user_id: I32Filtering, user_id, filter);
+        filter
+    }
+}
+
+#[repr(i32)]
+#[derive(Enum, Eq, PartialEq, Clone, Copy, Debug, FromSqlRow, AsExpression)]
+#[diesel(sql_type = Integer)]
+pub enum EventStatus {
+    Open = 0,
+    /// Awards of the event have been granted
+    Closed = 1,
+}
+
+#[derive(InputObject, Eq, PartialEq, Clone)]
+pub struct EventStatusFiltering {
+    pub eq: Option<EventStatus>,
+    pub ne: Option<EventStatus>,
+    pub eq_any: Option<Vec<EventStatus>>,
+    pub ne_all: Option<Vec<EventStatus>>,
+}
+
+impl RawFilter<EventStatus> for EventStatusFiltering {
+    fn check(&self, item: &EventStatus) -> bool {
+        if let Some(eq) = self.eq.as_ref() {
+            item == eq
+        } else if let Some(ne) = self.ne.as_ref() {
+            item != ne
+        } else if let Some(eq_any) = self.eq_any.as_ref() {
+            eq_any.iter().any(|u| u == item)
+        } else if let Some(ne_all) = self.ne_all.as_ref() {
+            ne_all.iter().all(|u| u != item)
+        } else {
+            true
+        }
+    }
+}
+
+impl ToSql<Integer, DB> for EventStatus {
+    fn to_sql(&self, out: &mut Output<DB>) -> serialize::Result {
+        out.write_i32::<NetworkEndian>(*self as i32)
+            .map(|_| IsNull::No)
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)
+    }
+}
+
+impl<DB> FromSql<Integer, DB> for EventStatus
+where
+    DB: Backend,
+    i32: FromSql<Integer, DB>,
+{
+    fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+        match i32::from_sql(bytes)? {
+            0 => Ok(EventStatus::Open),
+            1 => Ok(EventStatus::Closed),
+            v => Err(format!("Invalid value `{}` for event status", &v).into()),
+        }
+    }
+}
+
+/// Object for event table
+#[derive(Queryable, Identifiable, Clone, Debug)]
+#[diesel(table_name = event)]
+pub struct Event {
+    pub id: ID,
+    pub title: String,
+    pub banner_img_url: String,
+    pub status: EventStatus,
+    pub start_time: Timestamptz,
+    pub end_time: Timestamptz,
+    pub page_link: String,
+    pub page_src: String,
+    pub user_id: ID,
+}
+
+impl Event {
+    /// Filter on `created` that matches puzzles posted during the event
+    pub fn created_filtering(&self) -> TimestamptzFiltering {
+        TimestamptzFiltering {
+            eq: None,
+            gt: None,
+            lt: None,
+            ge: Some(self.start_time),
+            le: Some(self.end_time),
+            eq_any: None,
+        }
+    }
+}
+
+#[Object]
+impl Event {
+    async fn id(&self) -> ID {
+        self.id
+    }
+    async fn title(&self) -> &str {
+        &self.title
+    }
+    async fn banner_img_url(&self) -> &str {
+        &self.banner_img_url
+    }
+    async fn status(&self) -> EventStatus {
+        self.status
+    }
+    async fn start_time(&self) -> Timestamptz {
+        self.start_time
+    }
+    async fn end_time(&self) -> Timestamptz {
+        self.end_time
+    }
+    async fn page_link(&self) -> &str {
+        &self.page_link
+    }
+    async fn page_src(&self) -> &str {
+        &self.page_src
+    }
+    async fn user_id(&self) -> ID {
+        self.user_id
+    }
+
+    async fn user(&self, ctx: &Context<'_>) -> async_graphql::Result<User> {
+        use crate::schema::user;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let user_inst = user::table
+            .filter(user::id.eq(self.user_id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(user_inst)
+    }
+
+    /// Puzzles created between the start and the end of the event
+    async fn puzzles(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        filter: Option<PuzzleFilter>,
+        order: Option<Vec<PuzzleOrder>>,
+    ) -> async_graphql::Result<Vec<Puzzle>> {
+        use crate::gql_schema::PuzzleQuery;
+
+        let filter = filter
+            .map(|mut filter| {
+                filter.created = Some(self.created_filtering());
+                filter
+            })
+            .unwrap_or_else(|| PuzzleFilter {
+                created: Some(self.created_filtering()),
+                ..Default::default()
+            });
+
+        let query = PuzzleQuery::default();
+        query
+            .puzzles(ctx, limit, offset, Some(vec![filter]), order)
+            .await
+    }
+
+    async fn puzzle_count(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<PuzzleFilter>,
+    ) -> async_graphql::Result<i64> {
+        use crate::gql_schema::PuzzleQuery;
+
+        let filter = filter
+            .map(|mut filter| {
+                filter.created = Some(self.created_filtering());
+                filter
+            })
+            .unwrap_or_else(|| PuzzleFilter {
+                created: Some(self.created_filtering()),
+                ..Default::default()
+            });
+
+        let query = PuzzleQuery::default();
+        query.puzzle_count(ctx, Some(vec![filter])).await
+    }
+
+    async fn event_awards(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        filter: Option<EventAwardFilter>,
+        order: Option<Vec<EventAwardOrder>>,
+    ) -> async_graphql::Result<Vec<EventAward>> {
+        use crate::gql_schema::EventAwardQuery;
+
+        let filter = filter
+            .map(|mut filter| {
+                filter.event_id = Some(I32Filtering::eq(self.id));
+                filter
+            })
+            .unwrap_or_else(|| EventAwardFilter {
+                event_id: Some(I32Filtering::eq(self.id)),
+                ..Default::default()
+            });
+
+        let query = EventAwardQuery::default();
+        query
+            .event_awards(ctx, limit, offset, Some(vec![filter]), order)
+            .await
+    }
+}
@@ -0,0 +1,120 @@
+use async_graphql::{self, Context, InputObject, Object};
+use diesel::{prelude::*, query_dsl::QueryDsl, sql_types::Bool};
+
+use crate::context::GlobalCtx;
+use crate::schema::event_award;
+
+use super::*;
+
+/// Available orders for event_award query
+#[derive(InputObject, Clone)]
+pub struct EventAwardOrder {
+    id: Option<Ordering>,
+}
+
+/// Helper object to apply the order to the query
+pub struct EventAwardOrders(Vec<EventAwardOrder>);
+
+impl Default for EventAwardOrders {
+    fn default() -> Self {
+        Self(vec![])
+    }
+}
+
+impl EventAwardOrders {
+    pub fn new(orders: Vec<EventAwardOrder>) -> Self {
+        Self(orders)
+    }
+
+    pub fn apply_order<'a>(
+        self,
+        query_dsl: event_award::BoxedQuery<'a, DB>,
+    ) -> event_award::BoxedQuery<'a, DB> {
+        use crate::schema::event_award::dsl::*;
+
+        let mut query = query_dsl;
+
+        for obj in self.0 {
+            gen_order!(obj, id, query);
+        }
+
+        query
+    }
+}
+
+/// Available filters for event_award query
+#[derive(InputObject, Clone, Default)]
+pub struct EventAwardFilter {
+    pub id: Option<I32Filtering>,
+    pub award_id: Option<I32Filtering>,
+    pub event_id: Option<I32Filtering>,
+}
+
+impl CindyFilter<event_award::table> for EventAwardFilter {
+    fn as_expression(
+        self,
+    ) -> Option<Box<dyn BoxableExpression<event_award::table, DB, SqlType = Bool>>> {
+        use crate::schema::event_award::dsl::*;
+
+        let mut filter: Option<Box<dyn BoxableExpression<event_award, DB, SqlType = Bool>>> = None;
+        let EventAwardFilter {
+            id: obj_id,
+            award_id: obj_award_id,
+            event_id: obj_event_id,
+        } = self;
+        gen_number_filter!(obj_id: I32Filtering, id, filter);
+        gen_number_filter!(obj_award_id: I32Filtering, award_id, filter);
+        gen_number_filter!(obj_event_id: I32Filtering, event_id, filter);
+        filter
+    }
+}
+
+/// Object for event_award table
+///
+/// Users qualifying for the event are granted the award when the event closes.
+#[derive(Queryable, Identifiable, Clone, Debug)]
+#[diesel(table_name = event_award)]
+pub struct EventAward {
+    pub id: ID,
+    pub award_id: ID,
+    pub event_id: ID,
+}
+
+#[Object]
+impl EventAward {
+    async fn id(&self) -> ID {
+        self.id
+    }
+    async fn award_id(&self) -> ID {
+        self.award_id
+    }
+    async fn event_id(&self) -> ID {
+        self.event_id
+    }
+
+    async fn award(&self, ctx: &Context<'_>) -> async_graphql::Result<Award> {
+        use crate::schema::award;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let award_inst = award::table
+            .filter(award::id.eq(self.award_id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(award_inst)
+    }
+
+    async fn event(&self, ctx: &Context<'_>) -> async_graphql::Result<Event> {
+        use crate::schema::event;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let event_inst = event::table
+            .filter(event::id.eq(self.event_id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(event_inst)
+    }
+}
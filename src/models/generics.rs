@@ -2,6 +2,7 @@ use async_graphql::{self, async_trait, Context, Enum, Guard, InputObject, MaybeU
 use chrono::{DateTime, NaiveDate, Utc};
 use diesel::{expression::BoxableExpression, prelude::*, sql_types::Bool};
 
+use super::user_suspension::SuspensionScope;
 use crate::auth::Role;
 use crate::context::RequestCtx;
 
@@ -458,6 +459,58 @@ impl Guard for StaffRoleGuard {
     }
 }
 
+/// Allow admin/staff, and users granted the permission
+pub struct PermissionGuard {
+    permission: &'static str,
+}
+
+impl PermissionGuard {
+    pub fn new(permission: &'static str) -> Self {
+        Self { permission }
+    }
+}
+
+#[async_trait::async_trait]
+impl Guard for PermissionGuard {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        if let Some(reqctx) = ctx.data_opt::<RequestCtx>() {
+            match reqctx.get_role() {
+                Role::Admin | Role::Staff => Ok(()),
+                Role::User if reqctx.has_permission(self.permission) => Ok(()),
+                _ => Err(format!("Forbidden: Permission `{}` required", self.permission).into()),
+            }
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Deny users under a suspension covering the scope
+pub struct SuspensionGuard {
+    scope: SuspensionScope,
+}
+
+impl SuspensionGuard {
+    pub fn new(scope: SuspensionScope) -> Self {
+        Self { scope }
+    }
+}
+
+#[async_trait::async_trait]
+impl Guard for SuspensionGuard {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        if let Some(reqctx) = ctx.data_opt::<RequestCtx>() {
+            if reqctx.get_role() != Role::Admin && reqctx.is_suspended(self.scope) {
+                Err("Forbidden: User is suspended".into())
+            } else {
+                Ok(())
+            }
+        } else {
+            Ok(())
+        }
+    }
+}
+
 /// Guard guests, limit users with same user id, allow admins
 pub fn user_id_guard(ctx: &Context<'_>, user_id: ID) -> async_graphql::Result<()> {
     let role = ctx.data::<RequestCtx>()?.get_role();
@@ -474,6 +527,21 @@ pub fn user_id_guard(ctx: &Context<'_>, user_id: ID) -> async_graphql::Result<()
     }
 }
 
+/// Guard guests, limit users and staff with same user id, allow admins
+pub fn owner_guard(ctx: &Context<'_>, user_id: ID) -> async_graphql::Result<()> {
+    let role = ctx.data::<RequestCtx>()?.get_role();
+    match role {
+        Role::Admin => Ok(()),
+        Role::Staff | Role::User => assert_eq_guard(
+            ctx.data::<RequestCtx>()?
+                .get_user_id()
+                .ok_or(async_graphql::Error::new("No user"))?,
+            user_id,
+        ),
+        Role::Guest => Err(async_graphql::Error::new("Not logged in")),
+    }
+}
+
 // TODO Rewrite all these macros with proc_macro
 
 /// Generate filter for the query in a loop.
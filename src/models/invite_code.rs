@@ -0,0 +1,77 @@
+use async_graphql::{self, Context, Object};
+use chrono::Utc;
+use diesel::prelude::*;
+
+use crate::context::GlobalCtx;
+use crate::schema::invite_code;
+
+use super::*;
+
+/// Object for invite_code table
+#[derive(Queryable, Identifiable, Clone, Debug)]
+#[diesel(table_name = invite_code)]
+pub struct InviteCode {
+    pub id: ID,
+    pub code: String,
+    pub created_by: Option<ID>,
+    pub max_uses: i32,
+    pub uses: i32,
+    pub created: Timestamptz,
+    pub expires: Option<Timestamptz>,
+    pub revoked: Option<Timestamptz>,
+}
+
+#[Object]
+impl InviteCode {
+    async fn id(&self) -> ID {
+        self.id
+    }
+    async fn code(&self) -> &str {
+        &self.code
+    }
+    async fn created_by_id(&self) -> Option<ID> {
+        self.created_by
+    }
+    async fn max_uses(&self) -> i32 {
+        self.max_uses
+    }
+    async fn uses(&self) -> i32 {
+        self.uses
+    }
+    async fn created(&self) -> Timestamptz {
+        self.created
+    }
+    async fn expires(&self) -> Option<Timestamptz> {
+        self.expires
+    }
+    async fn revoked(&self) -> Option<Timestamptz> {
+        self.revoked
+    }
+    /// Whether the code can still be used to sign up
+    async fn active(&self) -> bool {
+        self.revoked.is_none()
+            && self.uses < self.max_uses
+            && self
+                .expires
+                .map(|expires| expires > Utc::now())
+                .unwrap_or(true)
+    }
+
+    async fn created_by(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<User>> {
+        use crate::schema::user;
+
+        let created_by = match self.created_by {
+            Some(created_by) => created_by,
+            None => return Ok(None),
+        };
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let user_inst = user::table
+            .filter(user::id.eq(created_by))
+            .limit(1)
+            .first(&mut conn)
+            .optional()?;
+
+        Ok(user_inst)
+    }
+}
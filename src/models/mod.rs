@@ -1,7 +1,10 @@
 #[macro_use]
 mod generics;
 
+pub mod audit_log;
 pub mod award;
+pub mod award_application;
+pub mod award_rule;
 pub mod bookmark;
 pub mod chatmessage;
 pub mod chatroom;
@@ -9,21 +12,34 @@ pub mod comment;
 pub mod dialogue;
 pub mod direct_message;
 pub mod dm_read;
+pub mod event;
+pub mod event_award;
 pub mod favchat;
 pub mod hint;
 pub mod image;
+pub mod invite_code;
 pub mod license;
+pub mod permission;
+pub mod personal_access_token;
 pub mod puzzle;
 pub mod puzzle_log;
+pub mod puzzle_revision;
+pub mod puzzle_search;
 pub mod puzzle_tag;
+pub mod replay;
+pub mod replay_dialogue;
+pub mod schedule;
 pub mod star;
 pub mod tag;
 pub mod user;
 pub mod user_award;
+pub mod user_suspension;
 
 pub use generics::*;
 
+pub use audit_log::{AuditAction, AuditLog};
 pub use award::Award;
+pub use award_application::AwardApplication;
 pub use bookmark::Bookmark;
 pub use chatmessage::Chatmessage;
 pub use chatroom::Chatroom;
@@ -31,14 +47,24 @@ pub use comment::Comment;
 pub use dialogue::Dialogue;
 pub use direct_message::DirectMessage;
 pub use dm_read::DmRead;
+pub use event::Event;
+pub use event_award::EventAward;
 pub use favchat::Favchat;
 pub use hint::Hint;
+pub use invite_code::InviteCode;
 pub use license::License;
+pub use permission::{AuthGroup, Permission};
+pub use personal_access_token::{PersonalAccessToken, TokenScope};
 pub use puzzle::{Genre, Puzzle, Status, Yami};
+pub use puzzle_revision::PuzzleRevision;
 pub use puzzle_tag::PuzzleTag;
+pub use replay::Replay;
+pub use replay_dialogue::ReplayDialogue;
+pub use schedule::Schedule;
 pub use star::Star;
 pub use tag::Tag;
 pub use user::User;
 pub use user_award::UserAward;
+pub use user_suspension::{SuspensionScope, UserSuspension};
 
 pub use puzzle_log::PuzzleLog;
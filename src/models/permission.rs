@@ -0,0 +1,153 @@
+use async_graphql::{self, Context, Object};
+use diesel::prelude::*;
+
+use crate::context::GlobalCtx;
+use crate::schema::{
+    auth_group, auth_group_permissions, auth_permission, django_content_type, sui_hei_user_groups,
+    sui_hei_user_user_permissions,
+};
+
+use super::*;
+
+/// Columns to load a `Permission` from `auth_permission` joined with `django_content_type`
+pub const PERMISSION_COLUMNS: (
+    auth_permission::id,
+    auth_permission::name,
+    django_content_type::model,
+    auth_permission::codename,
+) = (
+    auth_permission::id,
+    auth_permission::name,
+    django_content_type::model,
+    auth_permission::codename,
+);
+
+/// Object for auth_permission table, along with the model it applies to
+///
+/// Permissions are referred to by keys like `chatmessage.delete`,
+/// which stands for the `delete_chatmessage` codename on the `chatmessage` model.
+#[derive(Queryable, Clone, Debug)]
+pub struct Permission {
+    pub id: ID,
+    pub name: String,
+    pub model: String,
+    pub codename: String,
+}
+
+impl Permission {
+    pub fn to_key(&self) -> String {
+        let action = self
+            .codename
+            .strip_suffix(&format!("_{}", self.model))
+            .unwrap_or(&self.codename);
+        format!("{}.{}", self.model, action)
+    }
+
+    /// Find the permission by its key.
+    pub fn find(conn: &mut PgConnection, key: &str) -> async_graphql::Result<Self> {
+        let (model, action) = key
+            .split_once('.')
+            .ok_or_else(|| async_graphql::Error::new(format!("Invalid permission `{}`", key)))?;
+        let permission = auth_permission::table
+            .inner_join(django_content_type::table)
+            .filter(django_content_type::model.eq(model))
+            .filter(auth_permission::codename.eq(format!("{}_{}", action, model)))
+            .select(PERMISSION_COLUMNS)
+            .first(conn)
+            .optional()?
+            .ok_or_else(|| async_graphql::Error::new(format!("Unknown permission `{}`", key)))?;
+
+        Ok(permission)
+    }
+}
+
+#[Object]
+impl Permission {
+    async fn id(&self) -> ID {
+        self.id
+    }
+    async fn name(&self) -> &str {
+        &self.name
+    }
+    /// Key of the permission used by guards, e.g. `chatmessage.delete`
+    async fn key(&self) -> String {
+        self.to_key()
+    }
+}
+
+/// Keys of the permissions granted to the user, directly or by groups.
+pub fn user_permissions(conn: &mut PgConnection, user_id: ID) -> QueryResult<Vec<String>> {
+    let direct: Vec<Permission> = auth_permission::table
+        .inner_join(django_content_type::table)
+        .inner_join(sui_hei_user_user_permissions::table)
+        .filter(sui_hei_user_user_permissions::user_id.eq(user_id))
+        .select(PERMISSION_COLUMNS)
+        .load(conn)?;
+    let grouped: Vec<Permission> = auth_permission::table
+        .inner_join(django_content_type::table)
+        .inner_join(auth_group_permissions::table)
+        .filter(
+            auth_group_permissions::group_id.eq_any(
+                sui_hei_user_groups::table
+                    .filter(sui_hei_user_groups::user_id.eq(user_id))
+                    .select(sui_hei_user_groups::group_id),
+            ),
+        )
+        .select(PERMISSION_COLUMNS)
+        .load(conn)?;
+
+    let mut keys: Vec<String> = direct
+        .iter()
+        .chain(grouped.iter())
+        .map(|permission| permission.to_key())
+        .collect();
+    keys.sort();
+    keys.dedup();
+
+    Ok(keys)
+}
+
+/// Object for auth_group table
+#[derive(Queryable, Identifiable, Clone, Debug)]
+#[diesel(table_name = auth_group)]
+pub struct AuthGroup {
+    pub id: ID,
+    pub name: String,
+}
+
+#[Object]
+impl AuthGroup {
+    async fn id(&self) -> ID {
+        self.id
+    }
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn permissions(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Permission>> {
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let permissions = auth_permission::table
+            .inner_join(django_content_type::table)
+            .inner_join(auth_group_permissions::table)
+            .filter(auth_group_permissions::group_id.eq(self.id))
+            .select(PERMISSION_COLUMNS)
+            .load(&mut conn)?;
+
+        Ok(permissions)
+    }
+
+    async fn users(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<User>> {
+        use crate::schema::user;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let users = user::table
+            .inner_join(sui_hei_user_groups::table)
+            .filter(sui_hei_user_groups::group_id.eq(self.id))
+            .select(user::all_columns)
+            .load(&mut conn)?;
+
+        Ok(users)
+    }
+}
@@ -0,0 +1,146 @@
+use async_graphql::{self, Context, Enum, Object};
+use byteorder::{NetworkEndian, WriteBytesExt};
+use chrono::Utc;
+use diesel::{
+    backend::Backend,
+    deserialize::{self, FromSql},
+    expression::AsExpression,
+    prelude::*,
+    serialize::{self, IsNull, Output, ToSql},
+    sql_types::Integer,
+};
+use std::error::Error;
+
+use crate::context::GlobalCtx;
+use crate::schema::personal_access_token;
+
+use super::*;
+
+/// What a personal access token may do besides reading
+#[repr(i32)]
+#[derive(Enum, Eq, PartialEq, Clone, Copy, Debug, FromSqlRow, AsExpression)]
+#[diesel(sql_type = Integer)]
+pub enum TokenScope {
+    /// Queries and subscriptions only
+    ReadOnly = 0,
+    /// Posting and editing chatmessages and direct messages
+    ChatWrite = 1,
+    /// Posting and editing puzzles, hints and answers
+    PuzzleWrite = 2,
+}
+
+impl TokenScope {
+    /// Mutations allowed in this scope
+    fn mutations(&self) -> &'static [&'static str] {
+        match self {
+            TokenScope::ReadOnly => &[],
+            TokenScope::ChatWrite => &[
+                "createChatmessage",
+                "updateChatmessage",
+                "createDirectMessage",
+                "updateDirectMessage",
+                "upsertDmRead",
+            ],
+            TokenScope::PuzzleWrite => &[
+                "createPuzzle",
+                "updatePuzzle",
+                "createHint",
+                "updateHint",
+                "updateDialogue",
+            ],
+        }
+    }
+
+    /// Whether the mutation, by its GraphQL field name, is allowed in this scope
+    pub fn allows(&self, mutation: &str) -> bool {
+        self.mutations().contains(&mutation)
+    }
+}
+
+impl ToSql<Integer, DB> for TokenScope {
+    fn to_sql(&self, out: &mut Output<DB>) -> serialize::Result {
+        out.write_i32::<NetworkEndian>(*self as i32)
+            .map(|_| IsNull::No)
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)
+    }
+}
+
+impl<DB> FromSql<Integer, DB> for TokenScope
+where
+    DB: Backend,
+    i32: FromSql<Integer, DB>,
+{
+    fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+        match i32::from_sql(bytes)? {
+            0 => Ok(TokenScope::ReadOnly),
+            1 => Ok(TokenScope::ChatWrite),
+            2 => Ok(TokenScope::PuzzleWrite),
+            v => Err(format!("Invalid value `{}` for token scope", &v).into()),
+        }
+    }
+}
+
+/// Object for personal_access_token table
+///
+/// Only the digest of the token is stored, the token itself is shown once on creation.
+#[derive(Queryable, Identifiable, Clone, Debug)]
+#[diesel(table_name = personal_access_token)]
+pub struct PersonalAccessToken {
+    pub id: ID,
+    pub user_id: ID,
+    pub name: String,
+    pub token: String,
+    pub scopes: Vec<TokenScope>,
+    pub created: Timestamptz,
+    pub last_used: Option<Timestamptz>,
+    pub expires: Option<Timestamptz>,
+    pub revoked: Option<Timestamptz>,
+}
+
+#[Object]
+impl PersonalAccessToken {
+    async fn id(&self) -> ID {
+        self.id
+    }
+    async fn user_id(&self) -> ID {
+        self.user_id
+    }
+    async fn name(&self) -> &str {
+        &self.name
+    }
+    async fn scopes(&self) -> &[TokenScope] {
+        &self.scopes
+    }
+    async fn created(&self) -> Timestamptz {
+        self.created
+    }
+    async fn last_used(&self) -> Option<Timestamptz> {
+        self.last_used
+    }
+    async fn expires(&self) -> Option<Timestamptz> {
+        self.expires
+    }
+    async fn revoked(&self) -> Option<Timestamptz> {
+        self.revoked
+    }
+    async fn active(&self) -> bool {
+        self.revoked.is_none()
+            && self
+                .expires
+                .map(|expires| expires > Utc::now())
+                .unwrap_or(true)
+    }
+
+    async fn user(&self, ctx: &Context<'_>) -> async_graphql::Result<User> {
+        use crate::schema::user;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let user_inst = user::table
+            .filter(user::id.eq(self.user_id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(user_inst)
+    }
+}
@@ -12,13 +12,15 @@ use diesel::{
 };
 use std::error::Error;
 
-use crate::context::GlobalCtx;
+use crate::auth::Role;
+use crate::context::{GlobalCtx, RequestCtx};
 use crate::schema::puzzle;
 
 use super::bookmark::{BookmarkFilter, BookmarkOrder};
 use super::comment::{CommentFilter, CommentOrder};
 use super::dialogue::{DialogueFilter, DialogueOrder};
 use super::hint::{HintFilter, HintOrder};
+use super::puzzle_revision::{PuzzleRevisionFilter, PuzzleRevisionOrder};
 use super::puzzle_tag::{PuzzleTagFilter, PuzzleTagOrder};
 use super::star::{StarFilter, StarOrder};
 use super::*;
@@ -126,6 +128,137 @@ impl CindyFilter<puzzle::table> for PuzzleFilter {
     }
 }
 
+/// Convert puzzle filters to an expression guarded by the visibility policy of the requester.
+///
+/// Filters on spoiler fields only match puzzles whose spoilers are visible to the requester,
+/// so that e.g. `solution: { like: "%...%" }` cannot be used to probe an unsolved puzzle.
+/// Likewise, filters on the author only match puzzles whose author is visible to the requester.
+/// Scheduled drafts never match unless they are visible to the requester.
+pub fn guarded_filter_expression(
+    filters: Option<Vec<PuzzleFilter>>,
+    reqctx: Option<&RequestCtx>,
+) -> Option<Box<dyn BoxableExpression<puzzle::table, DB, SqlType = Bool>>> {
+    let filter = filters.and_then(|filters| guarded_filters(filters, reqctx));
+    match (filter, draft_visible_expression(reqctx)) {
+        (Some(filter), Some(restriction)) => Some(Box::new(filter.and(restriction))),
+        (filter, None) => filter,
+        (None, restriction) => restriction,
+    }
+}
+
+fn guarded_filters(
+    filters: Vec<PuzzleFilter>,
+    reqctx: Option<&RequestCtx>,
+) -> Option<Box<dyn BoxableExpression<puzzle::table, DB, SqlType = Bool>>> {
+    let mut filter: Option<Box<dyn BoxableExpression<puzzle::table, DB, SqlType = Bool>>> = None;
+    for item in filters.into_iter() {
+        let filters_spoiler = item.solution.is_some();
+        let filters_author = item.user_id.is_some();
+        let mut item = match item.as_expression() {
+            Some(item) => item,
+            None => continue,
+        };
+        if filters_spoiler {
+            if let Some(restriction) = spoiler_visible_expression(reqctx) {
+                item = Box::new(item.and(restriction));
+            }
+        }
+        if filters_author {
+            if let Some(restriction) = author_visible_expression(reqctx) {
+                item = Box::new(item.and(restriction));
+            }
+        }
+        filter = Some(if let Some(filter_) = filter {
+            Box::new(filter_.or(item))
+        } else {
+            item
+        });
+    }
+    filter
+}
+
+/// Expression matching puzzles that are visible to the requester.
+///
+/// Scheduled drafts are only visible to their author and staff.
+/// Returns `None` if the requester is allowed to see all puzzles.
+pub fn draft_visible_expression(
+    reqctx: Option<&RequestCtx>,
+) -> Option<Box<dyn BoxableExpression<puzzle::table, DB, SqlType = Bool>>> {
+    use crate::schema::puzzle::dsl::*;
+
+    let role = reqctx
+        .map(|reqctx| reqctx.get_role())
+        .unwrap_or(Role::Guest);
+    match role {
+        Role::Staff | Role::Admin => None,
+        Role::User => {
+            if let Some(uid) = reqctx.and_then(|reqctx| reqctx.get_user_id()) {
+                Some(Box::new(status.ne(Status::Scheduled).or(user_id.eq(uid))))
+            } else {
+                Some(Box::new(status.ne(Status::Scheduled)))
+            }
+        }
+        Role::Guest => Some(Box::new(status.ne(Status::Scheduled))),
+    }
+}
+
+/// Expression matching puzzles whose spoilers are visible to the requester.
+///
+/// Returns `None` if the requester is allowed to see all spoilers.
+pub fn spoiler_visible_expression(
+    reqctx: Option<&RequestCtx>,
+) -> Option<Box<dyn BoxableExpression<puzzle::table, DB, SqlType = Bool>>> {
+    use crate::schema::puzzle::dsl::*;
+
+    let role = reqctx
+        .map(|reqctx| reqctx.get_role())
+        .unwrap_or(Role::Guest);
+    match role {
+        Role::Staff | Role::Admin => None,
+        Role::User => {
+            if let Some(uid) = reqctx.and_then(|reqctx| reqctx.get_user_id()) {
+                Some(Box::new(status.ne(Status::Undergoing).or(user_id.eq(uid))))
+            } else {
+                Some(Box::new(status.ne(Status::Undergoing)))
+            }
+        }
+        Role::Guest => Some(Box::new(status.ne(Status::Undergoing))),
+    }
+}
+
+/// Expression matching puzzles whose author is visible to the requester.
+///
+/// Returns `None` if the requester is allowed to see all authors.
+pub fn author_visible_expression(
+    reqctx: Option<&RequestCtx>,
+) -> Option<Box<dyn BoxableExpression<puzzle::table, DB, SqlType = Bool>>> {
+    use crate::schema::puzzle::dsl::*;
+
+    let role = reqctx
+        .map(|reqctx| reqctx.get_role())
+        .unwrap_or(Role::Guest);
+    match role {
+        Role::Staff | Role::Admin => None,
+        Role::User => {
+            if let Some(uid) = reqctx.and_then(|reqctx| reqctx.get_user_id()) {
+                Some(Box::new(
+                    anonymous
+                        .eq(false)
+                        .or(status.ne(Status::Undergoing))
+                        .or(user_id.eq(uid)),
+                ))
+            } else {
+                Some(Box::new(
+                    anonymous.eq(false).or(status.ne(Status::Undergoing)),
+                ))
+            }
+        }
+        Role::Guest => Some(Box::new(
+            anonymous.eq(false).or(status.ne(Status::Undergoing)),
+        )),
+    }
+}
+
 #[derive(InputObject, Eq, PartialEq, Clone)]
 pub struct YamiFiltering {
     pub eq: Option<Yami>,
@@ -249,6 +382,15 @@ pub enum Status {
     Dazed = 2,
     Hidden = 3,
     ForceHidden = 4,
+    /// Draft waiting to be published by a schedule
+    Scheduled = 5,
+}
+
+impl Status {
+    /// Whether the spoilers of a puzzle in this status are kept from solvers.
+    pub fn is_unrevealed(&self) -> bool {
+        matches!(self, Status::Undergoing | Status::Scheduled)
+    }
 }
 
 #[derive(InputObject, Eq, PartialEq, Clone)]
@@ -295,11 +437,16 @@ where
             2 => Ok(Status::Dazed),
             3 => Ok(Status::Hidden),
             4 => Ok(Status::ForceHidden),
+            5 => Ok(Status::Scheduled),
             v => Err(format!("Invalid value `{}` for genre", &v).into()),
         }
     }
 }
 
+/// Puzzle changes pushed to subscribers.
+///
+/// The payload is resolved with the `Puzzle` resolvers, so spoilers are redacted according
+/// to the context of each subscriber.
 #[derive(Clone)]
 pub enum PuzzleSub {
     Created(Puzzle),
@@ -434,6 +581,44 @@ pub struct Puzzle {
     pub license_id: Option<ID>,
 }
 
+impl Puzzle {
+    /// Whether the requester is the author of the puzzle or has staff privileges.
+    pub fn is_privileged(&self, reqctx: Option<&RequestCtx>) -> bool {
+        let reqctx = match reqctx {
+            Some(reqctx) => reqctx,
+            None => return false,
+        };
+        match reqctx.get_role() {
+            Role::Staff | Role::Admin => true,
+            Role::User => reqctx.get_user_id() == Some(self.user_id),
+            Role::Guest => false,
+        }
+    }
+
+    /// Whether the puzzle is visible to the requester at all.
+    ///
+    /// Scheduled drafts are hidden from everyone except the author and staff.
+    pub fn visible(&self, reqctx: Option<&RequestCtx>) -> bool {
+        self.status != Status::Scheduled || self.is_privileged(reqctx)
+    }
+
+    /// Whether `solution` and `memo` of the puzzle are visible to the requester.
+    ///
+    /// Spoilers are redacted for everyone except the author and staff while the puzzle is
+    /// undergoing or scheduled.
+    pub fn spoiler_visible(&self, reqctx: Option<&RequestCtx>) -> bool {
+        !self.status.is_unrevealed() || self.is_privileged(reqctx)
+    }
+
+    /// Whether the author of the puzzle is visible to the requester.
+    ///
+    /// The author of an anonymous puzzle is masked for everyone except the author and staff
+    /// until the puzzle leaves undergoing.
+    pub fn author_visible(&self, reqctx: Option<&RequestCtx>) -> bool {
+        !self.anonymous || !self.status.is_unrevealed() || self.is_privileged(reqctx)
+    }
+}
+
 #[Object]
 impl Puzzle {
     async fn id(&self) -> ID {
@@ -451,8 +636,12 @@ impl Puzzle {
     async fn content(&self) -> &str {
         &self.content
     }
-    async fn solution(&self) -> &str {
-        &self.solution
+    async fn solution(&self, ctx: &Context<'_>) -> Option<&str> {
+        if self.spoiler_visible(ctx.data_opt::<RequestCtx>()) {
+            Some(&self.solution)
+        } else {
+            None
+        }
     }
     async fn created(&self) -> Timestamptz {
         self.created
@@ -463,11 +652,19 @@ impl Puzzle {
     async fn status(&self) -> Status {
         self.status
     }
-    async fn memo(&self) -> &str {
-        &self.memo
+    async fn memo(&self, ctx: &Context<'_>) -> Option<&str> {
+        if self.spoiler_visible(ctx.data_opt::<RequestCtx>()) {
+            Some(&self.memo)
+        } else {
+            None
+        }
     }
-    async fn user_id(&self) -> ID {
-        self.user_id
+    async fn user_id(&self, ctx: &Context<'_>) -> Option<ID> {
+        if self.author_visible(ctx.data_opt::<RequestCtx>()) {
+            Some(self.user_id)
+        } else {
+            None
+        }
     }
     async fn anonymous(&self) -> bool {
         self.anonymous
@@ -482,9 +679,13 @@ impl Puzzle {
         self.license_id
     }
 
-    async fn user(&self, ctx: &Context<'_>) -> async_graphql::Result<User> {
+    async fn user(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<User>> {
         use crate::schema::user;
 
+        if !self.author_visible(ctx.data_opt::<RequestCtx>()) {
+            return Ok(None);
+        }
+
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
 
         let user = user::table
@@ -492,7 +693,7 @@ impl Puzzle {
             .limit(1)
             .first(&mut conn)?;
 
-        Ok(user)
+        Ok(Some(user))
     }
 
     async fn license(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<License>> {
@@ -513,6 +714,32 @@ impl Puzzle {
         Ok(license)
     }
 
+    async fn revisions(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        filter: Option<PuzzleRevisionFilter>,
+        order: Option<Vec<PuzzleRevisionOrder>>,
+    ) -> async_graphql::Result<Vec<PuzzleRevision>> {
+        use crate::gql_schema::PuzzleRevisionQuery;
+
+        let filter = filter
+            .map(|mut filter| {
+                filter.puzzle_id = Some(I32Filtering::eq(self.id));
+                filter
+            })
+            .unwrap_or_else(|| PuzzleRevisionFilter {
+                puzzle_id: Some(I32Filtering::eq(self.id)),
+                ..Default::default()
+            });
+
+        let query = PuzzleRevisionQuery::default();
+        query
+            .puzzle_revisions(ctx, limit, offset, Some(vec![filter]), order)
+            .await
+    }
+
     async fn bookmarks(
         &self,
         ctx: &Context<'_>,
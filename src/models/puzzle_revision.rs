@@ -0,0 +1,223 @@
+use async_graphql::{self, Context, Enum, InputObject, Object, SimpleObject};
+use diesel::{prelude::*, query_dsl::QueryDsl, sql_types::Bool};
+use similar::{ChangeTag, TextDiff};
+
+use crate::context::{GlobalCtx, RequestCtx};
+use crate::schema::puzzle_revision;
+
+use super::*;
+
+/// Available orders for puzzle_revision query
+#[derive(InputObject, Clone)]
+pub struct PuzzleRevisionOrder {
+    id: Option<Ordering>,
+    created: Option<Ordering>,
+}
+
+/// Helper object to apply the order to the query
+pub struct PuzzleRevisionOrders(Vec<PuzzleRevisionOrder>);
+
+impl Default for PuzzleRevisionOrders {
+    fn default() -> Self {
+        Self(vec![])
+    }
+}
+
+impl PuzzleRevisionOrders {
+    pub fn new(orders: Vec<PuzzleRevisionOrder>) -> Self {
+        Self(orders)
+    }
+
+    pub fn apply_order<'a>(
+        self,
+        query_dsl: puzzle_revision::BoxedQuery<'a, DB>,
+    ) -> puzzle_revision::BoxedQuery<'a, DB> {
+        use crate::schema::puzzle_revision::dsl::*;
+
+        let mut query = query_dsl;
+
+        for obj in self.0 {
+            gen_order!(obj, id, query);
+            gen_order!(obj, created, query);
+        }
+
+        query
+    }
+}
+
+/// Available filters for puzzle_revision query
+#[derive(InputObject, Clone, Default)]
+pub struct PuzzleRevisionFilter {
+    pub id: Option<I32Filtering>,
+    pub puzzle_id: Option<I32Filtering>,
+    pub user_id: Option<NullableI32Filtering>,
+    pub created: Option<TimestamptzFiltering>,
+}
+
+impl CindyFilter<puzzle_revision::table> for PuzzleRevisionFilter {
+    fn as_expression(
+        self,
+    ) -> Option<Box<dyn BoxableExpression<puzzle_revision::table, DB, SqlType = Bool>>> {
+        use crate::schema::puzzle_revision::dsl::*;
+
+        let mut filter: Option<Box<dyn BoxableExpression<puzzle_revision, DB, SqlType = Bool>>> =
+            None;
+        let PuzzleRevisionFilter {
+            id: obj_id,
+            puzzle_id: obj_puzzle_id,
+            user_id: obj_user_id,
+            created: obj_created,
+        } = self;
+        gen_number_filter!(obj_id: I32Filtering, id, filter);
+        gen_number_filter!(obj_puzzle_id: I32Filtering, puzzle_id, filter);
+        gen_nullable_number_filter!(obj_user_id: NullableI32Filtering, user_id, filter);
+        gen_number_filter!(obj_created: TimestamptzFiltering, created, filter);
+        filter
+    }
+}
+
+/// Object for puzzle_revision table
+///
+/// Each revision is a snapshot of the puzzle after an edit.
+#[derive(Queryable, Identifiable, Clone, Debug)]
+#[diesel(table_name = puzzle_revision)]
+pub struct PuzzleRevision {
+    pub id: ID,
+    pub puzzle_id: ID,
+    pub user_id: Option<ID>,
+    pub content: String,
+    pub solution: String,
+    pub memo: String,
+    pub created: Timestamptz,
+}
+
+impl PuzzleRevision {
+    async fn get_puzzle(&self, ctx: &Context<'_>) -> async_graphql::Result<Puzzle> {
+        use crate::schema::puzzle;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let puzzle_inst = puzzle::table
+            .filter(puzzle::id.eq(self.puzzle_id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(puzzle_inst)
+    }
+}
+
+#[Object]
+impl PuzzleRevision {
+    async fn id(&self) -> ID {
+        self.id
+    }
+    async fn puzzle_id(&self) -> ID {
+        self.puzzle_id
+    }
+    /// Editor of the revision, masked like the author of the puzzle
+    async fn user_id(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<ID>> {
+        let puzzle_inst = self.get_puzzle(ctx).await?;
+        if puzzle_inst.author_visible(ctx.data_opt::<RequestCtx>()) {
+            Ok(self.user_id)
+        } else {
+            Ok(None)
+        }
+    }
+    async fn content(&self) -> &str {
+        &self.content
+    }
+    async fn solution(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<&str>> {
+        let puzzle_inst = self.get_puzzle(ctx).await?;
+        if puzzle_inst.spoiler_visible(ctx.data_opt::<RequestCtx>()) {
+            Ok(Some(&self.solution))
+        } else {
+            Ok(None)
+        }
+    }
+    async fn memo(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<&str>> {
+        let puzzle_inst = self.get_puzzle(ctx).await?;
+        if puzzle_inst.spoiler_visible(ctx.data_opt::<RequestCtx>()) {
+            Ok(Some(&self.memo))
+        } else {
+            Ok(None)
+        }
+    }
+    async fn created(&self) -> Timestamptz {
+        self.created
+    }
+
+    async fn puzzle(&self, ctx: &Context<'_>) -> async_graphql::Result<Puzzle> {
+        self.get_puzzle(ctx).await
+    }
+
+    async fn user(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<User>> {
+        use crate::schema::user;
+
+        let puzzle_inst = self.get_puzzle(ctx).await?;
+        if !puzzle_inst.author_visible(ctx.data_opt::<RequestCtx>()) {
+            return Ok(None);
+        }
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let user_inst = if let Some(id) = self.user_id {
+            user::table
+                .filter(user::id.eq(id))
+                .limit(1)
+                .first(&mut conn)
+                .ok()
+        } else {
+            None
+        };
+
+        Ok(user_inst)
+    }
+}
+
+#[derive(Enum, Eq, PartialEq, Clone, Copy, Debug)]
+pub enum DiffOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// A run of text that is kept, inserted or deleted between two revisions
+#[derive(SimpleObject, Clone, Debug)]
+pub struct DiffChunk {
+    pub op: DiffOp,
+    pub text: String,
+}
+
+impl DiffChunk {
+    /// Character-wise diff of two texts, with adjacent changes of the same kind merged.
+    pub fn diff(old: &str, new: &str) -> Vec<DiffChunk> {
+        let mut chunks: Vec<DiffChunk> = vec![];
+        for change in TextDiff::from_chars(old, new).iter_all_changes() {
+            let op = match change.tag() {
+                ChangeTag::Equal => DiffOp::Equal,
+                ChangeTag::Insert => DiffOp::Insert,
+                ChangeTag::Delete => DiffOp::Delete,
+            };
+            match chunks.last_mut() {
+                Some(last) if last.op == op => last.text.push_str(change.value()),
+                _ => chunks.push(DiffChunk {
+                    op,
+                    text: change.value().to_string(),
+                }),
+            }
+        }
+        chunks
+    }
+}
+
+/// Differences between two revisions of a puzzle
+///
+/// `solution` and `memo` are unset if the spoilers are not visible to the requester.
+#[derive(SimpleObject, Clone, Debug)]
+pub struct PuzzleRevisionDiff {
+    pub from_id: ID,
+    pub to_id: ID,
+    pub content: Vec<DiffChunk>,
+    pub solution: Option<Vec<DiffChunk>>,
+    pub memo: Option<Vec<DiffChunk>>,
+}
@@ -0,0 +1,168 @@
+use async_graphql::SimpleObject;
+
+use super::*;
+
+/// Whether the character belongs to a script that is written without spaces.
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}' // Hiragana, Katakana
+        | '\u{3400}'..='\u{4dbf}' // CJK Unified Ideographs Extension A
+        | '\u{4e00}'..='\u{9fff}' // CJK Unified Ideographs
+        | '\u{f900}'..='\u{faff}' // CJK Compatibility Ideographs
+        | '\u{ff66}'..='\u{ff9f}' // Halfwidth Katakana
+        | '\u{ac00}'..='\u{d7af}' // Hangul Syllables
+    )
+}
+
+/// Split a text into search tokens.
+///
+/// Runs of letters and digits become lowercased words, while runs of CJK characters,
+/// which are not separated by spaces, become overlapping character bigrams.
+/// Tokens are deduplicated and returned in order of appearance.
+pub fn tokenize(text: &str) -> Vec<String> {
+    fn flush_cjk(run: &mut Vec<char>, tokens: &mut Vec<String>) {
+        if run.len() == 1 {
+            tokens.push(run[0].to_string());
+        } else {
+            tokens.extend(run.windows(2).map(|pair| pair.iter().collect::<String>()));
+        }
+        run.clear();
+    }
+    fn flush_word(word: &mut String, tokens: &mut Vec<String>) {
+        if !word.is_empty() {
+            tokens.push(std::mem::take(word));
+        }
+    }
+
+    let mut tokens: Vec<String> = vec![];
+    let mut cjk_run: Vec<char> = vec![];
+    let mut word = String::new();
+
+    for c in text.chars() {
+        if is_cjk(c) {
+            flush_word(&mut word, &mut tokens);
+            cjk_run.push(c);
+        } else if c.is_alphanumeric() {
+            if !cjk_run.is_empty() {
+                flush_cjk(&mut cjk_run, &mut tokens);
+            }
+            word.extend(c.to_lowercase());
+        } else {
+            flush_word(&mut word, &mut tokens);
+            if !cjk_run.is_empty() {
+                flush_cjk(&mut cjk_run, &mut tokens);
+            }
+        }
+    }
+    flush_word(&mut word, &mut tokens);
+    if !cjk_run.is_empty() {
+        flush_cjk(&mut cjk_run, &mut tokens);
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    tokens.retain(|token| seen.insert(token.clone()));
+    tokens
+}
+
+/// A run of text in a search snippet
+#[derive(SimpleObject, Clone, Debug)]
+pub struct SnippetChunk {
+    pub text: String,
+    /// Whether the text matches the search query
+    pub highlighted: bool,
+}
+
+impl SnippetChunk {
+    /// Cut a snippet around the first match of the query in `text`.
+    ///
+    /// At most `radius` characters are kept on each side of the first match, and all
+    /// occurrences of the query terms within the snippet are highlighted.
+    /// The whole text is kept if `radius` is `None`.
+    pub fn snippet(text: &str, query: &str, radius: Option<usize>) -> Vec<SnippetChunk> {
+        let mut terms: Vec<Vec<char>> = query
+            .split_whitespace()
+            .map(|term| term.to_string())
+            .chain(tokenize(query))
+            .map(|term| term.chars().flat_map(|c| c.to_lowercase()).collect())
+            .filter(|term: &Vec<char>| !term.is_empty())
+            .collect();
+        // Prefer the longest match at each position
+        terms.sort_by_key(|term| std::cmp::Reverse(term.len()));
+
+        let chars: Vec<char> = text.chars().collect();
+        let lowered: Vec<char> = chars
+            .iter()
+            .map(|c| c.to_lowercase().next().unwrap_or(*c))
+            .collect();
+
+        let mut matched = vec![false; chars.len()];
+        let mut pos = 0;
+        while pos < chars.len() {
+            let term_len = terms
+                .iter()
+                .find(|term| lowered[pos..].starts_with(term))
+                .map(|term| term.len());
+            if let Some(term_len) = term_len {
+                matched[pos..pos + term_len]
+                    .iter_mut()
+                    .for_each(|m| *m = true);
+                pos += term_len;
+            } else {
+                pos += 1;
+            }
+        }
+
+        let (start, end) = match radius {
+            Some(radius) => {
+                let first = matched.iter().position(|m| *m).unwrap_or(0);
+                let first_end = matched[first..]
+                    .iter()
+                    .position(|m| !*m)
+                    .map_or(chars.len(), |len| first + len);
+                (
+                    first.saturating_sub(radius),
+                    (first_end + radius).min(chars.len()),
+                )
+            }
+            None => (0, chars.len()),
+        };
+
+        let mut chunks: Vec<SnippetChunk> = vec![];
+        if start > 0 {
+            chunks.push(SnippetChunk {
+                text: "…".to_string(),
+                highlighted: false,
+            });
+        }
+        for idx in start..end {
+            match chunks.last_mut() {
+                Some(last) if last.highlighted == matched[idx] && last.text != "…" => {
+                    last.text.push(chars[idx])
+                }
+                _ => chunks.push(SnippetChunk {
+                    text: chars[idx].to_string(),
+                    highlighted: matched[idx],
+                }),
+            }
+        }
+        if end < chars.len() {
+            chunks.push(SnippetChunk {
+                text: "…".to_string(),
+                highlighted: false,
+            });
+        }
+        chunks
+    }
+}
+
+/// A puzzle matching the search query
+#[derive(SimpleObject, Clone)]
+pub struct PuzzleSearchResult {
+    pub puzzle: Puzzle,
+    /// Relevance of the puzzle to the query, higher is better
+    pub rank: f32,
+    /// Title with matches highlighted
+    pub title: Vec<SnippetChunk>,
+    /// Part of the content around the first match, with matches highlighted
+    pub content: Vec<SnippetChunk>,
+}
@@ -0,0 +1,182 @@
+use async_graphql::{self, Context, InputObject, Object, SimpleObject};
+use diesel::{prelude::*, query_dsl::QueryDsl, sql_types::Bool};
+use serde::{Deserialize, Serialize};
+
+use crate::context::GlobalCtx;
+use crate::schema::replay;
+
+use super::replay_dialogue::{ReplayDialogueFilter, ReplayDialogueOrder};
+use super::*;
+
+/// Available orders for replay query
+#[derive(InputObject, Clone)]
+pub struct ReplayOrder {
+    id: Option<Ordering>,
+    created: Option<Ordering>,
+}
+
+/// Helper object to apply the order to the query
+pub struct ReplayOrders(Vec<ReplayOrder>);
+
+impl Default for ReplayOrders {
+    fn default() -> Self {
+        Self(vec![])
+    }
+}
+
+impl ReplayOrders {
+    pub fn new(orders: Vec<ReplayOrder>) -> Self {
+        Self(orders)
+    }
+
+    pub fn apply_order<'a>(
+        self,
+        query_dsl: replay::BoxedQuery<'a, DB>,
+    ) -> replay::BoxedQuery<'a, DB> {
+        use crate::schema::replay::dsl::*;
+
+        let mut query = query_dsl;
+
+        for obj in self.0 {
+            gen_order!(obj, id, query);
+            gen_order!(obj, created, query);
+        }
+
+        query
+    }
+}
+
+/// Available filters for replay query
+#[derive(InputObject, Clone, Default)]
+pub struct ReplayFilter {
+    pub id: Option<I32Filtering>,
+    pub title: Option<StringFiltering>,
+    pub puzzle_id: Option<NullableI32Filtering>,
+    pub user_id: Option<I32Filtering>,
+    pub created: Option<TimestamptzFiltering>,
+}
+
+impl CindyFilter<replay::table> for ReplayFilter {
+    fn as_expression(
+        self,
+    ) -> Option<Box<dyn BoxableExpression<replay::table, DB, SqlType = Bool>>> {
+        use crate::schema::replay::dsl::*;
+
+        let mut filter: Option<Box<dyn BoxableExpression<replay, DB, SqlType = Bool>>> = None;
+        let ReplayFilter {
+            id: obj_id,
+            title: obj_title,
+            puzzle_id: obj_puzzle_id,
+            user_id: obj_user_id,
+            created: obj_created,
+        } = self;
+        gen_number_filter!(obj_id: I32Filtering, id, filter);
+        gen_string_filter!(obj_title, title, filter);
+        gen_nullable_number_filter!(obj_puzzle_id: NullableI32Filtering, puzzle_id, filter);
+        gen_number_filter!(obj_user_id: I32Filtering, user_id, filter);
+        gen_number_filter!(obj_created: TimestamptzFiltering, created, filter);
+        filter
+    }
+}
+
+/// A milestone that players reach by asking the right questions in a replay
+#[derive(SimpleObject, InputObject, Serialize, Deserialize, Clone, Debug)]
+#[graphql(input_name = "ReplayMilestoneInput")]
+pub struct ReplayMilestone {
+    pub handle: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+/// Object for replay table
+#[derive(Queryable, Identifiable, Clone, Debug)]
+#[diesel(table_name = replay)]
+pub struct Replay {
+    pub id: ID,
+    pub title: String,
+    pub milestones: serde_json::Value,
+    pub puzzle_id: Option<ID>,
+    pub user_id: ID,
+    pub created: Timestamptz,
+}
+
+#[Object]
+impl Replay {
+    async fn id(&self) -> ID {
+        self.id
+    }
+    async fn title(&self) -> &str {
+        &self.title
+    }
+    async fn milestones(&self) -> async_graphql::Result<Vec<ReplayMilestone>> {
+        Ok(serde_json::from_value(self.milestones.clone())?)
+    }
+    async fn puzzle_id(&self) -> Option<ID> {
+        self.puzzle_id
+    }
+    async fn user_id(&self) -> ID {
+        self.user_id
+    }
+    async fn created(&self) -> Timestamptz {
+        self.created
+    }
+
+    async fn user(&self, ctx: &Context<'_>) -> async_graphql::Result<User> {
+        use crate::schema::user;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let user_inst = user::table
+            .filter(user::id.eq(self.user_id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(user_inst)
+    }
+
+    async fn puzzle(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Puzzle>> {
+        use crate::schema::puzzle;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let puzzle_inst = if let Some(id) = self.puzzle_id {
+            puzzle::table
+                .filter(puzzle::id.eq(id))
+                .limit(1)
+                .first(&mut conn)
+                .ok()
+        } else {
+            None
+        };
+
+        Ok(puzzle_inst)
+    }
+
+    async fn replay_dialogues(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        filter: Option<ReplayDialogueFilter>,
+        order: Option<Vec<ReplayDialogueOrder>>,
+    ) -> async_graphql::Result<Vec<ReplayDialogue>> {
+        use crate::gql_schema::ReplayDialogueQuery;
+
+        let filter = filter
+            .map(|mut filter| {
+                filter.replay_id = Some(I32Filtering::eq(self.id));
+                filter
+            })
+            .unwrap_or_else(|| ReplayDialogueFilter {
+                replay_id: Some(I32Filtering::eq(self.id)),
+                ..Default::default()
+            });
+
+        let query = ReplayDialogueQuery::default();
+        query
+            .replay_dialogues(ctx, limit, offset, Some(vec![filter]), order)
+            .await
+    }
+}
@@ -0,0 +1,160 @@
+use async_graphql::{self, Context, InputObject, Object, SimpleObject};
+use diesel::{prelude::*, query_dsl::QueryDsl, sql_types::Bool};
+
+use crate::context::GlobalCtx;
+use crate::schema::replay_dialogue;
+
+use super::*;
+
+/// Available orders for replay_dialogue query
+#[derive(InputObject, Clone)]
+pub struct ReplayDialogueOrder {
+    id: Option<Ordering>,
+}
+
+/// Helper object to apply the order to the query
+pub struct ReplayDialogueOrders(Vec<ReplayDialogueOrder>);
+
+impl Default for ReplayDialogueOrders {
+    fn default() -> Self {
+        Self(vec![])
+    }
+}
+
+impl ReplayDialogueOrders {
+    pub fn new(orders: Vec<ReplayDialogueOrder>) -> Self {
+        Self(orders)
+    }
+
+    pub fn apply_order<'a>(
+        self,
+        query_dsl: replay_dialogue::BoxedQuery<'a, DB>,
+    ) -> replay_dialogue::BoxedQuery<'a, DB> {
+        use crate::schema::replay_dialogue::dsl::*;
+
+        let mut query = query_dsl;
+
+        for obj in self.0 {
+            gen_order!(obj, id, query);
+        }
+
+        query
+    }
+}
+
+/// Available filters for replay_dialogue query
+#[derive(InputObject, Clone, Default)]
+pub struct ReplayDialogueFilter {
+    pub id: Option<I32Filtering>,
+    pub replay_id: Option<I32Filtering>,
+    pub question: Option<StringFiltering>,
+    pub answer: Option<StringFiltering>,
+}
+
+impl CindyFilter<replay_dialogue::table> for ReplayDialogueFilter {
+    fn as_expression(
+        self,
+    ) -> Option<Box<dyn BoxableExpression<replay_dialogue::table, DB, SqlType = Bool>>> {
+        use crate::schema::replay_dialogue::dsl::*;
+
+        let mut filter: Option<Box<dyn BoxableExpression<replay_dialogue, DB, SqlType = Bool>>> =
+            None;
+        let ReplayDialogueFilter {
+            id: obj_id,
+            replay_id: obj_replay_id,
+            question: obj_question,
+            answer: obj_answer,
+        } = self;
+        gen_number_filter!(obj_id: I32Filtering, id, filter);
+        gen_number_filter!(obj_replay_id: I32Filtering, replay_id, filter);
+        gen_string_filter!(obj_question, question, filter);
+        gen_string_filter!(obj_answer, answer, filter);
+        filter
+    }
+}
+
+/// Object for replay_dialogue table
+#[derive(Queryable, Identifiable, Clone, Debug)]
+#[diesel(table_name = replay_dialogue)]
+pub struct ReplayDialogue {
+    pub id: ID,
+    pub replay_id: ID,
+    pub question: String,
+    pub answer: String,
+    #[diesel(column_name = good)]
+    pub is_good: bool,
+    #[diesel(column_name = true_)]
+    pub is_true: bool,
+    pub keywords: serde_json::Value,
+    pub milestones: serde_json::Value,
+    pub dependency: String,
+}
+
+impl ReplayDialogue {
+    /// Keywords of the question, skipping malformed entries
+    pub fn keyword_list(&self) -> Vec<String> {
+        self.keywords
+            .as_array()
+            .map(|keywords| {
+                keywords
+                    .iter()
+                    .filter_map(|keyword| keyword.as_str().map(|k| k.to_owned()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[Object]
+impl ReplayDialogue {
+    async fn id(&self) -> ID {
+        self.id
+    }
+    async fn replay_id(&self) -> ID {
+        self.replay_id
+    }
+    async fn question(&self) -> &str {
+        &self.question
+    }
+    async fn answer(&self) -> &str {
+        &self.answer
+    }
+    async fn is_good(&self) -> bool {
+        self.is_good
+    }
+    async fn is_true(&self) -> bool {
+        self.is_true
+    }
+    async fn keywords(&self) -> Vec<String> {
+        self.keyword_list()
+    }
+    /// Handles of the milestones reached by asking this question
+    async fn milestones(&self) -> async_graphql::Result<Vec<String>> {
+        Ok(serde_json::from_value(self.milestones.clone())?)
+    }
+    /// Milestones required before this question is available
+    async fn dependency(&self) -> &str {
+        &self.dependency
+    }
+
+    async fn replay(&self, ctx: &Context<'_>) -> async_graphql::Result<Replay> {
+        use crate::schema::replay;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let replay_inst = replay::table
+            .filter(replay::id.eq(self.replay_id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(replay_inst)
+    }
+}
+
+/// A replay dialogue that matches a question
+#[derive(SimpleObject, Clone)]
+pub struct ReplayDialogueSuggestion {
+    pub replay_dialogue: ReplayDialogue,
+    /// Ratio of keywords of the dialogue found in the question
+    pub score: f64,
+}
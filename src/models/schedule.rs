@@ -0,0 +1,150 @@
+use async_graphql::{self, Context, InputObject, Object};
+use diesel::{prelude::*, query_dsl::QueryDsl, sql_types::Bool};
+
+use crate::context::GlobalCtx;
+use crate::schema::schedule;
+
+use super::*;
+
+/// Available orders for schedule query
+#[derive(InputObject, Clone)]
+pub struct ScheduleOrder {
+    id: Option<Ordering>,
+    created: Option<Ordering>,
+    scheduled: Option<Ordering>,
+}
+
+/// Helper object to apply the order to the query
+pub struct ScheduleOrders(Vec<ScheduleOrder>);
+
+impl Default for ScheduleOrders {
+    fn default() -> Self {
+        Self(vec![])
+    }
+}
+
+impl ScheduleOrders {
+    pub fn new(orders: Vec<ScheduleOrder>) -> Self {
+        Self(orders)
+    }
+
+    pub fn apply_order<'a>(
+        self,
+        query_dsl: schedule::BoxedQuery<'a, DB>,
+    ) -> schedule::BoxedQuery<'a, DB> {
+        use crate::schema::schedule::dsl::*;
+
+        let mut query = query_dsl;
+
+        for obj in self.0 {
+            gen_order!(obj, id, query);
+            gen_order!(obj, created, query);
+            gen_order!(obj, scheduled, query);
+        }
+
+        query
+    }
+}
+
+/// Available filters for schedule query
+#[derive(InputObject, Clone, Default)]
+pub struct ScheduleFilter {
+    pub id: Option<I32Filtering>,
+    pub content: Option<StringFiltering>,
+    pub created: Option<TimestamptzFiltering>,
+    pub scheduled: Option<TimestamptzFiltering>,
+    pub user_id: Option<I32Filtering>,
+    pub puzzle_id: Option<NullableI32Filtering>,
+}
+
+impl CindyFilter<schedule::table> for ScheduleFilter {
+    fn as_expression(
+        self,
+    ) -> Option<Box<dyn BoxableExpression<schedule::table, DB, SqlType = Bool>>> {
+        use crate::schema::schedule::dsl::*;
+
+        let mut filter: Option<Box<dyn BoxableExpression<schedule, DB, SqlType = Bool>>> = None;
+        let ScheduleFilter {
+            id: obj_id,
+            content: obj_content,
+            created: obj_created,
+            scheduled: obj_scheduled,
+            user_id: obj_user_id,
+            puzzle_id: obj_puzzle_id,
+        } = self;
+        gen_number_filter!(obj_id: I32Filtering, id, filter);
+        gen_string_filter!(obj_content, content, filter);
+        gen_number_filter!(obj_created: TimestamptzFiltering, created, filter);
+        gen_number_filter!(obj_scheduled: TimestamptzFiltering, scheduled, filter);
+        gen_number_filter!(obj_user_id: I32Filtering, user_id, filter);
+        gen_nullable_number_filter!(obj_puzzle_id: NullableI32Filtering, puzzle_id, filter);
+        filter
+    }
+}
+
+/// Object for schedule table
+///
+/// A schedule publishes its draft puzzle at `scheduled`.
+#[derive(Queryable, Identifiable, Clone, Debug)]
+#[diesel(table_name = schedule)]
+pub struct Schedule {
+    pub id: ID,
+    pub content: String,
+    pub created: Timestamptz,
+    pub scheduled: Timestamptz,
+    pub user_id: ID,
+    pub puzzle_id: Option<ID>,
+}
+
+#[Object]
+impl Schedule {
+    async fn id(&self) -> ID {
+        self.id
+    }
+    async fn content(&self) -> &str {
+        &self.content
+    }
+    async fn created(&self) -> Timestamptz {
+        self.created
+    }
+    async fn scheduled(&self) -> Timestamptz {
+        self.scheduled
+    }
+    async fn user_id(&self) -> ID {
+        self.user_id
+    }
+    async fn puzzle_id(&self) -> Option<ID> {
+        self.puzzle_id
+    }
+
+    async fn user(&self, ctx: &Context<'_>) -> async_graphql::Result<User> {
+        use crate::schema::user;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let user_inst = user::table
+            .filter(user::id.eq(self.user_id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(user_inst)
+    }
+
+    async fn puzzle(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<Puzzle>> {
+        use crate::schema::puzzle;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let puzzle_inst = if let Some(id) = self.puzzle_id {
+            puzzle::table
+                .filter(puzzle::id.eq(id))
+                .limit(1)
+                .first(&mut conn)
+                .ok()
+        } else {
+            None
+        };
+
+        Ok(puzzle_inst)
+    }
+}
@@ -1,4 +1,10 @@
 use anyhow::{anyhow, Context as _, Result};
+use argon2::{
+    password_hash::{
+        rand_core::OsRng, PasswordHash, PasswordHasher as _, PasswordVerifier, SaltString,
+    },
+    Argon2,
+};
 use async_graphql::{self, Context, InputObject, Object};
 use chrono::Utc;
 use diesel::{
@@ -9,25 +15,27 @@ use diesel::{
     r2d2::{ConnectionManager, PooledConnection},
     sql_types::{BigInt, Bool, Int4},
 };
-use rand::{distributions::Alphanumeric, Rng};
 use ring::pbkdf2;
 use std::num::NonZeroU32;
 
+use super::award_rule::{AwardProgress, AwardRule, UserStats};
 use super::bookmark::{BookmarkFilter, BookmarkOrder};
 use super::comment::{CommentFilter, CommentOrder};
 use super::favchat::{FavchatFilter, FavchatOrder};
-use super::puzzle::{PuzzleFilter, PuzzleOrder};
+use super::permission::user_permissions;
+use super::puzzle::{
+    author_visible_expression, draft_visible_expression, PuzzleFilter, PuzzleOrder,
+};
 use super::puzzle_tag::{PuzzleTagFilter, PuzzleTagOrder};
 use super::star::{StarFilter, StarOrder};
 use super::user_award::{UserAwardFilter, UserAwardOrder};
+use super::user_suspension::active_suspension_scopes;
 use super::*;
 
-use crate::context::GlobalCtx;
+use crate::context::{GlobalCtx, RequestCtx};
 use crate::schema::user;
 
-const SALT_LEN: usize = 16;
-const CRED_LEN: usize = 32;
-const ITER_TIMES: u32 = 100000;
+const ARGON2_PREFIX: &str = "argon2";
 
 /// Available orders for users query
 #[derive(InputObject, Clone)]
@@ -150,6 +158,7 @@ pub struct User {
     pub hide_bookmark: bool,
     pub icon: Option<String>,
     pub default_license_id: Option<ID>,
+    pub email_verified: Option<Timestamptz>,
 }
 
 #[Object]
@@ -185,8 +194,15 @@ impl User {
     async fn last_name(&self) -> &str {
         &self.last_name
     }
-    async fn email(&self) -> &str {
-        &self.email
+    /// Email of the user, only to the user and admins, null to others
+    async fn email(&self, ctx: &Context<'_>) -> Option<&str> {
+        owner_guard(ctx, self.id).ok().map(|_| self.email.as_str())
+    }
+    /// Whether the current email is verified, only to the user and admins, null to others
+    async fn email_verified(&self, ctx: &Context<'_>) -> Option<bool> {
+        owner_guard(ctx, self.id)
+            .ok()
+            .map(|_| self.email_verified.is_some())
     }
     async fn is_superuser(&self) -> bool {
         self.is_superuser
@@ -301,9 +317,17 @@ impl User {
 
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
 
-        let result = comment::table
+        let mut query = comment::table
             .inner_join(puzzle::table)
             .filter(puzzle::user_id.eq(self.id))
+            .into_boxed();
+        if !self.reveals_anonymous(ctx) {
+            query = query.filter(not(
+                puzzle::anonymous.and(puzzle::status.eq(Status::Undergoing))
+            ));
+        }
+
+        let result = query
             .count()
             .get_result(&mut conn)
             .map_err(|err| async_graphql::Error::from(err))?;
@@ -356,8 +380,15 @@ impl User {
 
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
 
-        let result = puzzle
-            .filter(user_id.eq(self.id))
+        let mut query = puzzle.filter(user_id.eq(self.id)).into_boxed();
+        if let Some(restriction) = author_visible_expression(ctx.data_opt::<RequestCtx>()) {
+            query = query.filter(restriction);
+        }
+        if let Some(restriction) = draft_visible_expression(ctx.data_opt::<RequestCtx>()) {
+            query = query.filter(restriction);
+        }
+
+        let result = query
             .count()
             .get_result(&mut conn)
             .map_err(|err| async_graphql::Error::from(err))?;
@@ -370,9 +401,18 @@ impl User {
 
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
 
-        let result = puzzle
+        let mut query = puzzle
             .filter(user_id.eq(self.id))
             .filter(not(yami.eq(0)))
+            .into_boxed();
+        if let Some(restriction) = author_visible_expression(ctx.data_opt::<RequestCtx>()) {
+            query = query.filter(restriction);
+        }
+        if let Some(restriction) = draft_visible_expression(ctx.data_opt::<RequestCtx>()) {
+            query = query.filter(restriction);
+        }
+
+        let result = query
             .count()
             .get_result(&mut conn)
             .map_err(|err| async_graphql::Error::from(err))?;
@@ -388,8 +428,15 @@ impl User {
 
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
 
-        let result = puzzle
-            .filter(user_id.eq(self.id))
+        let mut query = puzzle.filter(user_id.eq(self.id)).into_boxed();
+        if let Some(restriction) = author_visible_expression(ctx.data_opt::<RequestCtx>()) {
+            query = query.filter(restriction);
+        }
+        if let Some(restriction) = draft_visible_expression(ctx.data_opt::<RequestCtx>()) {
+            query = query.filter(restriction);
+        }
+
+        let result = query
             .select(max(created))
             .get_result(&mut conn)
             .map_err(|err| async_graphql::Error::from(err))?;
@@ -442,9 +489,17 @@ impl User {
 
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
 
-        let result = star::table
+        let mut query = star::table
             .inner_join(puzzle::table)
             .filter(puzzle::user_id.eq(self.id))
+            .into_boxed();
+        if !self.reveals_anonymous(ctx) {
+            query = query.filter(not(
+                puzzle::anonymous.and(puzzle::status.eq(Status::Undergoing))
+            ));
+        }
+
+        let result = query
             .select(sum(star::value))
             .get_result(&mut conn)
             .map_err(|err| async_graphql::Error::from(err))?;
@@ -471,9 +526,17 @@ impl User {
 
         let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
 
-        let result = star::table
+        let mut query = star::table
             .inner_join(puzzle::table)
             .filter(puzzle::user_id.eq(self.id))
+            .into_boxed();
+        if !self.reveals_anonymous(ctx) {
+            query = query.filter(not(
+                puzzle::anonymous.and(puzzle::status.eq(Status::Undergoing))
+            ));
+        }
+
+        let result = query
             .count()
             .get_result(&mut conn)
             .map_err(|err| async_graphql::Error::from(err))?;
@@ -602,8 +665,87 @@ impl User {
 
         Ok(result)
     }
+
+    /// Permissions granted to the user directly or by groups
+    async fn permissions(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<String>> {
+        user_id_guard(ctx, self.id)?;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        Ok(user_permissions(&mut conn, self.id)?)
+    }
+
+    /// Whether two-factor authentication is enabled
+    async fn two_factor_enabled(&self, ctx: &Context<'_>) -> async_graphql::Result<bool> {
+        user_id_guard(ctx, self.id)?;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        Ok(crate::auth::two_factor::is_enabled(&mut conn, self.id)?)
+    }
+
+    /// Number of two-factor backup codes left unused
+    async fn two_factor_backup_codes_left(&self, ctx: &Context<'_>) -> async_graphql::Result<i64> {
+        user_id_guard(ctx, self.id)?;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        Ok(crate::auth::two_factor::remaining_backup_codes(
+            &mut conn, self.id,
+        )?)
+    }
+
+    /// Progress towards each award granted by rule
+    async fn award_progress(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<AwardProgress>> {
+        use crate::schema::{award, user_award};
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let stats = UserStats::load(&mut conn, Some(self.id))?
+            .pop()
+            .ok_or_else(|| async_graphql::Error::new("User not found"))?;
+        let granted: Vec<ID> = user_award::table
+            .filter(user_award::user_id.eq(self.id))
+            .select(user_award::award_id)
+            .load(&mut conn)?;
+        let awards: Vec<Award> = award::table.order(award::id.asc()).load(&mut conn)?;
+
+        let progress = awards
+            .into_iter()
+            .filter_map(|award_inst| {
+                let rule = AwardRule::parse(&award_inst.requisition)?;
+                let is_granted = granted.contains(&award_inst.id);
+                Some(AwardProgress::new(award_inst, &rule, &stats, is_granted))
+            })
+            .collect();
+
+        Ok(progress)
+    }
+}
+
+/// Algorithm of a stored credential, recognised by its prefix as in Django
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum PasswordHasher {
+    /// `argon2$argon2id$v=19$m=<memory>,t=<iterations>,p=<parallelism>$<salt>$<hash>`
+    Argon2,
+    /// `pbkdf2_sha256$<iterations>$<salt>$<hash>`
+    Pbkdf2Sha256,
+    /// `pbkdf2_sha1$<iterations>$<salt>$<hash>`, from older Django versions
+    Pbkdf2Sha1,
+}
+
+impl PasswordHasher {
+    fn of(credential: &str) -> Result<Self> {
+        match credential.split('$').next() {
+            Some(ARGON2_PREFIX) => Ok(PasswordHasher::Argon2),
+            Some("pbkdf2_sha256") => Ok(PasswordHasher::Pbkdf2Sha256),
+            Some("pbkdf2_sha1") => Ok(PasswordHasher::Pbkdf2Sha1),
+            _ => Err(anyhow!("Unable to parse password: unknown algorithm")),
+        }
+    }
 }
 
+/// Credential parts of PBKDF2 algorithms
 struct Password {
     pub alg: pbkdf2::Algorithm,
     pub salt: Vec<u8>,
@@ -612,25 +754,19 @@ struct Password {
 }
 
 impl User {
-    fn salt() -> String {
-        rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(SALT_LEN)
-            .collect()
+    /// Whether the requester may see the anonymous puzzles of this user.
+    fn reveals_anonymous(&self, ctx: &Context<'_>) -> bool {
+        user_id_guard(ctx, self.id).is_ok()
     }
 
+    /// Derive the credential to store from the password, with Argon2id.
     pub fn derive_credential(password: &str) -> String {
-        let mut credential = [0u8; CRED_LEN];
-        let salt = Self::salt();
-        pbkdf2::derive(
-            pbkdf2::PBKDF2_HMAC_SHA256,
-            NonZeroU32::new(ITER_TIMES).unwrap(),
-            salt.as_bytes(),
-            password.as_bytes(),
-            &mut credential,
-        );
-        let credential = base64::encode(credential.as_ref());
-        format!("pbkdf2_sha256${}${}${}", ITER_TIMES, salt, credential)
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .expect("Error hashing password with Argon2id.");
+        // PHC string starting with `$argon2id`, prefixed as in Django
+        format!("{}{}", ARGON2_PREFIX, hash)
     }
 
     /// Authenticate the user.
@@ -652,17 +788,23 @@ impl User {
         if !usr.is_active {
             return Err(anyhow!("User is not activated by administrator. Contact the administrator for more details."));
         }
+        if active_suspension_scopes(&mut conn, usr.id)?
+            .iter()
+            .any(|scope| scope.covers(SuspensionScope::Full))
+        {
+            return Err(anyhow!(
+                "User is suspended. Contact the administrator for more details."
+            ));
+        }
 
-        let Password {
-            alg,
-            iter,
-            salt,
-            credential,
-        } = usr.decompose_password()?;
-        let mut reproduce = [0u8; CRED_LEN];
-        pbkdf2::derive(alg, iter, &salt, password.as_bytes(), &mut reproduce);
-        pbkdf2::verify(alg, iter, &salt, password.as_bytes(), &credential)
-            .map_err(|_| anyhow!("Invalid password"))?;
+        usr.verify_password(password)?;
+
+        // Upgrade credentials of legacy algorithms or parameters
+        if usr.needs_rehash() {
+            diesel::update(&usr)
+                .set(user::password.eq(Self::derive_credential(password)))
+                .execute(&mut conn)?;
+        }
 
         diesel::update(&usr)
             .set(last_login.eq(Some(Utc::now())))
@@ -670,8 +812,67 @@ impl User {
         Ok(usr)
     }
 
+    /// Check the password against the stored credential.
+    pub fn verify_password(&self, password: &str) -> Result<()> {
+        match PasswordHasher::of(&self.password)? {
+            PasswordHasher::Argon2 => {
+                let hash = self.argon2_hash()?;
+                Argon2::default()
+                    .verify_password(password.as_bytes(), &hash)
+                    .map_err(|_| anyhow!("Invalid password"))
+            }
+            PasswordHasher::Pbkdf2Sha256 | PasswordHasher::Pbkdf2Sha1 => {
+                let Password {
+                    alg,
+                    iter,
+                    salt,
+                    credential,
+                } = self.decompose_password()?;
+                pbkdf2::verify(alg, iter, &salt, password.as_bytes(), &credential)
+                    .map_err(|_| anyhow!("Invalid password"))
+            }
+        }
+    }
+
+    /// Whether the credential is not derived by `derive_credential` with current parameters.
+    fn needs_rehash(&self) -> bool {
+        match PasswordHasher::of(&self.password) {
+            Ok(PasswordHasher::Argon2) => {
+                let hash = match self.argon2_hash() {
+                    Ok(hash) => hash,
+                    Err(_) => return true,
+                };
+                let params = match argon2::Params::try_from(&hash) {
+                    Ok(params) => params,
+                    Err(_) => return true,
+                };
+                // Output length is parsed from the hash but left unset by default,
+                // so only the costs are compared
+                let defaults = argon2::Params::default();
+                hash.algorithm != argon2::Algorithm::default().ident()
+                    || hash.version != Some(argon2::Version::default().into())
+                    || params.m_cost() != defaults.m_cost()
+                    || params.t_cost() != defaults.t_cost()
+                    || params.p_cost() != defaults.p_cost()
+            }
+            _ => true,
+        }
+    }
+
+    fn argon2_hash(&self) -> Result<PasswordHash<'_>> {
+        let phc = self
+            .password
+            .strip_prefix(ARGON2_PREFIX)
+            .ok_or(anyhow!("Unable to parse password: algorithm not found"))?;
+        PasswordHash::new(phc).map_err(|err| anyhow!("Unable to parse password: {}", err))
+    }
+
     fn decompose_password(&self) -> Result<Password> {
         let mut parts = self.password.split('$');
+        let alg = match PasswordHasher::of(&self.password)? {
+            PasswordHasher::Pbkdf2Sha1 => pbkdf2::PBKDF2_HMAC_SHA1,
+            _ => pbkdf2::PBKDF2_HMAC_SHA256,
+        };
         let _alg = parts
             .next()
             .ok_or(anyhow!("Unable to parse password: algorithm not found"))?;
@@ -686,7 +887,7 @@ impl User {
             .ok_or(anyhow!("Unable to parse password: credential not found"))?;
 
         Ok(Password {
-            alg: pbkdf2::PBKDF2_HMAC_SHA256,
+            alg,
             iter: iter.parse()?,
             salt: salt.as_bytes().to_owned(),
             credential: base64::decode(&credential)?,
@@ -91,6 +91,15 @@ pub struct UserAward {
     pub user_id: ID,
 }
 
+/// Award granted to a user by the server, on an event, application or rule
+#[derive(Insertable)]
+#[diesel(table_name = user_award)]
+pub struct GrantUserAwardData {
+    pub created: Date,
+    pub award_id: ID,
+    pub user_id: ID,
+}
+
 #[Object]
 impl UserAward {
     async fn id(&self) -> ID {
@@ -0,0 +1,276 @@
+use async_graphql::{self, Context, Enum, InputObject, Object};
+use byteorder::{NetworkEndian, WriteBytesExt};
+use chrono::Utc;
+use diesel::{
+    backend::Backend,
+    deserialize::{self, FromSql},
+    expression::AsExpression,
+    prelude::*,
+    query_dsl::QueryDsl,
+    serialize::{self, IsNull, Output, ToSql},
+    sql_types::{Bool, Integer},
+};
+use std::error::Error;
+
+use crate::context::GlobalCtx;
+use crate::schema::user_suspension;
+
+use super::*;
+
+/// Available orders for user_suspension query
+#[derive(InputObject, Clone)]
+pub struct UserSuspensionOrder {
+    id: Option<Ordering>,
+    created: Option<Ordering>,
+    expires: Option<Ordering>,
+}
+
+/// Helper object to apply the order to the query
+pub struct UserSuspensionOrders(Vec<UserSuspensionOrder>);
+
+impl Default for UserSuspensionOrders {
+    fn default() -> Self {
+        Self(vec![])
+    }
+}
+
+impl UserSuspensionOrders {
+    pub fn new(orders: Vec<UserSuspensionOrder>) -> Self {
+        Self(orders)
+    }
+
+    pub fn apply_order<'a>(
+        self,
+        query_dsl: user_suspension::BoxedQuery<'a, DB>,
+    ) -> user_suspension::BoxedQuery<'a, DB> {
+        use crate::schema::user_suspension::dsl::*;
+
+        let mut query = query_dsl;
+
+        for obj in self.0 {
+            gen_order!(obj, id, query);
+            gen_order!(obj, created, query);
+            gen_order!(obj, expires, query);
+        }
+
+        query
+    }
+}
+
+/// Available filters for user_suspension query
+#[derive(InputObject, Clone, Default)]
+pub struct UserSuspensionFilter {
+    pub id: Option<I32Filtering>,
+    pub user_id: Option<I32Filtering>,
+    pub actor_id: Option<NullableI32Filtering>,
+    pub scope: Option<SuspensionScopeFiltering>,
+    pub created: Option<TimestamptzFiltering>,
+    pub expires: Option<NullableTimestamptzFiltering>,
+    /// Whether the suspension is still in effect
+    pub active: Option<bool>,
+}
+
+impl CindyFilter<user_suspension::table> for UserSuspensionFilter {
+    fn as_expression(
+        self,
+    ) -> Option<Box<dyn BoxableExpression<user_suspension::table, DB, SqlType = Bool>>> {
+        use crate::schema::user_suspension::dsl::*;
+
+        let mut filter: Option<Box<dyn BoxableExpression<user_suspension, DB, SqlType = Bool>>> =
+            None;
+        let UserSuspensionFilter {
+            id: obj_id,
+            user_id: obj_user_id,
+            actor_id: obj_actor_id,
+            scope: obj_scope,
+            created: obj_created,
+            expires: obj_expires,
+            active: obj_active,
+        } = self;
+        gen_number_filter!(obj_id: I32Filtering, id, filter);
+        gen_number_filter!(obj_user_id: I32Filtering, user_id, filter);
+        gen_nullable_number_filter!(obj_actor_id: NullableI32Filtering, actor_id, filter);
+        gen_enum_filter!(obj_scope: SuspensionScopeFiltering, scope, filter);
+        gen_number_filter!(obj_created: TimestamptzFiltering, created, filter);
+        gen_nullable_number_filter!(obj_expires: NullableTimestamptzFiltering, expires, filter);
+        if let Some(obj_active) = obj_active {
+            let active_exp: Box<dyn BoxableExpression<user_suspension, DB, SqlType = Bool>> =
+                if obj_active {
+                    Box::new(active_expression())
+                } else {
+                    Box::new(diesel::dsl::not(active_expression()))
+                };
+            filter = Some(if let Some(filt_) = filter {
+                Box::new(filt_.and(active_exp))
+            } else {
+                active_exp
+            });
+        }
+        filter
+    }
+}
+
+/// Suspensions without an expiry, or expiring in future
+fn active_expression() -> diesel::dsl::Or<
+    diesel::dsl::IsNull<user_suspension::expires>,
+    diesel::dsl::AssumeNotNull<diesel::dsl::Gt<user_suspension::expires, Timestamptz>>,
+> {
+    use crate::schema::user_suspension::dsl::*;
+
+    expires
+        .is_null()
+        .or(expires.gt(Utc::now()).assume_not_null())
+}
+
+/// What a suspended user is kept from doing
+#[repr(i32)]
+#[derive(Enum, Eq, PartialEq, Clone, Copy, Debug, FromSqlRow, AsExpression)]
+#[diesel(sql_type = Integer)]
+pub enum SuspensionScope {
+    /// Chatmessages and direct messages
+    Chat = 0,
+    /// Questions in puzzles
+    Question = 1,
+    /// Everything a logged in user could do
+    Full = 2,
+}
+
+impl SuspensionScope {
+    /// Whether a suspension of this scope keeps the user from `scope`
+    pub fn covers(&self, scope: SuspensionScope) -> bool {
+        *self == SuspensionScope::Full || *self == scope
+    }
+}
+
+#[derive(InputObject, Eq, PartialEq, Clone)]
+pub struct SuspensionScopeFiltering {
+    pub eq: Option<SuspensionScope>,
+    pub ne: Option<SuspensionScope>,
+    pub eq_any: Option<Vec<SuspensionScope>>,
+    pub ne_all: Option<Vec<SuspensionScope>>,
+}
+
+impl RawFilter<SuspensionScope> for SuspensionScopeFiltering {
+    fn check(&self, item: &SuspensionScope) -> bool {
+        if let Some(eq) = self.eq.as_ref() {
+            item == eq
+        } else if let Some(ne) = self.ne.as_ref() {
+            item != ne
+        } else if let Some(eq_any) = self.eq_any.as_ref() {
+            eq_any.iter().any(|u| u == item)
+        } else if let Some(ne_all) = self.ne_all.as_ref() {
+            ne_all.iter().all(|u| u != item)
+        } else {
+            true
+        }
+    }
+}
+
+impl ToSql<Integer, DB> for SuspensionScope {
+    fn to_sql(&self, out: &mut Output<DB>) -> serialize::Result {
+        out.write_i32::<NetworkEndian>(*self as i32)
+            .map(|_| IsNull::No)
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)
+    }
+}
+
+impl<DB> FromSql<Integer, DB> for SuspensionScope
+where
+    DB: Backend,
+    i32: FromSql<Integer, DB>,
+{
+    fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<Self> {
+        match i32::from_sql(bytes)? {
+            0 => Ok(SuspensionScope::Chat),
+            1 => Ok(SuspensionScope::Question),
+            2 => Ok(SuspensionScope::Full),
+            v => Err(format!("Invalid value `{}` for suspension scope", &v).into()),
+        }
+    }
+}
+
+/// Scopes of suspensions in effect for the user.
+pub fn active_suspension_scopes(
+    conn: &mut PgConnection,
+    user_id: ID,
+) -> QueryResult<Vec<SuspensionScope>> {
+    user_suspension::table
+        .filter(user_suspension::user_id.eq(user_id))
+        .filter(active_expression())
+        .select(user_suspension::scope)
+        .load(conn)
+}
+
+/// Object for user_suspension table
+#[derive(Queryable, Identifiable, Clone, Debug)]
+#[diesel(table_name = user_suspension)]
+pub struct UserSuspension {
+    pub id: ID,
+    pub user_id: ID,
+    pub actor_id: Option<ID>,
+    pub reason: String,
+    pub scope: SuspensionScope,
+    pub created: Timestamptz,
+    pub expires: Option<Timestamptz>,
+}
+
+#[Object]
+impl UserSuspension {
+    async fn id(&self) -> ID {
+        self.id
+    }
+    async fn user_id(&self) -> ID {
+        self.user_id
+    }
+    async fn actor_id(&self) -> Option<ID> {
+        self.actor_id
+    }
+    async fn reason(&self) -> &str {
+        &self.reason
+    }
+    async fn scope(&self) -> SuspensionScope {
+        self.scope
+    }
+    async fn created(&self) -> Timestamptz {
+        self.created
+    }
+    /// Suspensions without an expiry are permanent bans
+    async fn expires(&self) -> Option<Timestamptz> {
+        self.expires
+    }
+    async fn active(&self) -> bool {
+        self.expires
+            .map(|expires| expires > Utc::now())
+            .unwrap_or(true)
+    }
+
+    async fn user(&self, ctx: &Context<'_>) -> async_graphql::Result<User> {
+        use crate::schema::user;
+
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let user_inst = user::table
+            .filter(user::id.eq(self.user_id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(user_inst)
+    }
+
+    async fn actor(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<User>> {
+        use crate::schema::user;
+
+        let actor_id = match self.actor_id {
+            Some(actor_id) => actor_id,
+            None => return Ok(None),
+        };
+        let mut conn = ctx.data::<GlobalCtx>()?.get_conn()?;
+
+        let user_inst = user::table
+            .filter(user::id.eq(actor_id))
+            .limit(1)
+            .first(&mut conn)?;
+
+        Ok(Some(user_inst))
+    }
+}
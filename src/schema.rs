@@ -24,6 +24,18 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    auth_session (id) {
+        id -> Uuid,
+        user_id -> Int4,
+        refresh_token -> Text,
+        created -> Timestamptz,
+        refreshed -> Timestamptz,
+        expires -> Timestamptz,
+        revoked -> Nullable<Timestamptz>,
+    }
+}
+
 diesel::table! {
     award (id) {
         id -> Int4,
@@ -169,6 +181,18 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    email_verification_token (id) {
+        id -> Int4,
+        user_id -> Int4,
+        email -> Varchar,
+        token -> Text,
+        created -> Timestamptz,
+        expires -> Timestamptz,
+        used -> Nullable<Timestamptz>,
+    }
+}
+
 diesel::table! {
     event (id) {
         id -> Int4,
@@ -242,6 +266,19 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    invite_code (id) {
+        id -> Int4,
+        code -> Text,
+        created_by -> Nullable<Int4>,
+        max_uses -> Int4,
+        uses -> Int4,
+        created -> Timestamptz,
+        expires -> Nullable<Timestamptz>,
+        revoked -> Nullable<Timestamptz>,
+    }
+}
+
 diesel::table! {
     license (id) {
         id -> Int4,
@@ -253,6 +290,31 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    password_reset_token (id) {
+        id -> Int4,
+        user_id -> Int4,
+        token -> Text,
+        created -> Timestamptz,
+        expires -> Timestamptz,
+        used -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    personal_access_token (id) {
+        id -> Int4,
+        user_id -> Int4,
+        name -> Text,
+        token -> Text,
+        scopes -> Array<Int4>,
+        created -> Timestamptz,
+        last_used -> Nullable<Timestamptz>,
+        expires -> Nullable<Timestamptz>,
+        revoked -> Nullable<Timestamptz>,
+    }
+}
+
 diesel::table! {
     puzzle (id) {
         id -> Int4,
@@ -273,6 +335,18 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    puzzle_revision (id) {
+        id -> Int4,
+        puzzle_id -> Int4,
+        user_id -> Nullable<Int4>,
+        content -> Text,
+        solution -> Text,
+        memo -> Text,
+        created -> Timestamptz,
+    }
+}
+
 diesel::table! {
     puzzle_tag (id) {
         id -> Int4,
@@ -315,6 +389,7 @@ diesel::table! {
         created -> Timestamptz,
         scheduled -> Timestamptz,
         user_id -> Int4,
+        puzzle_id -> Nullable<Int4>,
     }
 }
 
@@ -378,6 +453,7 @@ diesel::table! {
         hide_bookmark -> Bool,
         icon -> Nullable<Varchar>,
         default_license_id -> Nullable<Int4>,
+        email_verified -> Nullable<Timestamptz>,
     }
 }
 
@@ -390,9 +466,53 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    user_identity (id) {
+        id -> Int4,
+        user_id -> Int4,
+        provider -> Text,
+        subject -> Text,
+        email -> Text,
+        created -> Timestamptz,
+        last_login -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    user_suspension (id) {
+        id -> Int4,
+        user_id -> Int4,
+        actor_id -> Nullable<Int4>,
+        reason -> Text,
+        scope -> Int4,
+        created -> Timestamptz,
+        expires -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    user_totp (user_id) {
+        user_id -> Int4,
+        secret -> Text,
+        created -> Timestamptz,
+        confirmed -> Nullable<Timestamptz>,
+        last_step -> Int8,
+    }
+}
+
+diesel::table! {
+    user_totp_backup_code (id) {
+        id -> Int4,
+        user_id -> Int4,
+        code -> Text,
+        used -> Nullable<Timestamptz>,
+    }
+}
+
 diesel::joinable!(auth_group_permissions -> auth_group (group_id));
 diesel::joinable!(auth_group_permissions -> auth_permission (permission_id));
 diesel::joinable!(auth_permission -> django_content_type (content_type_id));
+diesel::joinable!(auth_session -> user (user_id));
 diesel::joinable!(award_application -> award (award_id));
 diesel::joinable!(bookmark -> puzzle (puzzle_id));
 diesel::joinable!(bookmark -> user (user_id));
@@ -406,6 +526,7 @@ diesel::joinable!(dialogue -> user (user_id));
 diesel::joinable!(django_admin_log -> django_content_type (content_type_id));
 diesel::joinable!(django_admin_log -> user (user_id));
 diesel::joinable!(dm_read -> direct_message (dm_id));
+diesel::joinable!(email_verification_token -> user (user_id));
 diesel::joinable!(event -> user (user_id));
 diesel::joinable!(event_award -> award (award_id));
 diesel::joinable!(event_award -> event (event_id));
@@ -417,14 +538,20 @@ diesel::joinable!(hint -> puzzle (puzzle_id));
 diesel::joinable!(hint -> user (receiver_id));
 diesel::joinable!(image -> puzzle (puzzle_id));
 diesel::joinable!(image -> user (user_id));
+diesel::joinable!(invite_code -> user (created_by));
+diesel::joinable!(password_reset_token -> user (user_id));
+diesel::joinable!(personal_access_token -> user (user_id));
 diesel::joinable!(puzzle -> license (license_id));
 diesel::joinable!(puzzle -> user (user_id));
+diesel::joinable!(puzzle_revision -> puzzle (puzzle_id));
+diesel::joinable!(puzzle_revision -> user (user_id));
 diesel::joinable!(puzzle_tag -> puzzle (puzzle_id));
 diesel::joinable!(puzzle_tag -> tag (tag_id));
 diesel::joinable!(puzzle_tag -> user (user_id));
 diesel::joinable!(replay -> puzzle (puzzle_id));
 diesel::joinable!(replay -> user (user_id));
 diesel::joinable!(replay_dialogue -> replay (replay_id));
+diesel::joinable!(schedule -> puzzle (puzzle_id));
 diesel::joinable!(schedule -> user (user_id));
 diesel::joinable!(star -> puzzle (puzzle_id));
 diesel::joinable!(star -> user (user_id));
@@ -434,11 +561,15 @@ diesel::joinable!(sui_hei_user_groups -> user (user_id));
 diesel::joinable!(sui_hei_user_user_permissions -> auth_permission (permission_id));
 diesel::joinable!(sui_hei_user_user_permissions -> user (user_id));
 diesel::joinable!(user_award -> award (award_id));
+diesel::joinable!(user_identity -> user (user_id));
+diesel::joinable!(user_totp -> user (user_id));
+diesel::joinable!(user_totp_backup_code -> user (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     auth_group,
     auth_group_permissions,
     auth_permission,
+    auth_session,
     award,
     award_application,
     bookmark,
@@ -452,6 +583,7 @@ diesel::allow_tables_to_appear_in_same_query!(
     django_migrations,
     django_session,
     dm_read,
+    email_verification_token,
     event,
     event_award,
     favorite_chatroom,
@@ -460,8 +592,12 @@ diesel::allow_tables_to_appear_in_same_query!(
     hasura_user_ranking_trigger,
     hint,
     image,
+    invite_code,
     license,
+    password_reset_token,
+    personal_access_token,
     puzzle,
+    puzzle_revision,
     puzzle_tag,
     replay,
     replay_dialogue,
@@ -473,4 +609,8 @@ diesel::allow_tables_to_appear_in_same_query!(
     tag,
     user,
     user_award,
+    user_identity,
+    user_suspension,
+    user_totp,
+    user_totp_backup_code,
 );
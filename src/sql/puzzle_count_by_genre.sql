@@ -1,3 +1,5 @@
 SELECT genre, count(id) as puzzle_count FROM puzzle
 WHERE puzzle.user_id = $1
+  AND puzzle.status <> 5
+  AND ($2 OR NOT (puzzle.anonymous AND puzzle.status = 0))
 GROUP BY genre
@@ -1,3 +1,5 @@
 SELECT COUNT(DISTINCT "puzzle"."id") AS count FROM "dialogue"
 INNER JOIN "puzzle" ON "dialogue"."puzzle_id" = "puzzle"."id"
-WHERE "dialogue"."user_id" = $1;
+WHERE "dialogue"."user_id" = $1
+  AND "puzzle"."status" <> 5
+  AND ($2 OR NOT ("puzzle"."anonymous" AND "puzzle"."status" = 0 AND "puzzle"."user_id" = $1));
@@ -1,6 +1,8 @@
 SELECT DISTINCT ON (dialogue.puzzle_id) puzzle.* FROM dialogue
 INNER JOIN puzzle ON dialogue.puzzle_id = puzzle.id
 WHERE dialogue.user_id = $1
+  AND puzzle.status <> 5
+  AND ($4 OR NOT (puzzle.anonymous AND puzzle.status = 0 AND puzzle.user_id = $1))
 ORDER BY dialogue.puzzle_id DESC NULLS LAST
 LIMIT $2
 OFFSET $3;
@@ -3,6 +3,8 @@ FROM (
     SELECT puzzle.id, count(star.id) as star_count FROM puzzle
     INNER JOIN star ON star.puzzle_id = puzzle.id
     WHERE puzzle.user_id = $1
+      AND puzzle.status <> 5
+      AND ($2 OR NOT (puzzle.anonymous AND puzzle.status = 0))
     GROUP BY puzzle.id
 ) as grp
 GROUP BY star_count
@@ -0,0 +1,5 @@
+SELECT puzzle.id FROM puzzle
+LEFT JOIN sui_hei_puzzle_tokenize_cache cache ON cache.puzzle_id = puzzle.id
+WHERE cache.id IS NULL
+ORDER BY puzzle.id
+LIMIT $1
@@ -0,0 +1,3 @@
+INSERT INTO sui_hei_puzzle_tokenize_cache (puzzle_id, tokens)
+VALUES ($1, to_jsonb($2::text[]))
+ON CONFLICT (puzzle_id) DO UPDATE SET tokens = EXCLUDED.tokens
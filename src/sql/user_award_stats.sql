@@ -0,0 +1,94 @@
+WITH authored AS (
+  SELECT
+    puzzle.id,
+    puzzle.user_id,
+    puzzle.yami
+  FROM
+    puzzle
+  WHERE
+    puzzle.status <> 5
+    AND NOT (puzzle.anonymous AND puzzle.status = 0)
+),
+puzzle_stats AS (
+  SELECT
+    authored.user_id,
+    count(*) AS puzzle_count,
+    count(*) FILTER (WHERE authored.yami <> 0) AS yami_puzzle_count
+  FROM
+    authored
+  GROUP BY
+    authored.user_id
+),
+received_star_stats AS (
+  SELECT
+    authored.user_id,
+    sum(star.value) AS received_star_sum,
+    count(*) AS received_star_count
+  FROM
+    star
+    INNER JOIN authored ON authored.id = star.puzzle_id
+  GROUP BY
+    authored.user_id
+),
+received_comment_stats AS (
+  SELECT
+    authored.user_id,
+    count(*) AS received_comment_count
+  FROM
+    comment
+    INNER JOIN authored ON authored.id = comment.puzzle_id
+  GROUP BY
+    authored.user_id
+),
+dialogue_stats AS (
+  SELECT
+    dialogue.user_id,
+    count(*) AS dialogue_count,
+    count(*) FILTER (WHERE dialogue.good) AS good_question_count,
+    count(*) FILTER (WHERE dialogue."true") AS true_answer_count
+  FROM
+    dialogue
+  GROUP BY
+    dialogue.user_id
+),
+star_stats AS (
+  SELECT
+    star.user_id,
+    count(*) AS star_count
+  FROM
+    star
+  GROUP BY
+    star.user_id
+),
+comment_stats AS (
+  SELECT
+    comment.user_id,
+    count(*) AS comment_count
+  FROM
+    comment
+  GROUP BY
+    comment.user_id
+)
+SELECT
+  "user".id,
+  coalesce(puzzle_stats.puzzle_count, 0) AS puzzle_count,
+  coalesce(puzzle_stats.yami_puzzle_count, 0) AS yami_puzzle_count,
+  coalesce(received_star_stats.received_star_sum, 0)::bigint AS received_star_sum,
+  coalesce(received_star_stats.received_star_count, 0) AS received_star_count,
+  coalesce(received_comment_stats.received_comment_count, 0) AS received_comment_count,
+  coalesce(dialogue_stats.dialogue_count, 0) AS dialogue_count,
+  coalesce(dialogue_stats.good_question_count, 0) AS good_question_count,
+  coalesce(dialogue_stats.true_answer_count, 0) AS true_answer_count,
+  coalesce(star_stats.star_count, 0) AS star_count,
+  coalesce(comment_stats.comment_count, 0) AS comment_count
+FROM
+  "user"
+  LEFT JOIN puzzle_stats ON puzzle_stats.user_id = "user".id
+  LEFT JOIN received_star_stats ON received_star_stats.user_id = "user".id
+  LEFT JOIN received_comment_stats ON received_comment_stats.user_id = "user".id
+  LEFT JOIN dialogue_stats ON dialogue_stats.user_id = "user".id
+  LEFT JOIN star_stats ON star_stats.user_id = "user".id
+  LEFT JOIN comment_stats ON comment_stats.user_id = "user".id
+WHERE
+  $1::integer IS NULL
+  OR "user".id = $1;
@@ -1,6 +1,8 @@
 SELECT puzzle.id, count(dialogue.id) as dialogue_count from puzzle
 INNER JOIN dialogue ON dialogue.puzzle_id = puzzle.id
 WHERE puzzle.user_id = $1 AND puzzle.yami <> 0
+  AND puzzle.status <> 5
+  AND ($2 OR NOT (puzzle.anonymous AND puzzle.status = 0))
 GROUP BY puzzle.id
 ORDER BY dialogue_count DESC
 LIMIT 1;
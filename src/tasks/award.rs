@@ -0,0 +1,58 @@
+use anyhow::Result;
+use chrono::Utc;
+use diesel::prelude::*;
+
+use crate::context::GlobalCtx;
+use crate::models::award_rule::{AwardRule, UserStats};
+use crate::models::user_award::GrantUserAwardData;
+use crate::models::{Award, ID};
+use crate::schema::{award, user_award};
+
+/// Rows inserted at once, keeping clear of the bind parameter limit
+const GRANT_CHUNK_SIZE: usize = 1000;
+
+/// Grant awards with a requisition rule to all users meeting it.
+///
+/// Awards whose requisition is not a rule are left alone.
+///
+/// Returns the number of granted user awards.
+pub fn grant_rule_awards(ctx: &GlobalCtx) -> Result<usize> {
+    let mut conn = ctx.get_conn()?;
+
+    let rules: Vec<(ID, AwardRule)> = award::table
+        .load::<Award>(&mut conn)?
+        .into_iter()
+        .filter_map(|award_inst| {
+            AwardRule::parse(&award_inst.requisition).map(|rule| (award_inst.id, rule))
+        })
+        .collect();
+    if rules.is_empty() {
+        return Ok(0);
+    }
+
+    let today = Utc::now().date_naive();
+    let grants: Vec<GrantUserAwardData> = UserStats::load(&mut conn, None)?
+        .iter()
+        .flat_map(|stats| {
+            rules
+                .iter()
+                .filter(|(_, rule)| rule.is_met(stats))
+                .map(|(award_id, _)| GrantUserAwardData {
+                    created: today,
+                    award_id: *award_id,
+                    user_id: stats.id,
+                })
+        })
+        .collect();
+
+    let mut count = 0;
+    for chunk in grants.chunks(GRANT_CHUNK_SIZE) {
+        count += diesel::insert_into(user_award::table)
+            .values(chunk)
+            .on_conflict((user_award::award_id, user_award::user_id))
+            .do_nothing()
+            .execute(&mut conn)?;
+    }
+
+    Ok(count)
+}
@@ -0,0 +1,53 @@
+use anyhow::Result;
+use chrono::Utc;
+use diesel::prelude::*;
+
+use crate::broker::CindyBroker;
+use crate::context::GlobalCtx;
+use crate::gql_schema::assign_referred_images;
+use crate::models::puzzle::{Puzzle, PuzzleSub, Status};
+use crate::models::puzzle_log::PuzzleLogSub;
+use crate::schema::puzzle;
+use crate::SERVER_TZ;
+
+/// Mark undergoing puzzles whose `dazed_on` has passed as dazed.
+///
+/// Side effects are the same as solving a puzzle with `updatePuzzle`:
+/// images referred in the solution are assigned to the puzzle, realtime
+/// channels of the puzzle are closed and the update is published.
+///
+/// Returns the number of dazed puzzles.
+pub fn daze_expired_puzzles(ctx: &GlobalCtx) -> Result<usize> {
+    let mut conn = ctx.get_conn()?;
+    let today = Utc::now().with_timezone(&*SERVER_TZ).date_naive();
+
+    let expired: Vec<Puzzle> = puzzle::table
+        .filter(puzzle::status.eq(Status::Undergoing))
+        .filter(puzzle::dazed_on.lt(today))
+        .get_results(&mut conn)?;
+
+    let mut count = 0;
+    for puzzle_inst in expired {
+        // Skip puzzles that have been updated since they were fetched
+        let updated: Option<Puzzle> = diesel::update(puzzle::table)
+            .filter(puzzle::id.eq(puzzle_inst.id))
+            .filter(puzzle::status.eq(Status::Undergoing))
+            .set(puzzle::status.eq(Status::Dazed))
+            .get_result(&mut conn)
+            .optional()?;
+        let puzzle = match updated {
+            Some(puzzle) => puzzle,
+            None => continue,
+        };
+
+        assign_referred_images(&mut conn, &puzzle.solution, puzzle.id);
+
+        let key_starts_with = format!("puzzleLog<{}", puzzle.id);
+        CindyBroker::<PuzzleLogSub>::cleaup_all(|key| key.starts_with(&key_starts_with));
+
+        CindyBroker::publish(PuzzleSub::Updated(puzzle_inst, puzzle));
+        count += 1;
+    }
+
+    Ok(count)
+}
@@ -0,0 +1,33 @@
+use anyhow::Result;
+use chrono::Utc;
+use diesel::prelude::*;
+
+use crate::context::GlobalCtx;
+use crate::gql_schema::close_event;
+use crate::models::event::EventStatus;
+use crate::schema::event;
+
+/// Close all open events past their end time, granting their awards.
+///
+/// Events ended before the `event_status` migration are closed by it, so that
+/// their awards are not granted again.
+///
+/// Returns the number of closed events.
+pub fn close_ended_events(ctx: &GlobalCtx) -> Result<usize> {
+    let mut conn = ctx.get_conn()?;
+
+    let ended: Vec<i32> = event::table
+        .filter(event::status.eq(EventStatus::Open))
+        .filter(event::end_time.le(Utc::now()))
+        .select(event::id)
+        .load(&mut conn)?;
+
+    let mut count = 0;
+    for event_id in ended {
+        if close_event(&mut conn, event_id)?.is_some() {
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
@@ -0,0 +1,13 @@
+//! Background jobs spawned along with the server.
+
+mod award;
+mod daze;
+mod event;
+mod schedule;
+mod tokenize;
+
+pub use award::grant_rule_awards;
+pub use daze::daze_expired_puzzles;
+pub use event::close_ended_events;
+pub use schedule::publish_scheduled_puzzles;
+pub use tokenize::refresh_tokenize_cache;
@@ -0,0 +1,73 @@
+use anyhow::Result;
+use chrono::Utc;
+use diesel::prelude::*;
+
+use crate::broker::CindyBroker;
+use crate::context::GlobalCtx;
+use crate::gql_schema::DazedTimeCalc;
+use crate::models::puzzle::{Puzzle, PuzzleSub, Status};
+use crate::models::Schedule;
+use crate::schema::{puzzle, schedule};
+use crate::SERVER_TZ;
+
+/// Publish the draft puzzles of all due schedules.
+///
+/// The draft becomes an undergoing puzzle created at the moment of publishing,
+/// with its `dazed_on` calculated from the date of then in the server timezone, which is
+/// what the daze task compares it to. The schedule is removed afterwards.
+///
+/// Returns the number of published puzzles.
+pub fn publish_scheduled_puzzles(ctx: &GlobalCtx) -> Result<usize> {
+    let mut conn = ctx.get_conn()?;
+    let now = Utc::now();
+
+    let due: Vec<Schedule> = schedule::table
+        .filter(schedule::scheduled.le(now))
+        .filter(schedule::puzzle_id.is_not_null())
+        .order(schedule::scheduled.asc())
+        .get_results(&mut conn)?;
+
+    let mut count = 0;
+    for schedule_inst in due {
+        let published = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+            let draft: Option<Puzzle> = puzzle::table
+                .filter(puzzle::id.nullable().eq(schedule_inst.puzzle_id))
+                .filter(puzzle::status.eq(Status::Scheduled))
+                .for_update()
+                .first(conn)
+                .optional()?;
+
+            let published = if let Some(draft) = draft {
+                let dazed_on = now.with_timezone(&*SERVER_TZ).date_naive()
+                    + DazedTimeCalc::default()
+                        .yami(Some(draft.yami))
+                        .genre(Some(draft.genre))
+                        .duration();
+                let puzzle: Puzzle = diesel::update(puzzle::table)
+                    .filter(puzzle::id.eq(draft.id))
+                    .set((
+                        puzzle::status.eq(Status::Undergoing),
+                        puzzle::created.eq(now),
+                        puzzle::modified.eq(now),
+                        puzzle::dazed_on.eq(dazed_on),
+                    ))
+                    .get_result(conn)?;
+                Some(puzzle)
+            } else {
+                None
+            };
+
+            diesel::delete(schedule::table.filter(schedule::id.eq(schedule_inst.id)))
+                .execute(conn)?;
+
+            Ok(published)
+        })?;
+
+        if let Some(puzzle) = published {
+            CindyBroker::publish(PuzzleSub::Created(puzzle));
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
@@ -0,0 +1,44 @@
+use anyhow::Result;
+use diesel::{prelude::*, sql_types::Integer};
+
+use crate::context::GlobalCtx;
+use crate::gql_schema::sync_tokenize_cache;
+use crate::models::Puzzle;
+use crate::schema::puzzle;
+
+const BATCH_SIZE: i32 = 500;
+
+#[derive(QueryableByName)]
+struct StalePuzzle {
+    #[diesel(sql_type = Integer)]
+    id: i32,
+}
+
+/// Build the tokenize cache of puzzles that have none.
+///
+/// Rows left by the legacy server are kept as they are, and only rebuilt when the
+/// puzzle is edited. Returns the number of refreshed puzzles.
+pub fn refresh_tokenize_cache(ctx: &GlobalCtx) -> Result<usize> {
+    let mut conn = ctx.get_conn()?;
+    let mut count = 0;
+
+    loop {
+        let stale: Vec<StalePuzzle> =
+            diesel::sql_query(include_str!("../sql/puzzle_tokenize_cache_stale.sql"))
+                .bind::<Integer, _>(BATCH_SIZE)
+                .get_results(&mut conn)?;
+        if stale.is_empty() {
+            break;
+        }
+
+        let puzzles: Vec<Puzzle> = puzzle::table
+            .filter(puzzle::id.eq_any(stale.iter().map(|p| p.id)))
+            .get_results(&mut conn)?;
+        for puzzle in puzzles.iter() {
+            sync_tokenize_cache(&mut conn, puzzle)?;
+        }
+        count += puzzles.len();
+    }
+
+    Ok(count)
+}